@@ -0,0 +1,343 @@
+use crate::pager::{parse_meta, sidecar, FLAG_FOLLOWER, SIDECARS};
+use crate::{BPlusTree, ChangeLog, Snapshot, PAGE_SIZE};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Error, ErrorKind, Result, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// An incremental backup holds the pages that differ from its base, each as a page number and
+// image, followed by the checksum of every page of the state it brings the base up to. That
+// list is its manifest, and a backup is named by the checksum of its manifest, so a chain can
+// be checked link by link. The header carries a checksum over the rest of the file and one of
+// its own.
+const INCREMENTAL_MAGIC: &[u8; 8] = b"BPINCR01";
+const INCREMENTAL_BASE: usize = 8;
+const INCREMENTAL_SEQUENCE: usize = 12;
+const INCREMENTAL_NUM_PAGES: usize = 20;
+const INCREMENTAL_COUNT: usize = 24;
+const INCREMENTAL_BODY_CRC: usize = 28;
+const INCREMENTAL_HEADER_CRC: usize = 32;
+const INCREMENTAL_HEADER_SIZE: usize = 36;
+
+fn get_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn not_a_backup(path: &Path, problem: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{}: {problem}", path.display()),
+    )
+}
+
+fn fingerprint(manifest: &[u32]) -> u32 {
+    let bytes: Vec<u8> = manifest.iter().flat_map(|c| c.to_le_bytes()).collect();
+    crc32c::crc32c(&bytes)
+}
+
+fn page_checksums(image: &[u8]) -> Vec<u32> {
+    image.chunks_exact(PAGE_SIZE).map(crc32c::crc32c).collect()
+}
+
+struct Incremental<'a> {
+    base: u32,
+    sequence: u64,
+    num_pages: usize,
+    pages: Vec<(usize, &'a [u8])>,
+    manifest: Vec<u32>,
+}
+
+impl<'a> Incremental<'a> {
+    fn parse(path: &Path, bytes: &'a [u8]) -> Result<Self> {
+        let torn = || not_a_backup(path, "incremental backup is damaged");
+        if bytes.len() < INCREMENTAL_HEADER_SIZE
+            || crc32c::crc32c(&bytes[..INCREMENTAL_HEADER_CRC])
+                != get_u32(bytes, INCREMENTAL_HEADER_CRC)
+        {
+            return Err(torn());
+        }
+        let num_pages = get_u32(bytes, INCREMENTAL_NUM_PAGES) as usize;
+        let count = get_u32(bytes, INCREMENTAL_COUNT) as usize;
+        let body = &bytes[INCREMENTAL_HEADER_SIZE..];
+        let records = count * (4 + PAGE_SIZE);
+        if body.len() != records + num_pages * 4
+            || crc32c::crc32c(body) != get_u32(bytes, INCREMENTAL_BODY_CRC)
+        {
+            return Err(torn());
+        }
+        let pages: Vec<(usize, &[u8])> = body[..records]
+            .chunks_exact(4 + PAGE_SIZE)
+            .map(|record| (get_u32(record, 0) as usize, &record[4..]))
+            .collect();
+        if pages.iter().any(|(page_num, _)| *page_num >= num_pages) {
+            return Err(torn());
+        }
+        Ok(Incremental {
+            base: get_u32(bytes, INCREMENTAL_BASE),
+            sequence: u64::from_le_bytes(
+                bytes[INCREMENTAL_SEQUENCE..INCREMENTAL_SEQUENCE + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+            num_pages,
+            pages,
+            manifest: body[records..]
+                .chunks_exact(4)
+                .map(|c| get_u32(c, 0))
+                .collect(),
+        })
+    }
+}
+
+// A full backup is an index file of its own; an incremental one starts with its magic.
+fn is_incremental(bytes: &[u8]) -> bool {
+    bytes.starts_with(INCREMENTAL_MAGIC)
+}
+
+// The sequence number a full backup was taken at.
+fn full_backup_sequence(path: &Path, image: &[u8]) -> Result<u64> {
+    if is_incremental(image) || image.is_empty() || !image.len().is_multiple_of(PAGE_SIZE) {
+        return Err(not_a_backup(path, "not a full backup"));
+    }
+    let meta = parse_meta(|page| (page == 0).then_some(&image[..PAGE_SIZE]))
+        .ok_or_else(|| not_a_backup(path, "not a full backup"))?;
+    Ok(meta.sequence)
+}
+
+// A file that opens as an index must not pick up a WAL or change log left behind by another.
+fn create_index_file(path: &Path) -> Result<File> {
+    let sidecars = SIDECARS.map(|extension| sidecar(path, extension));
+    for existing in std::iter::once(path.to_path_buf()).chain(sidecars) {
+        if existing.exists() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", existing.display()),
+            ));
+        }
+    }
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+impl Snapshot {
+    // Hands every page of the file as the snapshot sees it to `copy` in page order and returns
+    // the sequence number it was taken at. The lock is only held while one page is read, so
+    // writers carry on meanwhile; the pages they change are kept aside for the snapshot.
+    fn backup_pages(&self, mut copy: impl FnMut(usize, &[u8]) -> Result<()>) -> Result<u64> {
+        let read = |page_num: usize, page: &mut [u8]| {
+            let pager = self.pager.read().unwrap();
+            page.copy_from_slice(pager.get_page(page_num, Some(self.id)));
+        };
+        let mut page = vec![0u8; PAGE_SIZE];
+        read(0, &mut page);
+        let meta = parse_meta(|page_num| (page_num == 0).then_some(&page[..]))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "corrupt meta page"))?;
+        copy(0, &page)?;
+        for page_num in 1..meta.num_pages {
+            read(page_num, &mut page);
+            copy(page_num, &page)?;
+        }
+        Ok(meta.sequence)
+    }
+
+    // Writes a consistent copy of the whole file as the snapshot sees it to `path`, which must
+    // not exist yet. The copy opens as an index of its own. Returns the sequence number it was
+    // taken at. Moving the snapshot to another thread keeps the tree writable during the copy.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        let file = create_index_file(path.as_ref())?;
+        let mut out = BufWriter::new(&file);
+        let sequence = self.backup_pages(|_, page| out.write_all(page))?;
+        out.flush()?;
+        drop(out);
+        file.sync_all()?;
+        Ok(sequence)
+    }
+
+    // Writes only the pages that changed since `base`, a full or incremental backup of this
+    // file, to `path`, which must not exist yet. Returns the sequence number it was taken at.
+    pub fn backup_incremental_to<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        base: P,
+        path: Q,
+    ) -> Result<u64> {
+        let base_path = base.as_ref();
+        let bytes = std::fs::read(base_path)?;
+        let base = if is_incremental(&bytes) {
+            Incremental::parse(base_path, &bytes)?.manifest
+        } else {
+            full_backup_sequence(base_path, &bytes)?;
+            page_checksums(&bytes)
+        };
+        drop(bytes);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path.as_ref())?;
+        file.seek(SeekFrom::Start(INCREMENTAL_HEADER_SIZE as u64))?;
+        let mut out = BufWriter::new(&file);
+        let mut body_crc = 0;
+        let mut manifest = Vec::new();
+        let mut count = 0u32;
+        let sequence = self.backup_pages(|page_num, page| {
+            let checksum = crc32c::crc32c(page);
+            if base.get(page_num) != Some(&checksum) {
+                let number = (page_num as u32).to_le_bytes();
+                body_crc = crc32c::crc32c_append(crc32c::crc32c_append(body_crc, &number), page);
+                out.write_all(&number)?;
+                out.write_all(page)?;
+                count += 1;
+            }
+            manifest.push(checksum);
+            Ok(())
+        })?;
+        let manifest_bytes: Vec<u8> = manifest.iter().flat_map(|c| c.to_le_bytes()).collect();
+        body_crc = crc32c::crc32c_append(body_crc, &manifest_bytes);
+        out.write_all(&manifest_bytes)?;
+        out.flush()?;
+        drop(out);
+
+        let mut header = Vec::with_capacity(INCREMENTAL_HEADER_SIZE);
+        header.extend_from_slice(INCREMENTAL_MAGIC);
+        header.extend_from_slice(&fingerprint(&base).to_le_bytes());
+        header.extend_from_slice(&sequence.to_le_bytes());
+        header.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+        header.extend_from_slice(&count.to_le_bytes());
+        header.extend_from_slice(&body_crc.to_le_bytes());
+        header.extend_from_slice(&crc32c::crc32c(&header).to_le_bytes());
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header)?;
+        file.sync_all()?;
+        Ok(sequence)
+    }
+}
+
+impl BPlusTree {
+    // A full backup of the last commit; see `Snapshot::backup_to`.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        self.snapshot().backup_to(path)
+    }
+
+    // An incremental backup of the last commit; see `Snapshot::backup_incremental_to`.
+    pub fn backup_incremental_to<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        base: P,
+        path: Q,
+    ) -> Result<u64> {
+        self.snapshot().backup_incremental_to(base, path)
+    }
+
+    // Rebuilds an index at `output`, which must not exist yet, from a full backup followed by
+    // the incremental backups taken after it, oldest first. Returns the sequence number of the
+    // restored state.
+    pub fn restore<P: AsRef<Path>, Q: AsRef<Path>>(backups: &[P], output: Q) -> Result<u64> {
+        Self::restore_backups(backups, None, output.as_ref())
+    }
+
+    // Like `restore`, but stops at `sequence`: incremental backups taken after it are left
+    // out, and the rest of the way is replayed from the change log of the index at `primary`.
+    // `sequence` has to end a commit; one inside a transaction is refused. If the replay fails
+    // or is refused, `output` is left holding the restored backups.
+    pub fn restore_until<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
+        backups: &[P],
+        primary: Q,
+        sequence: u64,
+        output: R,
+    ) -> Result<u64> {
+        let output = output.as_ref();
+        let restored = Self::restore_backups(backups, Some(sequence), output)?;
+        if restored == sequence {
+            return Ok(sequence);
+        }
+
+        let mut tree = Self::open_with_flags(output, FLAG_FOLLOWER)?;
+        // A backup taken before the primary enabled its change log restores to a file that
+        // does not number its changes, and the replay would leave its sequence behind. Following
+        // is turned on in memory only, so the file is not left a follower.
+        tree.pager.write().unwrap().changes.following = true;
+        let mut log = ChangeLog::open(primary)?;
+        log.seek(restored + 1).map_err(|e| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("backups reach {restored} but the primary's {e}"),
+            )
+        })?;
+        let mut events = log.poll()?;
+        let reached = events.last().map_or(restored, |event| event.sequence);
+        if reached < sequence {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("the primary's change log ends at {reached}"),
+            ));
+        }
+        // Only the state at the end of a commit existed; one partway through a transaction
+        // never did.
+        let target = (sequence - restored - 1) as usize;
+        if !events[target].last_in_commit {
+            let before = events[..target]
+                .iter()
+                .rev()
+                .find(|event| event.last_in_commit)
+                .map_or(restored, |event| event.sequence);
+            let after = events[target..]
+                .iter()
+                .find(|event| event.last_in_commit)
+                .map_or(reached, |event| event.sequence);
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{sequence} is inside a commit; restore to {before} or {after}"),
+            ));
+        }
+        events.truncate(target + 1);
+        tree.apply_changes(&events)?;
+        Ok(sequence)
+    }
+
+    fn restore_backups<P: AsRef<Path>>(
+        backups: &[P],
+        until: Option<u64>,
+        output: &Path,
+    ) -> Result<u64> {
+        let Some((full, incrementals)) = backups.split_first() else {
+            return Err(Error::new(ErrorKind::InvalidInput, "no backups to restore"));
+        };
+        let full = full.as_ref();
+        let mut image = std::fs::read(full)?;
+        let mut sequence = full_backup_sequence(full, &image)?;
+        if until.is_some_and(|until| sequence > until) {
+            return Err(not_a_backup(
+                full,
+                &format!("taken at {sequence}, after the sequence to restore to"),
+            ));
+        }
+
+        for path in incrementals {
+            let path = path.as_ref();
+            let bytes = std::fs::read(path)?;
+            let incremental = Incremental::parse(path, &bytes)?;
+            if until.is_some_and(|until| incremental.sequence > until) {
+                break;
+            }
+            if incremental.base != fingerprint(&page_checksums(&image)) {
+                return Err(not_a_backup(
+                    path,
+                    "not taken against the backups before it",
+                ));
+            }
+            image.resize(incremental.num_pages * PAGE_SIZE, 0);
+            for (page_num, page) in &incremental.pages {
+                image[page_num * PAGE_SIZE..(page_num + 1) * PAGE_SIZE].copy_from_slice(page);
+            }
+            if page_checksums(&image) != incremental.manifest {
+                return Err(not_a_backup(
+                    path,
+                    "restored pages do not match its manifest",
+                ));
+            }
+            sequence = incremental.sequence;
+        }
+
+        let mut file = create_index_file(output)?;
+        file.write_all(&image)?;
+        file.sync_all()?;
+        Ok(sequence)
+    }
+}
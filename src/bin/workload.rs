@@ -0,0 +1,441 @@
+// YCSB-style workload runner. Loads `records` keys, runs `operations` operations drawn from the
+// configured mix and key distribution, and prints the results as one JSON object on stdout.
+//
+//   cargo run --release --bin workload -- --workload=a --records=1000000 --operations=200000
+use bplus_tree_index::BPlusTree;
+use std::fmt::Write as _;
+use std::process::exit;
+use std::time::Instant;
+
+const DATA_SIZE: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Distribution {
+    Uniform,
+    Zipfian,
+    Latest,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Read,
+    Update,
+    Insert,
+    Scan,
+    Delete,
+}
+
+const OPS: [(Op, &str); 5] = [
+    (Op::Read, "read"),
+    (Op::Update, "update"),
+    (Op::Insert, "insert"),
+    (Op::Scan, "scan"),
+    (Op::Delete, "delete"),
+];
+
+struct Config {
+    workload: String,
+    path: String,
+    records: u64,
+    operations: u64,
+    distribution: Distribution,
+    // Proportions in the order of OPS.
+    mix: [f64; 5],
+    value_size: usize,
+    scan_length: usize,
+    hashed: bool,
+    theta: f64,
+    batch: u64,
+    seed: u64,
+}
+
+impl Config {
+    // The core YCSB workloads. F's read-modify-write is run as an update after the read.
+    fn preset(name: &str) -> Option<Self> {
+        let (mix, distribution) = match name {
+            "a" => ([0.5, 0.5, 0.0, 0.0, 0.0], Distribution::Zipfian),
+            "b" => ([0.95, 0.05, 0.0, 0.0, 0.0], Distribution::Zipfian),
+            "c" => ([1.0, 0.0, 0.0, 0.0, 0.0], Distribution::Zipfian),
+            "d" => ([0.95, 0.0, 0.05, 0.0, 0.0], Distribution::Latest),
+            "e" => ([0.0, 0.0, 0.05, 0.95, 0.0], Distribution::Zipfian),
+            "f" => ([0.5, 0.5, 0.0, 0.0, 0.0], Distribution::Zipfian),
+            _ => return None,
+        };
+        Some(Config {
+            workload: name.to_string(),
+            path: "bptree_workload.dat".to_string(),
+            records: 100_000,
+            operations: 100_000,
+            distribution,
+            mix,
+            value_size: DATA_SIZE,
+            scan_length: 100,
+            hashed: true,
+            theta: 0.99,
+            batch: 10_000,
+            seed: 1,
+        })
+    }
+
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = Vec::new();
+        for arg in args {
+            let (name, value) = arg
+                .strip_prefix("--")
+                .and_then(|arg| arg.split_once('='))
+                .ok_or_else(|| format!("expected --name=value, got {arg}"))?;
+            options.push((name, value));
+        }
+        let workload = options
+            .iter()
+            .find(|(name, _)| *name == "workload")
+            .map_or("a", |(_, value)| value);
+        let mut config =
+            Config::preset(workload).ok_or_else(|| format!("unknown workload {workload}"))?;
+
+        let number = |value: &str| {
+            value
+                .replace('_', "")
+                .parse::<u64>()
+                .map_err(|_| format!("not a number: {value}"))
+        };
+        let ratio = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|r| *r >= 0.0)
+                .ok_or_else(|| format!("not a proportion: {value}"))
+        };
+        for (name, value) in options {
+            match name {
+                "workload" => {}
+                "path" => config.path = value.to_string(),
+                "records" => config.records = number(value)?,
+                "operations" => config.operations = number(value)?,
+                "distribution" => {
+                    config.distribution = match value {
+                        "uniform" => Distribution::Uniform,
+                        "zipfian" => Distribution::Zipfian,
+                        "latest" => Distribution::Latest,
+                        _ => return Err(format!("unknown distribution {value}")),
+                    }
+                }
+                "read" => config.mix[0] = ratio(value)?,
+                "update" => config.mix[1] = ratio(value)?,
+                "insert" => config.mix[2] = ratio(value)?,
+                "scan" => config.mix[3] = ratio(value)?,
+                "delete" => config.mix[4] = ratio(value)?,
+                "value-size" => config.value_size = number(value)? as usize,
+                "scan-length" => config.scan_length = number(value)?.max(1) as usize,
+                "order" => {
+                    config.hashed = match value {
+                        "hashed" => true,
+                        "ordered" => false,
+                        _ => return Err(format!("unknown key order {value}")),
+                    }
+                }
+                "theta" => config.theta = ratio(value)?,
+                "batch" => config.batch = number(value)?.max(1),
+                "seed" => config.seed = number(value)?,
+                _ => return Err(format!("unknown option --{name}")),
+            }
+        }
+
+        if config.value_size > DATA_SIZE {
+            return Err(format!("values are at most {DATA_SIZE} bytes"));
+        }
+        if config.records + config.operations > u32::MAX as u64 {
+            return Err("records and inserts must fit in the 32-bit key space".to_string());
+        }
+        if config.mix.iter().sum::<f64>() <= 0.0 {
+            return Err("the operation mix is empty".to_string());
+        }
+        if !(0.0..1.0).contains(&config.theta) {
+            return Err("theta must be in [0, 1)".to_string());
+        }
+        Ok(config)
+    }
+}
+
+// SplitMix64, so runs with the same seed pick the same keys.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+}
+
+// Gray et al.'s generator as used by YCSB, returning ranks with 0 the most popular. The zeta sum
+// is extended as items are inserted rather than recomputed.
+struct Zipfian {
+    theta: f64,
+    items: u64,
+    zeta_n: f64,
+    zeta_2: f64,
+}
+
+impl Zipfian {
+    fn new(theta: f64) -> Self {
+        Zipfian {
+            theta,
+            items: 0,
+            zeta_n: 0.0,
+            zeta_2: 1.0 + 0.5f64.powf(theta),
+        }
+    }
+
+    fn next(&mut self, rng: &mut Rng, items: u64) -> u64 {
+        while self.items < items {
+            self.items += 1;
+            self.zeta_n += 1.0 / (self.items as f64).powf(self.theta);
+        }
+        let n = items as f64;
+        let alpha = 1.0 / (1.0 - self.theta);
+        let eta = (1.0 - (2.0 / n).powf(1.0 - self.theta)) / (1.0 - self.zeta_2 / self.zeta_n);
+        let u = rng.unit();
+        let uz = u * self.zeta_n;
+        if uz < 1.0 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(self.theta) {
+            1.min(items - 1)
+        } else {
+            ((n * (eta * u - eta + 1.0).powf(alpha)) as u64).min(items - 1)
+        }
+    }
+}
+
+// Maps the n-th inserted record to its key. The hashed order is a bijection on u32, so keys
+// never collide but land all over the tree like YCSB's hashed insert order.
+fn key_for(index: u64, hashed: bool) -> i32 {
+    let mut x = index as u32;
+    if hashed {
+        x = x.wrapping_mul(0x9e37_79b1);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x85eb_ca77);
+        x ^= x >> 13;
+    }
+    x as i32
+}
+
+fn value(index: u64, size: usize) -> [u8; DATA_SIZE] {
+    let mut data = [0u8; DATA_SIZE];
+    for (i, byte) in data[..size].iter_mut().enumerate() {
+        *byte = (index as usize).wrapping_add(i) as u8;
+    }
+    data
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((sorted.len() as f64 * p).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+fn latency_json(latencies: &mut [u64]) -> String {
+    latencies.sort_unstable();
+    let mean = latencies.iter().sum::<u64>() as f64 / latencies.len().max(1) as f64;
+    format!(
+        "{{\"count\": {}, \"mean_ns\": {:.0}, \"p50_ns\": {}, \"p99_ns\": {}, \"p999_ns\": {}, \"max_ns\": {}}}",
+        latencies.len(),
+        mean,
+        percentile(latencies, 0.5),
+        percentile(latencies, 0.99),
+        percentile(latencies, 0.999),
+        latencies.last().copied().unwrap_or(0)
+    )
+}
+
+fn run(config: &Config) -> std::io::Result<String> {
+    BPlusTree::remove_files(&config.path)?;
+    let mut tree = BPlusTree::open(&config.path)?;
+    let mut rng = Rng(config.seed);
+
+    eprintln!("loading {} records into {}", config.records, config.path);
+    let start = Instant::now();
+    let mut loaded = 0;
+    while loaded < config.records {
+        let end = (loaded + config.batch).min(config.records);
+        let mut tx = tree.begin()?;
+        for index in loaded..end {
+            tx.put(
+                key_for(index, config.hashed),
+                &value(index, config.value_size),
+            )?;
+        }
+        tx.commit()?;
+        loaded = end;
+    }
+    let load_seconds = start.elapsed().as_secs_f64();
+
+    eprintln!("running {} operations", config.operations);
+    let total: f64 = config.mix.iter().sum();
+    let mut zipfian = Zipfian::new(config.theta);
+    let mut inserted = config.records;
+    let mut latencies: [Vec<u64>; 5] = Default::default();
+    let mut scanned = 0;
+    let (reads_before, writes_before) = (tree.file_reads(), tree.page_writes());
+    let start = Instant::now();
+    for _ in 0..config.operations {
+        let mut pick = rng.unit() * total;
+        let slot = config
+            .mix
+            .iter()
+            .position(|&share| {
+                pick -= share;
+                pick < 0.0
+            })
+            .unwrap_or(4);
+        let index = match (config.distribution, inserted) {
+            (_, 0) => 0,
+            (Distribution::Uniform, n) => rng.below(n),
+            (Distribution::Zipfian, n) => zipfian.next(&mut rng, n),
+            (Distribution::Latest, n) => n - 1 - zipfian.next(&mut rng, n),
+        };
+        let key = key_for(index, config.hashed);
+
+        let op_start = Instant::now();
+        match OPS[slot].0 {
+            Op::Read => {
+                tree.read(key)?;
+            }
+            Op::Update => {
+                if config.workload == "f" {
+                    tree.read(key)?;
+                }
+                tree.write_data(key, &value(index ^ 1, config.value_size))?;
+            }
+            Op::Insert => {
+                tree.write_data(
+                    key_for(inserted, config.hashed),
+                    &value(inserted, config.value_size),
+                )?;
+                inserted += 1;
+            }
+            Op::Scan => {
+                let len = 1 + rng.below(config.scan_length as u64) as usize;
+                let from = tree.rank(key)?;
+                if let Some((end, _)) = tree.nth((from + len - 1).min(tree.len()?.max(1) - 1))? {
+                    scanned += tree.read_range_data(key, end)?.len();
+                }
+            }
+            Op::Delete => {
+                tree.delete(key)?;
+            }
+        }
+        latencies[slot].push(op_start.elapsed().as_nanos() as u64);
+    }
+    let run_seconds = start.elapsed().as_secs_f64();
+    let file_reads = tree.file_reads() - reads_before;
+    let page_writes = tree.page_writes() - writes_before;
+    let records_at_end = tree.len()?;
+    drop(tree);
+    let file_size = std::fs::metadata(&config.path)?.len();
+
+    let mut all: Vec<u64> = latencies.iter().flatten().copied().collect();
+    let operations = config.operations.max(1) as f64;
+    let distribution = match config.distribution {
+        Distribution::Uniform => "uniform",
+        Distribution::Zipfian => "zipfian",
+        Distribution::Latest => "latest",
+    };
+    let mut mix = String::new();
+    let mut per_op = String::new();
+    for (i, (_, name)) in OPS.iter().enumerate() {
+        let sep = if i == 0 { "" } else { ", " };
+        write!(mix, "{sep}\"{name}\": {}", config.mix[i] / total).unwrap();
+        if !latencies[i].is_empty() {
+            let sep = if per_op.is_empty() { "" } else { ", " };
+            write!(
+                per_op,
+                "{sep}\"{name}\": {}",
+                latency_json(&mut latencies[i])
+            )
+            .unwrap();
+        }
+    }
+
+    let mut json = String::from("{\n");
+    writeln!(json, "  \"workload\": \"{}\",", config.workload).unwrap();
+    writeln!(
+        json,
+        "  \"config\": {{\"records\": {}, \"operations\": {}, \"distribution\": \"{}\", \"theta\": {}, \"value_size\": {}, \"scan_length\": {}, \"order\": \"{}\", \"seed\": {}, \"mix\": {{{}}}}},",
+        config.records,
+        config.operations,
+        distribution,
+        config.theta,
+        config.value_size,
+        config.scan_length,
+        if config.hashed { "hashed" } else { "ordered" },
+        config.seed,
+        mix
+    )
+    .unwrap();
+    writeln!(
+        json,
+        "  \"load\": {{\"seconds\": {:.3}, \"throughput_ops\": {:.1}}},",
+        load_seconds,
+        config.records as f64 / load_seconds.max(f64::EPSILON)
+    )
+    .unwrap();
+    writeln!(
+        json,
+        "  \"run\": {{\"seconds\": {:.3}, \"throughput_ops\": {:.1}, \"latency\": {}, \"operations\": {{{}}}, \"scanned_records\": {}}},",
+        run_seconds,
+        config.operations as f64 / run_seconds.max(f64::EPSILON),
+        latency_json(&mut all),
+        per_op,
+        scanned
+    )
+    .unwrap();
+    writeln!(
+        json,
+        "  \"io\": {{\"file_reads_per_op\": {:.3}, \"page_writes_per_op\": {:.3}}},",
+        file_reads as f64 / operations,
+        page_writes as f64 / operations
+    )
+    .unwrap();
+    writeln!(json, "  \"records_at_end\": {records_at_end},").unwrap();
+    writeln!(json, "  \"file_size_bytes\": {file_size}").unwrap();
+    json.push('}');
+    Ok(json)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("workload: {e}");
+            eprintln!(
+                "usage: workload [--workload=a|b|c|d|e|f] [--records=N] [--operations=N] \
+                 [--distribution=uniform|zipfian|latest] [--read=R] [--update=R] [--insert=R] \
+                 [--scan=R] [--delete=R] [--value-size=N] [--scan-length=N] \
+                 [--order=hashed|ordered] [--theta=T] [--batch=N] [--seed=N] [--path=FILE]"
+            );
+            exit(2);
+        }
+    };
+    match run(&config) {
+        Ok(json) => println!("{json}"),
+        Err(e) => {
+            eprintln!("workload failed: {e}");
+            exit(1);
+        }
+    }
+    let _ = BPlusTree::remove_files(&config.path);
+}
@@ -0,0 +1,241 @@
+use crate::checksum::seal;
+use crate::index::SecondaryIndex;
+use crate::verify::corrupt;
+use crate::{BPlusTree, LeafNode, TreeReader, PAGE_SIZE};
+use std::io::{Error, ErrorKind, Result};
+use std::ops::{Deref, DerefMut};
+
+pub(crate) const CATALOG_PAGE_TYPE: u8 = 3;
+const CATALOG_COUNT: usize = 2;
+pub(crate) const CATALOG_CHECKSUM: usize = 4;
+const CATALOG_HEADER_SIZE: usize = 8;
+const ENTRY_SIZE: usize = 32;
+const ENTRY_ROOT: usize = 0;
+const ENTRY_KIND: usize = 4;
+const ENTRY_NAME_LEN: usize = 5;
+const ENTRY_NAME: usize = 6;
+const MAX_NAME_LEN: usize = ENTRY_SIZE - ENTRY_NAME;
+const CATALOG_CAPACITY: usize = (PAGE_SIZE - CATALOG_HEADER_SIZE) / ENTRY_SIZE;
+
+pub(crate) const KIND_INDEX: u8 = 1;
+pub(crate) const KIND_TREE: u8 = 2;
+
+// One named tree stored in the file besides the main one.
+#[derive(Clone)]
+pub(crate) struct CatalogEntry {
+    pub(crate) name: String,
+    pub(crate) kind: u8,
+    pub(crate) root: i32,
+}
+
+// A name that does not fit its entry, or a kind no version writes, makes the page malformed
+// rather than panicking the reader.
+fn decode(page: &[u8]) -> Option<Vec<CatalogEntry>> {
+    let count = u16::from_le_bytes([page[CATALOG_COUNT], page[CATALOG_COUNT + 1]]) as usize;
+    (0..count)
+        .map(|i| {
+            let entry = &page[CATALOG_HEADER_SIZE + i * ENTRY_SIZE..][..ENTRY_SIZE];
+            let name_len = entry[ENTRY_NAME_LEN] as usize;
+            let kind = entry[ENTRY_KIND];
+            if name_len == 0 || name_len > MAX_NAME_LEN || !matches!(kind, KIND_INDEX | KIND_TREE) {
+                return None;
+            }
+            Some(CatalogEntry {
+                name: String::from_utf8_lossy(&entry[ENTRY_NAME..ENTRY_NAME + name_len])
+                    .into_owned(),
+                kind,
+                root: i32::from_le_bytes(entry[ENTRY_ROOT..ENTRY_ROOT + 4].try_into().unwrap()),
+            })
+        })
+        .collect()
+}
+
+// Decodes a catalog page read straight from a possibly damaged file.
+pub(crate) fn salvage_catalog(page: &[u8]) -> Option<Vec<CatalogEntry>> {
+    let count = u16::from_le_bytes([page[CATALOG_COUNT], page[CATALOG_COUNT + 1]]) as usize;
+    if page[0] != CATALOG_PAGE_TYPE || count > CATALOG_CAPACITY {
+        return None;
+    }
+    decode(page)
+}
+
+fn encode(entries: &[CatalogEntry]) -> [u8; PAGE_SIZE] {
+    let mut page = [0u8; PAGE_SIZE];
+    page[0] = CATALOG_PAGE_TYPE;
+    page[CATALOG_COUNT..CATALOG_COUNT + 2].copy_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (i, e) in entries.iter().enumerate() {
+        let entry = &mut page[CATALOG_HEADER_SIZE + i * ENTRY_SIZE..][..ENTRY_SIZE];
+        entry[ENTRY_ROOT..ENTRY_ROOT + 4].copy_from_slice(&e.root.to_le_bytes());
+        entry[ENTRY_KIND] = e.kind;
+        entry[ENTRY_NAME_LEN] = e.name.len() as u8;
+        entry[ENTRY_NAME..ENTRY_NAME + e.name.len()].copy_from_slice(e.name.as_bytes());
+    }
+    seal(&mut page, CATALOG_CHECKSUM);
+    page
+}
+
+impl TreeReader<'_> {
+    pub(crate) fn read_catalog(&self) -> Result<Vec<CatalogEntry>> {
+        let pager = self.pager.read().unwrap();
+        let page_num = pager.catalog as usize;
+        let page: [u8; PAGE_SIZE] = pager.get_page(page_num, self.snapshot).try_into().unwrap();
+        drop(pager);
+        self.verify_page(page_num, &page, CATALOG_CHECKSUM)?;
+        salvage_catalog(&page).ok_or_else(|| corrupt(page_num, "malformed catalog"))
+    }
+
+    pub(crate) fn catalog_entry(&self, name: &str, kind: u8) -> Result<Option<CatalogEntry>> {
+        Ok(self
+            .read_catalog()?
+            .into_iter()
+            .find(|e| e.name == name && e.kind == kind))
+    }
+}
+
+impl BPlusTree {
+    pub(crate) fn create_catalog(&mut self) -> Result<()> {
+        let page = self.allocate_page()?;
+        let mut pager = self.pager.write().unwrap();
+        pager.catalog = page as i32;
+        pager.write_page(page, &encode(&[]))
+    }
+
+    fn write_catalog(&mut self, entries: &[CatalogEntry]) -> Result<()> {
+        let mut pager = self.pager.write().unwrap();
+        let page = pager.catalog as usize;
+        pager.write_page(page, &encode(entries))
+    }
+
+    pub(crate) fn add_catalog_entry(&mut self, entry: CatalogEntry) -> Result<()> {
+        if entry.name.is_empty() || entry.name.len() > MAX_NAME_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("name must be 1 to {MAX_NAME_LEN} bytes"),
+            ));
+        }
+        let mut entries = self.reader().read_catalog()?;
+        if entries
+            .iter()
+            .any(|e| e.name == entry.name && e.kind == entry.kind)
+        {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", entry.name),
+            ));
+        }
+        if entries.len() == CATALOG_CAPACITY {
+            return Err(Error::other("catalog is full"));
+        }
+        entries.push(entry);
+        self.write_catalog(&entries)
+    }
+
+    pub(crate) fn remove_catalog_entry(&mut self, name: &str, kind: u8) -> Result<()> {
+        let mut entries = self.reader().read_catalog()?;
+        entries.retain(|e| !(e.name == name && e.kind == kind));
+        self.write_catalog(&entries)
+    }
+
+    pub(crate) fn set_catalog_root(&mut self, name: &str, kind: u8, root: i32) -> Result<()> {
+        let mut entries = self.reader().read_catalog()?;
+        let Some(entry) = entries
+            .iter_mut()
+            .find(|e| e.name == name && e.kind == kind && e.root != root)
+        else {
+            return Ok(());
+        };
+        entry.root = root;
+        self.write_catalog(&entries)
+    }
+
+    pub fn create_tree(&mut self, name: &str) -> Result<()> {
+        self.autocommit(|tree| {
+            let page = tree.allocate_page()?;
+            tree.write_leaf_node(page, &LeafNode::new())?;
+            tree.add_catalog_entry(CatalogEntry {
+                name: name.to_string(),
+                kind: KIND_TREE,
+                root: page as i32,
+            })
+        })
+    }
+
+    // Switches this handle to the named tree until the returned guard is dropped. Everything
+    // done through the guard commits against the named tree's root in the catalog.
+    pub fn open_tree(&mut self, name: &str) -> Result<NamedTree<'_>> {
+        let entry = self
+            .reader()
+            .catalog_entry(name, KIND_TREE)?
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("no tree named {name}")))?;
+        let saved = TreeState {
+            root_page: std::mem::replace(&mut self.root_page, entry.root),
+            multimap: std::mem::replace(&mut self.multimap, false),
+            indexes: std::mem::take(&mut self.indexes),
+        };
+        self.open_trees.push(entry.name);
+        Ok(NamedTree { db: self, saved })
+    }
+
+    // Frees every page of the named tree. Returns false if there is no such tree. A tree open
+    // through any guard on this handle, nested or not, cannot be dropped.
+    pub fn drop_tree(&mut self, name: &str) -> Result<bool> {
+        if self.open_trees.iter().any(|open| open == name) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("tree {name} is open"),
+            ));
+        }
+        let Some(entry) = self.reader().catalog_entry(name, KIND_TREE)? else {
+            return Ok(false);
+        };
+        self.autocommit(|tree| {
+            tree.drop_subtree(entry.root as usize)?;
+            tree.remove_catalog_entry(name, KIND_TREE)
+        })?;
+        Ok(true)
+    }
+
+    pub fn list_trees(&self) -> Result<Vec<String>> {
+        Ok(self
+            .reader()
+            .read_catalog()?
+            .into_iter()
+            .filter(|e| e.kind == KIND_TREE)
+            .map(|e| e.name)
+            .collect())
+    }
+}
+
+struct TreeState {
+    root_page: i32,
+    multimap: bool,
+    indexes: Vec<SecondaryIndex>,
+}
+
+pub struct NamedTree<'a> {
+    db: &'a mut BPlusTree,
+    saved: TreeState,
+}
+
+impl Deref for NamedTree<'_> {
+    type Target = BPlusTree;
+
+    fn deref(&self) -> &BPlusTree {
+        self.db
+    }
+}
+
+impl DerefMut for NamedTree<'_> {
+    fn deref_mut(&mut self) -> &mut BPlusTree {
+        self.db
+    }
+}
+
+impl Drop for NamedTree<'_> {
+    fn drop(&mut self) {
+        self.db.root_page = self.saved.root_page;
+        self.db.multimap = self.saved.multimap;
+        self.db.indexes = std::mem::take(&mut self.saved.indexes);
+        self.db.open_trees.pop();
+    }
+}
@@ -0,0 +1,386 @@
+use crate::pager::{parse_meta, FLAG_CHANGE_LOG};
+use crate::{BPlusTree, DATA_SIZE, PAGE_SIZE};
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+// The change log is a header followed by fixed-size records, so the record for a sequence
+// number sits at a known offset. Version 2 marks the last record of each commit.
+const LOG_MAGIC: &[u8; 8] = b"BPFEED02";
+const LOG_FIRST: usize = 8;
+const LOG_HEADER_SIZE: usize = 16;
+
+const RECORD_SEQUENCE: usize = 0;
+const RECORD_KIND: usize = 8;
+const RECORD_HAS_OLD: usize = 9;
+const RECORD_LAST_IN_COMMIT: usize = 10;
+const RECORD_KEY: usize = 12;
+const RECORD_OLD: usize = 16;
+const RECORD_NEW: usize = RECORD_OLD + DATA_SIZE;
+const RECORD_CHECKSUM: usize = RECORD_NEW + DATA_SIZE;
+const RECORD_SIZE: usize = RECORD_CHECKSUM + 4;
+
+const KIND_PUT: u8 = 0;
+const KIND_DELETE: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Put {
+        key: i32,
+        old: Option<[u8; DATA_SIZE]>,
+        new: [u8; DATA_SIZE],
+    },
+    Delete {
+        key: i32,
+        old: [u8; DATA_SIZE],
+    },
+}
+
+impl Change {
+    pub fn key(&self) -> i32 {
+        match self {
+            Change::Put { key, .. } | Change::Delete { key, .. } => *key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+    pub sequence: u64,
+    pub change: Change,
+    // Set on the last change of its commit. The state after an event without it never existed
+    // on its own.
+    pub last_in_commit: bool,
+}
+
+impl ChangeEvent {
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut record = [0u8; RECORD_SIZE];
+        record[RECORD_SEQUENCE..RECORD_SEQUENCE + 8].copy_from_slice(&self.sequence.to_le_bytes());
+        let (kind, key, old, new) = match &self.change {
+            Change::Put { key, old, new } => (KIND_PUT, *key, *old, Some(new)),
+            Change::Delete { key, old } => (KIND_DELETE, *key, Some(*old), None),
+        };
+        record[RECORD_KIND] = kind;
+        record[RECORD_LAST_IN_COMMIT] = self.last_in_commit as u8;
+        record[RECORD_KEY..RECORD_KEY + 4].copy_from_slice(&key.to_le_bytes());
+        if let Some(old) = old {
+            record[RECORD_HAS_OLD] = 1;
+            record[RECORD_OLD..RECORD_NEW].copy_from_slice(&old);
+        }
+        if let Some(new) = new {
+            record[RECORD_NEW..RECORD_CHECKSUM].copy_from_slice(new);
+        }
+        let checksum = crc32c::crc32c(&record[..RECORD_CHECKSUM]);
+        record[RECORD_CHECKSUM..].copy_from_slice(&checksum.to_le_bytes());
+        record
+    }
+
+    fn decode(record: &[u8]) -> Option<Self> {
+        let checksum = u32::from_le_bytes(record[RECORD_CHECKSUM..].try_into().unwrap());
+        if checksum != crc32c::crc32c(&record[..RECORD_CHECKSUM]) {
+            return None;
+        }
+        let sequence = u64::from_le_bytes(record[..8].try_into().unwrap());
+        let key = i32::from_le_bytes(record[RECORD_KEY..RECORD_KEY + 4].try_into().unwrap());
+        let old: [u8; DATA_SIZE] = record[RECORD_OLD..RECORD_NEW].try_into().unwrap();
+        let new: [u8; DATA_SIZE] = record[RECORD_NEW..RECORD_CHECKSUM].try_into().unwrap();
+        let change = match (record[RECORD_KIND], record[RECORD_HAS_OLD]) {
+            (KIND_PUT, has_old) => Change::Put {
+                key,
+                old: (has_old == 1).then_some(old),
+                new,
+            },
+            (KIND_DELETE, 1) => Change::Delete { key, old },
+            _ => return None,
+        };
+        let last_in_commit = match record[RECORD_LAST_IN_COMMIT] {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        Some(ChangeEvent {
+            sequence,
+            change,
+            last_in_commit,
+        })
+    }
+}
+
+fn log_path(path: &Path) -> PathBuf {
+    let mut log = path.as_os_str().to_owned();
+    log.push(".changes");
+    PathBuf::from(log)
+}
+
+fn broken_log(problem: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("change log: {problem}"))
+}
+
+fn read_header(log: &File) -> Result<u64> {
+    let mut header = [0u8; LOG_HEADER_SIZE];
+    log.read_exact_at(&mut header, 0)
+        .map_err(|_| broken_log("missing header"))?;
+    if &header[..LOG_MAGIC.len()] != LOG_MAGIC {
+        return Err(broken_log("bad magic"));
+    }
+    Ok(u64::from_le_bytes(
+        header[LOG_FIRST..LOG_FIRST + 8].try_into().unwrap(),
+    ))
+}
+
+fn record_offset(first: u64, sequence: u64) -> u64 {
+    LOG_HEADER_SIZE as u64 + (sequence - first) * RECORD_SIZE as u64
+}
+
+struct LogWriter {
+    file: File,
+    first: u64,
+}
+
+// Changes made by the open transaction, and where committed ones go. Every change gets the
+// next sequence number when its transaction commits; the last one handed out is kept in the
+// meta page. With the log enabled, a commit writes and syncs its records before the WAL, and
+// records past the meta page's sequence are cut off on open, so the log never keeps a change
+// that did not commit. A follower always numbers its changes, so its sequence keeps step with
+// the primary's.
+pub(crate) struct ChangeFeed {
+    path: PathBuf,
+    pub(crate) following: bool,
+    pub(crate) sequence: u64,
+    pub(crate) pending: Vec<Change>,
+    log: Option<LogWriter>,
+    subscribers: Vec<Sender<ChangeEvent>>,
+}
+
+impl ChangeFeed {
+    pub(crate) fn new(index_path: &Path) -> Self {
+        ChangeFeed {
+            path: log_path(index_path),
+            following: false,
+            sequence: 0,
+            pending: Vec::new(),
+            log: None,
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.following || self.log.is_some() || !self.subscribers.is_empty()
+    }
+
+    // Opens the log of a file that has it enabled, creating it afresh if it went missing.
+    pub(crate) fn open_log(&mut self) -> Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        if file.metadata()?.len() == 0 {
+            let mut header = [0u8; LOG_HEADER_SIZE];
+            header[..LOG_MAGIC.len()].copy_from_slice(LOG_MAGIC);
+            header[LOG_FIRST..].copy_from_slice(&(self.sequence + 1).to_le_bytes());
+            file.write_all_at(&header, 0)?;
+            file.sync_all()?;
+        }
+        let first = read_header(&file)?;
+        if first > self.sequence + 1 {
+            return Err(broken_log(format!(
+                "starts at {first}, past the index's last change {}",
+                self.sequence
+            )));
+        }
+        let committed = record_offset(first, self.sequence + 1);
+        if file.metadata()?.len() > committed {
+            file.set_len(committed)?;
+            file.sync_all()?;
+        }
+        self.log = Some(LogWriter { file, first });
+        Ok(())
+    }
+
+    pub(crate) fn subscribe(&mut self) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    fn events(&self) -> impl Iterator<Item = ChangeEvent> + '_ {
+        let last = self.sequence + self.pending.len() as u64;
+        self.pending
+            .iter()
+            .zip(self.sequence + 1..)
+            .map(move |(change, sequence)| ChangeEvent {
+                sequence,
+                change: change.clone(),
+                last_in_commit: sequence == last,
+            })
+    }
+
+    // Writes the pending changes to the log ahead of the commit and returns the sequence number
+    // the meta page should record.
+    pub(crate) fn stage(&mut self) -> Result<u64> {
+        if let (Some(log), false) = (&self.log, self.pending.is_empty()) {
+            let offset = record_offset(log.first, self.sequence + 1);
+            let records: Vec<u8> = self.events().flat_map(|event| event.encode()).collect();
+            log.file.set_len(offset)?;
+            log.file.write_all_at(&records, offset)?;
+            log.file.sync_data()?;
+        }
+        Ok(self.sequence + self.pending.len() as u64)
+    }
+
+    // Hands the committed changes to the subscribers, dropping the ones that hung up.
+    pub(crate) fn publish(&mut self, sequence: u64) {
+        let events: Vec<ChangeEvent> = self.events().collect();
+        self.subscribers.retain(|subscriber| {
+            events
+                .iter()
+                .all(|event| subscriber.send(event.clone()).is_ok())
+        });
+        self.pending.clear();
+        self.sequence = sequence;
+    }
+}
+
+// Reads the change log of an index, possibly while another process writes to it. Only changes
+// the index has committed are returned.
+pub struct ChangeLog {
+    index: File,
+    log: File,
+    first: u64,
+    next: u64,
+}
+
+impl ChangeLog {
+    // Positioned at the oldest change the log holds.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let index = File::open(path.as_ref())?;
+        let log = File::open(log_path(path.as_ref()))?;
+        let first = read_header(&log)?;
+        Ok(ChangeLog {
+            index,
+            log,
+            first,
+            next: first,
+        })
+    }
+
+    // The sequence number of the next change `poll` returns.
+    pub fn position(&self) -> u64 {
+        self.next
+    }
+
+    // Resumes from `sequence`, typically one past the last change processed. Fails if the log
+    // starts after it.
+    pub fn seek(&mut self, sequence: u64) -> Result<()> {
+        if sequence < self.first {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("change log starts at {}", self.first),
+            ));
+        }
+        self.next = sequence;
+        Ok(())
+    }
+
+    fn committed_sequence(&self) -> Result<u64> {
+        let mut meta = vec![0u8; PAGE_SIZE];
+        self.index.read_exact_at(&mut meta, 0)?;
+        let meta = parse_meta(|page| (page == 0).then_some(&meta[..]))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "not a B+ tree index file"))?;
+        Ok(meta.sequence)
+    }
+
+    // Every committed change from the current position on, oldest first. Returns an empty list
+    // when there is nothing new yet.
+    pub fn poll(&mut self) -> Result<Vec<ChangeEvent>> {
+        let last = self.committed_sequence()?;
+        if last < self.next {
+            return Ok(Vec::new());
+        }
+        let mut records = vec![0u8; (last - self.next + 1) as usize * RECORD_SIZE];
+        self.log
+            .read_exact_at(&mut records, record_offset(self.first, self.next))
+            .map_err(|_| broken_log(format!("records up to {last} missing")))?;
+        let mut events = Vec::with_capacity(records.len() / RECORD_SIZE);
+        for (record, sequence) in records.chunks_exact(RECORD_SIZE).zip(self.next..) {
+            match ChangeEvent::decode(record) {
+                Some(event) if event.sequence == sequence => events.push(event),
+                _ => return Err(broken_log(format!("record {sequence} is damaged"))),
+            }
+        }
+        self.next = last + 1;
+        Ok(events)
+    }
+}
+
+impl BPlusTree {
+    // Changes are followed for the main tree of a unique-key index only.
+    fn check_feed(&self) -> Result<()> {
+        if self.multimap || !self.open_trees.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "change feeds need the main tree of a unique-key index",
+            ));
+        }
+        Ok(())
+    }
+
+    // Records a change to the tree and keeps the secondary indexes in step with it.
+    pub(crate) fn changed(
+        &mut self,
+        key: i32,
+        old: Option<&[u8; DATA_SIZE]>,
+        new: Option<&[u8; DATA_SIZE]>,
+    ) -> Result<()> {
+        if self.is_capturing() {
+            let change = match (old, new) {
+                (old, Some(new)) => Change::Put {
+                    key,
+                    old: old.copied(),
+                    new: *new,
+                },
+                (Some(old), None) => Change::Delete { key, old: *old },
+                (None, None) => unreachable!("a change needs a value on one side"),
+            };
+            self.pager.write().unwrap().changes.pending.push(change);
+        }
+        self.reindex(key, old, new)
+    }
+
+    pub(crate) fn is_capturing(&self) -> bool {
+        !self.multimap
+            && self.open_trees.is_empty()
+            && self.pager.read().unwrap().changes.is_active()
+    }
+
+    // A channel receiving every change from the next commit on, in sequence order.
+    pub fn subscribe(&mut self) -> Result<Receiver<ChangeEvent>> {
+        self.check_feed()?;
+        Ok(self.pager.write().unwrap().changes.subscribe())
+    }
+
+    // Starts keeping `<path>.changes`, which `ChangeLog` reads. The setting is stored in the
+    // file, so the log stays on for every later open.
+    pub fn enable_change_log(&mut self) -> Result<()> {
+        self.check_feed()?;
+        let mut pager = self.pager.write().unwrap();
+        if pager.flags & FLAG_CHANGE_LOG != 0 {
+            return Ok(());
+        }
+        pager.changes.open_log()?;
+        let flags = pager.flags | FLAG_CHANGE_LOG;
+        pager.set_flags(flags);
+        drop(pager);
+        self.autocommit(|_| Ok(()))
+    }
+
+    // The sequence number of the last committed change, 0 before the first.
+    pub fn sequence(&self) -> u64 {
+        self.pager.read().unwrap().changes.sequence
+    }
+}
@@ -0,0 +1,67 @@
+use crate::verify::corrupt;
+use crate::{BPlusTree, TreeReader, PAGE_SIZE};
+use std::io::Result;
+use std::sync::atomic::Ordering;
+
+// What a node read does when the stored checksum does not match the page contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumPolicy {
+    // Fail the read with an `InvalidData` error naming the page instead of decoding it.
+    #[default]
+    Error,
+    // Count the page, tell the observers, and decode it anyway.
+    Log,
+    // Do not verify at all, for reading what is left of a damaged file.
+    Salvage,
+}
+
+// CRC32C of the page with the 4 checksum bytes at `offset` left out.
+pub(crate) fn page_checksum(page: &[u8; PAGE_SIZE], offset: usize) -> u32 {
+    crc32c::crc32c_append(crc32c::crc32c(&page[..offset]), &page[offset + 4..])
+}
+
+pub(crate) fn seal(page: &mut [u8; PAGE_SIZE], offset: usize) {
+    let checksum = page_checksum(page, offset);
+    page[offset..offset + 4].copy_from_slice(&checksum.to_le_bytes());
+}
+
+impl TreeReader<'_> {
+    // Every mismatch is counted and reported to `Observer::on_checksum_failure`, whatever the
+    // policy; only `Error` fails the read.
+    pub(crate) fn verify_page(
+        &self,
+        page_num: usize,
+        page: &[u8; PAGE_SIZE],
+        offset: usize,
+    ) -> Result<()> {
+        let pager = self.pager.read().unwrap();
+        if pager.checksum_policy == ChecksumPolicy::Salvage {
+            return Ok(());
+        }
+        let stored = u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap());
+        if stored == page_checksum(page, offset) {
+            return Ok(());
+        }
+        pager.checksum_failures.fetch_add(1, Ordering::Relaxed);
+        pager.notify(|o| o.on_checksum_failure(page_num));
+        match pager.checksum_policy {
+            ChecksumPolicy::Error => Err(corrupt(page_num, "checksum mismatch")),
+            ChecksumPolicy::Log | ChecksumPolicy::Salvage => Ok(()),
+        }
+    }
+}
+
+impl BPlusTree {
+    pub fn set_checksum_policy(&mut self, policy: ChecksumPolicy) {
+        self.pager.write().unwrap().checksum_policy = policy;
+    }
+
+    // Number of node reads whose checksum did not match since the file was opened.
+    pub fn checksum_failures(&self) -> u64 {
+        self.pager
+            .read()
+            .unwrap()
+            .checksum_failures
+            .load(Ordering::Relaxed)
+    }
+}
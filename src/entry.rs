@@ -0,0 +1,150 @@
+use crate::{BPlusTree, LeafNode, Update, DATA_SIZE};
+use std::io::Result;
+
+// Entries keep the leaf found by `BPlusTree::entry` so reads and writes through them reuse the
+// same descent. Entries taken outside a transaction commit every write they make.
+pub enum Entry<'a> {
+    Occupied(OccupiedEntry<'a>),
+    Vacant(VacantEntry<'a>),
+}
+
+pub struct OccupiedEntry<'a> {
+    tree: &'a mut BPlusTree,
+    autocommit: bool,
+    key: i32,
+    leaf_page: usize,
+    leaf: LeafNode,
+    pos: usize,
+}
+
+pub struct VacantEntry<'a> {
+    tree: &'a mut BPlusTree,
+    autocommit: bool,
+    key: i32,
+    leaf_page: usize,
+    leaf: LeafNode,
+    pos: usize,
+}
+
+fn finish(tree: &mut BPlusTree, autocommit: bool, result: Result<()>) -> Result<()> {
+    if !autocommit {
+        return result;
+    }
+    match result {
+        Ok(()) => tree.commit(),
+        Err(e) => {
+            tree.rollback();
+            Err(e)
+        }
+    }
+}
+
+impl<'a> Entry<'a> {
+    pub(crate) fn new(tree: &'a mut BPlusTree, key: i32, autocommit: bool) -> Result<Self> {
+        let (leaf_page, leaf, found) = tree.locate(key)?;
+        Ok(match found {
+            Ok(pos) => Entry::Occupied(OccupiedEntry {
+                tree,
+                autocommit,
+                key,
+                leaf_page,
+                leaf,
+                pos,
+            }),
+            Err(pos) => Entry::Vacant(VacantEntry {
+                tree,
+                autocommit,
+                key,
+                leaf_page,
+                leaf,
+                pos,
+            }),
+        })
+    }
+
+    pub fn key(&self) -> i32 {
+        match self {
+            Entry::Occupied(entry) => entry.key,
+            Entry::Vacant(entry) => entry.key,
+        }
+    }
+
+    pub fn or_insert(self, default: [u8; DATA_SIZE]) -> Result<[u8; DATA_SIZE]> {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> [u8; DATA_SIZE]>(
+        self,
+        default: F,
+    ) -> Result<[u8; DATA_SIZE]> {
+        match self {
+            Entry::Occupied(entry) => entry.get(),
+            Entry::Vacant(entry) => {
+                let data = default();
+                entry.insert(data)?;
+                Ok(data)
+            }
+        }
+    }
+
+    pub fn and_modify<F: FnOnce(&mut [u8; DATA_SIZE])>(self, f: F) -> Result<Self> {
+        match self {
+            Entry::Occupied(mut entry) => {
+                let mut data = entry.get()?;
+                f(&mut data);
+                entry.insert(data)?;
+                Ok(Entry::Occupied(entry))
+            }
+            Entry::Vacant(entry) => Ok(Entry::Vacant(entry)),
+        }
+    }
+}
+
+impl OccupiedEntry<'_> {
+    pub fn key(&self) -> i32 {
+        self.key
+    }
+
+    pub fn get(&self) -> Result<[u8; DATA_SIZE]> {
+        self.tree.reader().value_at(&self.leaf, self.pos)
+    }
+
+    pub fn insert(&mut self, data: [u8; DATA_SIZE]) -> Result<[u8; DATA_SIZE]> {
+        let old = self.get()?;
+        let result = self
+            .tree
+            .overwrite_value(self.leaf_page, &mut self.leaf, self.pos, &data);
+        finish(self.tree, self.autocommit, result)?;
+        Ok(old)
+    }
+
+    pub fn remove(self) -> Result<[u8; DATA_SIZE]> {
+        let old = self.get()?;
+        let result = self.tree.apply_update(
+            self.leaf_page,
+            self.leaf,
+            Ok(self.pos),
+            self.key,
+            Update::Remove,
+        );
+        finish(self.tree, self.autocommit, result)?;
+        Ok(old)
+    }
+}
+
+impl VacantEntry<'_> {
+    pub fn key(&self) -> i32 {
+        self.key
+    }
+
+    pub fn insert(self, data: [u8; DATA_SIZE]) -> Result<()> {
+        let result = self.tree.apply_update(
+            self.leaf_page,
+            self.leaf,
+            Err(self.pos),
+            self.key,
+            Update::Put(data),
+        );
+        finish(self.tree, self.autocommit, result)
+    }
+}
@@ -0,0 +1,341 @@
+use crate::verify::Violation;
+use crate::{BPlusTree, InternalNode, LeafNode, TreeReader, INTERNAL_BODY, INTERNAL_ORDER};
+use bincode::config;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::io::{Error, ErrorKind, Result};
+
+// Leaves in DOT show this many keys from each end; the JSON always has all of them.
+const DOT_LEAF_KEYS: usize = 3;
+
+enum Kind {
+    Internal {
+        children: Vec<i32>,
+        counts: Vec<u64>,
+    },
+    Leaf {
+        prev: i32,
+        next: i32,
+    },
+    // Failed its checksum, could not be decoded or is not a tree page at all.
+    Unreadable,
+}
+
+struct Node {
+    page: usize,
+    depth: usize,
+    parent: i32,
+    keys: Vec<i32>,
+    kind: Kind,
+    problems: Vec<String>,
+}
+
+struct Export {
+    from: Option<usize>,
+    max_depth: Option<usize>,
+    nodes: Vec<Node>,
+    // Found by the verifier on pages the export does not include, such as posting pages.
+    elsewhere: Vec<Violation>,
+}
+
+impl TreeReader<'_> {
+    // Reads the pages under `from` down to `max_depth` levels below it without trusting any of
+    // them, and attaches what the verifier reports for each.
+    fn export(&self, from: Option<usize>, max_depth: Option<usize>) -> Result<Export> {
+        let num_pages = self.pager.read().unwrap().num_pages();
+        let from = from.or((self.root_page != -1).then_some(self.root_page as usize));
+        if from.is_some_and(|page| page == 0 || page >= num_pages) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("page {} is not in the file", from.unwrap()),
+            ));
+        }
+
+        let mut problems: HashMap<usize, Vec<Violation>> = HashMap::new();
+        for violation in self.violations() {
+            problems.entry(violation.page).or_default().push(violation);
+        }
+
+        let mut nodes = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack: Vec<(usize, usize)> = from.into_iter().map(|page| (page, 0)).collect();
+        while let Some((page_num, depth)) = stack.pop() {
+            if !seen.insert(page_num) {
+                continue;
+            }
+            let mut node = Node {
+                page: page_num,
+                depth,
+                parent: -1,
+                keys: Vec::new(),
+                kind: Kind::Unreadable,
+                problems: problems
+                    .remove(&page_num)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|violation| violation.problem)
+                    .collect(),
+            };
+            let page = match self.checked_page(page_num, num_pages) {
+                Ok(page) => page,
+                Err(e) => {
+                    if node.problems.is_empty() {
+                        node.problems.push(problem(e));
+                    }
+                    nodes.push(node);
+                    continue;
+                }
+            };
+
+            if page[0] == 1 {
+                let leaf = LeafNode { page };
+                node.parent = leaf.parent();
+                if leaf.is_well_formed() {
+                    node.keys = (0..leaf.num_keys()).map(|i| leaf.key(i)).collect();
+                    node.kind = Kind::Leaf {
+                        prev: leaf.prev_leaf(),
+                        next: leaf.next_leaf(),
+                    };
+                } else if node.problems.is_empty() {
+                    node.problems.push("malformed leaf".to_string());
+                }
+                nodes.push(node);
+                continue;
+            }
+
+            match bincode::decode_from_slice::<InternalNode, _>(
+                &page[INTERNAL_BODY..],
+                config::standard(),
+            ) {
+                Ok((internal, _)) => {
+                    let n = internal.num_keys.min(INTERNAL_ORDER);
+                    node.parent = internal.parent;
+                    node.keys = internal.keys[..n].to_vec();
+                    let children = internal.children[..=n].to_vec();
+                    if max_depth.is_none_or(|max| depth < max) {
+                        // Pushed in reverse so nodes come off the stack in key order.
+                        for &child in children.iter().rev() {
+                            stack.push((child.max(0) as usize, depth + 1));
+                        }
+                    }
+                    node.kind = Kind::Internal {
+                        children,
+                        counts: internal.counts[..=n].to_vec(),
+                    };
+                }
+                Err(e) => {
+                    if node.problems.is_empty() {
+                        node.problems.push(e.to_string());
+                    }
+                }
+            }
+            nodes.push(node);
+        }
+
+        let mut elsewhere: Vec<Violation> = problems.into_values().flatten().collect();
+        elsewhere.sort_by_key(|violation| violation.page);
+        Ok(Export {
+            from,
+            max_depth,
+            nodes,
+            elsewhere,
+        })
+    }
+}
+
+fn problem(e: Error) -> String {
+    match e.into_inner().map(|inner| inner.downcast::<Violation>()) {
+        Some(Ok(violation)) => violation.problem,
+        Some(Err(inner)) => inner.to_string(),
+        None => "unreadable".to_string(),
+    }
+}
+
+fn list<T: ToString>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(T::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// Escapes the characters that mean something inside a DOT record label.
+fn dot_text(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        if "{}|<>\"\\".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl Export {
+    fn json(&self) -> String {
+        let optional = |value: Option<usize>| value.map_or("null".to_string(), |v| v.to_string());
+        let mut json = String::from("{\n");
+        writeln!(json, "  \"from\": {},", optional(self.from)).unwrap();
+        writeln!(json, "  \"max_depth\": {},", optional(self.max_depth)).unwrap();
+        json.push_str("  \"nodes\": [");
+        for (i, node) in self.nodes.iter().enumerate() {
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            write!(
+                json,
+                "    {{\"page\": {}, \"depth\": {}, \"parent\": {}, ",
+                node.page, node.depth, node.parent
+            )
+            .unwrap();
+            match &node.kind {
+                Kind::Internal { children, counts } => write!(
+                    json,
+                    "\"kind\": \"internal\", \"children\": [{}], \"counts\": [{}], ",
+                    list(children),
+                    list(counts)
+                ),
+                Kind::Leaf { prev, next } => write!(
+                    json,
+                    "\"kind\": \"leaf\", \"prev\": {prev}, \"next\": {next}, "
+                ),
+                Kind::Unreadable => write!(json, "\"kind\": \"unreadable\", "),
+            }
+            .unwrap();
+            let problems: Vec<_> = node.problems.iter().map(|p| json_string(p)).collect();
+            write!(
+                json,
+                "\"keys\": [{}], \"violations\": [{}]}}",
+                list(&node.keys),
+                problems.join(", ")
+            )
+            .unwrap();
+        }
+        json.push_str(if self.nodes.is_empty() {
+            "],\n"
+        } else {
+            "\n  ],\n"
+        });
+        json.push_str("  \"other_violations\": [");
+        for (i, violation) in self.elsewhere.iter().enumerate() {
+            json.push_str(if i == 0 { "\n" } else { ",\n" });
+            write!(
+                json,
+                "    {{\"page\": {}, \"problem\": {}}}",
+                violation.page,
+                json_string(&violation.problem)
+            )
+            .unwrap();
+        }
+        json.push_str(if self.elsewhere.is_empty() {
+            "]\n}\n"
+        } else {
+            "\n  ]\n}\n"
+        });
+        json
+    }
+
+    fn dot(&self) -> String {
+        let drawn: HashSet<usize> = self.nodes.iter().map(|node| node.page).collect();
+        let mut dot = String::from("digraph bptree {\n");
+        dot.push_str("  node [shape=record, fontname=\"monospace\"];\n");
+        for node in &self.nodes {
+            let keys = match node.kind {
+                Kind::Leaf { .. } if node.keys.len() > 2 * DOT_LEAF_KEYS => format!(
+                    "{} .. {} ({} keys)",
+                    list(&node.keys[..DOT_LEAF_KEYS]),
+                    list(&node.keys[node.keys.len() - DOT_LEAF_KEYS..]),
+                    node.keys.len()
+                ),
+                _ => list(&node.keys),
+            };
+            let title = match node.kind {
+                Kind::Internal { .. } => "internal",
+                Kind::Leaf { .. } => "leaf",
+                Kind::Unreadable => "unreadable",
+            };
+            let mut label = format!(
+                "{title} {} | parent {} | {}",
+                node.page,
+                node.parent,
+                dot_text(&keys)
+            );
+            for problem in &node.problems {
+                write!(label, " | {}", dot_text(problem)).unwrap();
+            }
+            let style = if node.problems.is_empty() {
+                ""
+            } else {
+                ", color=red, fontcolor=red, penwidth=2"
+            };
+            writeln!(dot, "  p{} [label=\"{{{label}}}\"{style}];", node.page).unwrap();
+
+            match &node.kind {
+                Kind::Internal { children, counts } => {
+                    for (child, count) in children.iter().zip(counts) {
+                        let child = child.max(&0);
+                        if drawn.contains(&(*child as usize)) {
+                            writeln!(dot, "  p{} -> p{child} [label=\"{count}\"];", node.page)
+                                .unwrap();
+                        }
+                    }
+                }
+                Kind::Leaf { prev, next } => {
+                    for (link, style) in [(next, "dashed"), (prev, "dotted")] {
+                        if *link != -1 && drawn.contains(&(*link as usize)) {
+                            writeln!(
+                                dot,
+                                "  p{} -> p{link} [style={style}, constraint=false];",
+                                node.page
+                            )
+                            .unwrap();
+                        }
+                    }
+                }
+                Kind::Unreadable => {}
+            }
+        }
+        if !self.elsewhere.is_empty() {
+            let lines: Vec<_> = self
+                .elsewhere
+                .iter()
+                .map(|violation| dot_text(&violation.to_string()))
+                .collect();
+            writeln!(
+                dot,
+                "  other_violations [label=\"{{{}}}\", color=red, fontcolor=red];",
+                lines.join(" | ")
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl BPlusTree {
+    // The tree under `from` (the root if None), `max_depth` levels down (all of them if None),
+    // as a Graphviz digraph. Child edges are labelled with the counts the parent keeps, `next`
+    // links are dashed and `prev` links dotted. Pages the verifier finds a problem on are drawn
+    // in red with the problems in their label.
+    pub fn export_dot(&self, from: Option<usize>, max_depth: Option<usize>) -> Result<String> {
+        Ok(self.reader().export(from, max_depth)?.dot())
+    }
+
+    // The same pages as `export_dot` as a JSON document, one object per page in key order.
+    pub fn export_json(&self, from: Option<usize>, max_depth: Option<usize>) -> Result<String> {
+        Ok(self.reader().export(from, max_depth)?.json())
+    }
+}
@@ -0,0 +1,218 @@
+// Entry points for the cargo-fuzz targets under fuzz/, shared with the tests that replay their
+// corpus. Malformed input has to come back as an error from `open`, `verify` or the read that
+// ran into it; anything that panics or hangs here is a bug.
+use crate::catalog::{CATALOG_CHECKSUM, CATALOG_PAGE_TYPE};
+use crate::checksum::seal;
+use crate::pager::{sidecar, SIDECARS};
+use crate::posting::{POSTING_CHECKSUM, POSTING_PAGE_TYPE};
+use crate::{BPlusTree, DATA_SIZE, INLINE_VALUES, INTERNAL_CHECKSUM, LEAF_CHECKSUM, PAGE_SIZE};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+// An index path of its own for each run, removed with its WAL and change log when dropped.
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    fn new() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path =
+            std::env::temp_dir().join(format!("bptree_fuzz_{}_{id}.dat", std::process::id()));
+        let scratch = Scratch { path };
+        scratch.remove();
+        scratch
+    }
+
+    fn remove(&self) {
+        let _ = std::fs::remove_file(&self.path);
+        for extension in SIDECARS {
+            let _ = std::fs::remove_file(sidecar(&self.path, extension));
+        }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+fn value(key: i32, seed: u8) -> [u8; DATA_SIZE] {
+    let mut data = [seed; DATA_SIZE];
+    data[..4].copy_from_slice(&key.to_le_bytes());
+    data
+}
+
+// A committed multimap index two levels deep, with posting lists, for the page target to damage.
+fn base_file() -> &'static [u8] {
+    static BASE: OnceLock<Vec<u8>> = OnceLock::new();
+    BASE.get_or_init(|| {
+        let scratch = Scratch::new();
+        let mut tree = BPlusTree::open_multimap(&scratch.path).unwrap();
+        let mut tx = tree.begin().unwrap();
+        for key in 0..120 {
+            tx.insert_value(key * 3, &value(key * 3, 0)).unwrap();
+        }
+        for key in 0..3 {
+            for seed in 1..=INLINE_VALUES as u8 {
+                tx.insert_value(key * 3, &value(key * 3, seed)).unwrap();
+            }
+        }
+        tx.commit().unwrap();
+        drop(tree);
+        std::fs::read(&scratch.path).unwrap()
+    })
+}
+
+// Reads that may run into damage: each can fail, but has to return rather than panic or loop.
+// `verify` does not read the catalog, so the catalog reads run on every file that opens.
+fn probe_reads(tree: &mut BPlusTree) {
+    let _ = tree.read_range_data(i32::MIN, i32::MAX);
+    let _ = tree.count_range(..);
+    let _ = tree.first();
+    let _ = tree.last();
+    let _ = tree.stats();
+    let len = tree.len().unwrap_or(0);
+    for i in (0..16).map(|n| len / 16 * n) {
+        let _ = tree.nth(i);
+    }
+    for key in [i32::MIN, -1, 0, 1, 42, 1000, i32::MAX] {
+        let _ = tree.read(key);
+        let _ = tree.get_all(key);
+        let _ = tree.rank(key);
+        let _ = tree.lower_bound(key);
+        let _ = tree.upper_bound(key);
+        let _ = tree.floor(key);
+    }
+    let _ = tree.lookup_by("fuzz", 0);
+    for name in tree.list_trees().unwrap_or_default() {
+        if let Ok(named) = tree.open_tree(&name) {
+            let _ = named.read_range_data(i32::MIN, i32::MAX);
+        }
+    }
+}
+
+// Opens the bytes as an index file. Reads of a file that fails to verify only have to come
+// back, as do the catalog reads of one that verifies. Otherwise every record has to be readable
+// and the tree has to stay valid through a write and a delete.
+pub fn index_file(data: &[u8]) {
+    let scratch = Scratch::new();
+    std::fs::write(&scratch.path, data).unwrap();
+    let Ok(mut tree) = BPlusTree::open(&scratch.path) else {
+        return;
+    };
+    let verified = tree.verify().is_ok();
+    probe_reads(&mut tree);
+    if !verified {
+        return;
+    }
+
+    let records = tree.read_range_data(i32::MIN, i32::MAX).unwrap();
+    let mut keys = Vec::new();
+    let mut values = 0;
+    for i in 0..tree.len().unwrap() {
+        let (key, data) = tree.nth(i).unwrap().unwrap();
+        assert_eq!(tree.read(key).unwrap(), Some(data));
+        assert_eq!(tree.rank(key).unwrap(), i);
+        let all = tree.get_all(key).unwrap();
+        assert_eq!(all.first(), Some(&data));
+        values += all.len();
+        keys.push(key);
+    }
+    assert_eq!(records.len(), values);
+    assert_eq!(
+        tree.first().unwrap().map(|(key, _)| key),
+        keys.first().copied()
+    );
+    assert_eq!(
+        tree.last().unwrap().map(|(key, _)| key),
+        keys.last().copied()
+    );
+
+    let key = keys
+        .get(keys.len() / 2)
+        .map_or(0, |key| key.wrapping_add(1));
+    if tree.is_multimap() {
+        tree.insert_value(key, &value(key, 7)).unwrap();
+        tree.delete_all(key).unwrap();
+    } else {
+        tree.write_data(key, &value(key, 7)).unwrap();
+        tree.delete(key).unwrap();
+    }
+    tree.verify().unwrap();
+}
+
+// The first byte picks a page of `base_file` to replace with the rest of the input. Images of
+// the checksummed page types get a valid checksum so the decoder behind it is what gets exercised.
+pub fn page_image(data: &[u8]) {
+    let Some((&target, image)) = data.split_first() else {
+        return;
+    };
+    let mut file = base_file().to_vec();
+    let page_num = 1 + target as usize % (file.len() / PAGE_SIZE - 1);
+    let mut page = [0u8; PAGE_SIZE];
+    let len = image.len().min(PAGE_SIZE);
+    page[..len].copy_from_slice(&image[..len]);
+    match page[0] {
+        0 => seal(&mut page, INTERNAL_CHECKSUM),
+        1 => seal(&mut page, LEAF_CHECKSUM),
+        POSTING_PAGE_TYPE => seal(&mut page, POSTING_CHECKSUM),
+        CATALOG_PAGE_TYPE => seal(&mut page, CATALOG_CHECKSUM),
+        _ => {}
+    }
+    file[page_num * PAGE_SIZE..(page_num + 1) * PAGE_SIZE].copy_from_slice(&page);
+    index_file(&file);
+}
+
+// Runs the input as a sequence of 4-byte operations against a fresh index and a BTreeMap,
+// comparing every result. Runs of consecutive keys go through one transaction so a short input
+// can still split and merge internal nodes.
+pub fn api_sequence(data: &[u8]) {
+    let scratch = Scratch::new();
+    let mut tree = BPlusTree::open(&scratch.path).unwrap();
+    let mut model = BTreeMap::new();
+    for op in data.chunks_exact(4) {
+        let key = i16::from_le_bytes([op[1], op[2]]) as i32;
+        let arg = op[3];
+        match op[0] % 7 {
+            0 => {
+                let new = model.insert(key, value(key, arg)).is_none();
+                assert_eq!(tree.write_data(key, &value(key, arg)).unwrap(), new);
+            }
+            1 => assert_eq!(tree.delete(key).unwrap(), model.remove(&key).is_some()),
+            2 => assert_eq!(tree.read(key).unwrap(), model.get(&key).copied()),
+            3 => {
+                let end = key + arg as i32;
+                let expected: Vec<_> = model.range(key..=end).map(|(_, data)| *data).collect();
+                assert!(tree.read_range_data(key, end).unwrap() == expected);
+            }
+            4 => {
+                let mut tx = tree.begin().unwrap();
+                for key in key..key + arg as i32 * 16 {
+                    tx.put(key, &value(key, arg)).unwrap();
+                    model.insert(key, value(key, arg));
+                }
+                tx.commit().unwrap();
+            }
+            5 => {
+                let mut tx = tree.begin().unwrap();
+                for key in key..key + arg as i32 * 16 {
+                    tx.delete(key).unwrap();
+                    model.remove(&key);
+                }
+                tx.commit().unwrap();
+            }
+            _ => {
+                drop(tree);
+                tree = BPlusTree::open(&scratch.path).unwrap();
+            }
+        }
+        tree.verify().unwrap();
+    }
+    let all: Vec<_> = model.values().copied().collect();
+    assert!(tree.read_range_data(i32::MIN, i32::MAX).unwrap() == all);
+}
@@ -0,0 +1,257 @@
+use crate::catalog::{CatalogEntry, KIND_INDEX};
+use crate::{BPlusTree, LeafNode, TreeReader, DATA_SIZE};
+use std::io::{Error, ErrorKind, Result};
+
+type KeyExtractor = Box<dyn Fn(&[u8; DATA_SIZE]) -> i32>;
+
+// A secondary index is a multimap tree in the same file mapping the extracted key to the
+// primary keys of the records that carry it. Only the tree is stored; the extractor has to be
+// registered again on every open before writing, or the index falls behind.
+pub(crate) struct SecondaryIndex {
+    name: String,
+    extract: KeyExtractor,
+}
+
+fn primary_value(key: i32) -> [u8; DATA_SIZE] {
+    let mut value = [0u8; DATA_SIZE];
+    value[..4].copy_from_slice(&key.to_le_bytes());
+    value
+}
+
+fn primary_key(value: &[u8; DATA_SIZE]) -> i32 {
+    i32::from_le_bytes(value[..4].try_into().unwrap())
+}
+
+fn unknown_index(name: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("no index named {name}"))
+}
+
+impl TreeReader<'_> {
+    fn index_reader(&self, name: &str) -> Result<TreeReader<'_>> {
+        let entry = self
+            .catalog_entry(name, KIND_INDEX)?
+            .ok_or_else(|| unknown_index(name))?;
+        Ok(TreeReader {
+            pager: self.pager,
+            snapshot: self.snapshot,
+            root_page: entry.root,
+        })
+    }
+
+    pub(crate) fn lookup_by(
+        &self,
+        index: &str,
+        secondary_key: i32,
+    ) -> Result<Vec<(i32, [u8; DATA_SIZE])>> {
+        let mut keys: Vec<i32> = self
+            .index_reader(index)?
+            .get_all(secondary_key)?
+            .iter()
+            .map(primary_key)
+            .collect();
+        keys.sort_unstable();
+        let mut records = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(data) = self.read(key)? {
+                records.push((key, data));
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl BPlusTree {
+    // Runs `op` with the index tree swapped in as the current tree and stores its new root in
+    // the catalog. Registered indexes are set aside meanwhile so the index is not indexed itself.
+    fn on_index<R>(&mut self, name: &str, op: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let entry = self
+            .reader()
+            .catalog_entry(name, KIND_INDEX)?
+            .ok_or_else(|| unknown_index(name))?;
+        let root_page = std::mem::replace(&mut self.root_page, entry.root);
+        let multimap = std::mem::replace(&mut self.multimap, true);
+        let indexes = std::mem::take(&mut self.indexes);
+        let result = op(self);
+        let index_root = std::mem::replace(&mut self.root_page, root_page);
+        self.multimap = multimap;
+        self.indexes = indexes;
+
+        let result = result?;
+        if index_root != entry.root {
+            self.set_catalog_root(name, KIND_INDEX, index_root)?;
+        }
+        Ok(result)
+    }
+
+    pub(crate) fn relink_indexes(&mut self) -> Result<usize> {
+        let names: Vec<String> = self
+            .indexes
+            .iter()
+            .map(|index| index.name.clone())
+            .collect();
+        let mut rewritten = 0;
+        for name in names {
+            rewritten += self.on_index(&name, |tree| tree.relink())?;
+        }
+        Ok(rewritten)
+    }
+
+    // Moves `key` from the index entries for its old value to those for its new one.
+    pub(crate) fn reindex(
+        &mut self,
+        key: i32,
+        old: Option<&[u8; DATA_SIZE]>,
+        new: Option<&[u8; DATA_SIZE]>,
+    ) -> Result<()> {
+        let changes: Vec<_> = self
+            .indexes
+            .iter()
+            .map(|index| {
+                let before = old.map(|data| (index.extract)(data));
+                let after = new.map(|data| (index.extract)(data));
+                (index.name.clone(), before, after)
+            })
+            .filter(|(_, before, after)| before != after)
+            .collect();
+
+        let value = primary_value(key);
+        for (name, before, after) in changes {
+            self.on_index(&name, |tree| {
+                if let Some(secondary_key) = before {
+                    tree.remove_value(secondary_key, &value)?;
+                }
+                if let Some(secondary_key) = after {
+                    tree.append_value(secondary_key, &value)?;
+                }
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn build_index(&mut self, index: &SecondaryIndex) -> Result<()> {
+        let page = self.allocate_page()?;
+        self.write_leaf_node(page, &LeafNode::new())?;
+        self.add_catalog_entry(CatalogEntry {
+            name: index.name.clone(),
+            kind: KIND_INDEX,
+            root: page as i32,
+        })?;
+
+        let records = self.reader().read_range_entries(i32::MIN, i32::MAX)?;
+        self.on_index(&index.name, |tree| {
+            for (key, data) in &records {
+                tree.append_value((index.extract)(data), &primary_value(*key))?;
+            }
+            Ok(())
+        })
+    }
+
+    // Maintains an index over `extract(value)` from now on. An index of that name already in
+    // the file is reattached as is; otherwise it is created and filled from the existing records.
+    pub fn register_index(
+        &mut self,
+        name: &str,
+        extract: impl Fn(&[u8; DATA_SIZE]) -> i32 + 'static,
+    ) -> Result<()> {
+        if self.multimap {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "secondary indexes need a unique-key index",
+            ));
+        }
+        // The guard of a named tree restores the main tree's indexes when it drops, so an
+        // index registered through it would stop being maintained while its pages live on.
+        if !self.open_trees.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "secondary indexes can only be registered on the main tree",
+            ));
+        }
+        if self.indexes.iter().any(|index| index.name == name) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("index {name} is already registered"),
+            ));
+        }
+
+        let index = SecondaryIndex {
+            name: name.to_string(),
+            extract: Box::new(extract),
+        };
+        if self.reader().catalog_entry(name, KIND_INDEX)?.is_none() {
+            self.autocommit(|tree| tree.build_index(&index))?;
+        }
+        self.indexes.push(index);
+        Ok(())
+    }
+
+    // Stops maintaining the index and frees its pages. Returns false if the file has no such index.
+    pub fn drop_index(&mut self, name: &str) -> Result<bool> {
+        // Through a named tree the guard's index list is empty and is thrown away on drop, so
+        // the main tree would go on maintaining an index whose pages are gone.
+        if !self.open_trees.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "secondary indexes can only be dropped on the main tree",
+            ));
+        }
+        self.indexes.retain(|index| index.name != name);
+        if self.reader().catalog_entry(name, KIND_INDEX)?.is_none() {
+            return Ok(false);
+        }
+        self.autocommit(|tree| {
+            tree.on_index(name, |tree| tree.drop_subtree(tree.root_page as usize))?;
+            tree.remove_catalog_entry(name, KIND_INDEX)
+        })?;
+        Ok(true)
+    }
+
+    // Records whose indexed key equals `secondary_key`, in primary key order.
+    pub fn lookup_by(
+        &self,
+        index: &str,
+        secondary_key: i32,
+    ) -> Result<Vec<(i32, [u8; DATA_SIZE])>> {
+        self.reader().lookup_by(index, secondary_key)
+    }
+
+    // Checks that the index holds exactly one entry per record, under the key its extractor
+    // gives for the record's current value.
+    pub fn verify_index(&self, name: &str) -> Result<()> {
+        let index = self
+            .indexes
+            .iter()
+            .find(|index| index.name == name)
+            .ok_or_else(|| unknown_index(name))?;
+        let reader = self.reader();
+
+        let mut expected: Vec<(i32, i32)> = reader
+            .read_range_entries(i32::MIN, i32::MAX)?
+            .iter()
+            .map(|(key, data)| ((index.extract)(data), *key))
+            .collect();
+        let mut actual: Vec<(i32, i32)> = reader
+            .index_reader(name)?
+            .read_range_entries(i32::MIN, i32::MAX)?
+            .iter()
+            .map(|(secondary_key, value)| (*secondary_key, primary_key(value)))
+            .collect();
+        expected.sort_unstable();
+        actual.sort_unstable();
+
+        if let Some(i) =
+            (0..expected.len().max(actual.len())).find(|&i| expected.get(i) != actual.get(i))
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "index {name} is out of sync: expected {:?} at entry {i}, found {:?}",
+                    expected.get(i),
+                    actual.get(i)
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -1,39 +1,406 @@
 use bincode::{config, Decode, Encode};
-use memmap2::{MmapMut, MmapOptions};
-use std::fs::{File, OpenOptions};
-use std::io::Result;
+use catalog::KIND_TREE;
+use checksum::seal;
+use index::SecondaryIndex;
+use pager::{Pager, FLAG_FOLLOWER, FLAG_MULTIMAP};
+use std::io::{Error, ErrorKind, Result};
+use std::ops::{Bound, RangeBounds};
 use std::path::Path;
+use std::sync::{Arc, RwLock};
+use verify::corrupt;
+
+mod backup;
+mod catalog;
+mod changes;
+mod checksum;
+mod entry;
+mod export;
+#[cfg(any(test, feature = "fuzzing"))]
+#[doc(hidden)]
+pub mod fuzzing;
+mod index;
+mod links;
+mod observer;
+mod pager;
+mod posting;
+mod repair;
+mod replication;
+mod snapshot;
+mod stats;
+#[cfg(test)]
+mod tests;
+#[macro_use]
+mod trace;
+mod transaction;
+mod verify;
+
+pub use catalog::NamedTree;
+pub use changes::{Change, ChangeEvent, ChangeLog};
+pub use checksum::ChecksumPolicy;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use observer::Observer;
+pub use repair::{LostRange, RepairReport};
+pub use replication::Follower;
+pub use snapshot::Snapshot;
+pub use stats::{LevelStats, TreeStats};
+pub use transaction::{Savepoint, Transaction};
 
 const PAGE_SIZE: usize = 4096;
 const DATA_SIZE: usize = 100;
 const INDEX_FILE: &str = "bptree_index.dat";
 
-const LEAF_ORDER: usize = 36;
-const INTERNAL_ORDER: usize = 340;
+// Keys, children and subtree counts are varint-encoded at up to 5 bytes each, so 250 keys
+// keep a full internal node inside one page.
+const INTERNAL_ORDER: usize = 250;
+
+const KEY_SIZE: usize = 4;
+const SLOT_SIZE: usize = 2;
+// Set in a slot whose cell holds a posting-list descriptor instead of a value.
+const SLOT_POSTING: u16 = 0x8000;
+// Cell offsets stay below PAGE_SIZE, so the bits above them hold how many values past the
+// first a multimap cell keeps inline.
+const SLOT_OFFSET: u16 = 0x0fff;
+const SLOT_INLINE_SHIFT: u32 = 12;
+// A multimap key keeps up to this many values in its leaf cell before they move out to a
+// posting list.
+const INLINE_VALUES: usize = 4;
+
+const LEAF_PREFIX_LEN: usize = 1;
+const LEAF_NUM_SLOTS: usize = 2;
+const LEAF_HEAP_START: usize = 4;
+const LEAF_NEXT: usize = 8;
+const LEAF_PREV: usize = 12;
+const LEAF_PARENT: usize = 16;
+const LEAF_PREFIX: usize = 20;
+const LEAF_CHECKSUM: usize = 24;
+const LEAF_HEADER_SIZE: usize = 28;
+
+// Internal pages start with the type byte and a checksum; the bincode body follows.
+const INTERNAL_CHECKSUM: usize = 4;
+const INTERNAL_BODY: usize = 8;
+
+// Number of values a leaf is guaranteed to hold with no prefix compression, however they are
+// grouped into cells.
+const LEAF_ORDER: usize = (PAGE_SIZE - LEAF_HEADER_SIZE) / (SLOT_SIZE + KEY_SIZE + DATA_SIZE);
+
+// Fewest values a non-root leaf holds. Rebalancing moves whole cells of up to INLINE_VALUES
+// values, so a leaf that cannot borrow has a sibling with at most LEAF_MIN_WIDTH +
+// INLINE_VALUES - 1 values, and this floor keeps the two of them within LEAF_ORDER for the merge.
+const LEAF_MIN_WIDTH: usize = (LEAF_ORDER + 2 - INLINE_VALUES) / 2;
+
+// A leaf entry: key, stored values and whether the single value is a posting-list descriptor.
+type Cell = (i32, Vec<[u8; DATA_SIZE]>, bool);
+
+// Keys are stored big-endian with the sign bit flipped so byte order matches key order.
+fn key_bytes(key: i32) -> [u8; KEY_SIZE] {
+    ((key as u32) ^ 0x8000_0000).to_be_bytes()
+}
 
-#[derive(Debug, Clone, Copy, Encode, Decode)]
+fn key_from_bytes(bytes: [u8; KEY_SIZE]) -> i32 {
+    (u32::from_be_bytes(bytes) ^ 0x8000_0000) as i32
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+// Shortest key, by significant bytes, that is greater than `left` and no greater than `right`.
+fn separator_key(left: i32, right: i32) -> i32 {
+    let (l, r) = (key_bytes(left), key_bytes(right));
+    for n in 1..KEY_SIZE {
+        let mut candidate = [0u8; KEY_SIZE];
+        candidate[..n].copy_from_slice(&r[..n]);
+        if candidate > l {
+            return key_from_bytes(candidate);
+        }
+    }
+    right
+}
+
+fn inclusive_bounds(range: impl RangeBounds<i32>) -> Option<(i32, i32)> {
+    let start = match range.start_bound() {
+        Bound::Included(&key) => key,
+        Bound::Excluded(&key) => key.checked_add(1)?,
+        Bound::Unbounded => i32::MIN,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&key) => key,
+        Bound::Excluded(&key) => key.checked_sub(1)?,
+        Bound::Unbounded => i32::MAX,
+    };
+    (start <= end).then_some((start, end))
+}
+
+// Slotted leaf page: a header, a slot directory of cell offsets growing up from the header,
+// and a heap of cells growing down from the end of the page. Each cell holds the key bytes
+// after the page-wide prefix followed by its values, one unless they are inline duplicates.
+#[derive(Clone)]
 struct LeafNode {
-    is_leaf: bool,
-    num_keys: usize,
-    keys: [i32; LEAF_ORDER],
-    data: [[u8; DATA_SIZE]; LEAF_ORDER],
-    next_leaf: i32,
-    prev_leaf: i32,
-    parent: i32,
+    page: [u8; PAGE_SIZE],
 }
 
 impl LeafNode {
     fn new() -> Self {
-        LeafNode {
-            is_leaf: true,
-            num_keys: 0,
-            keys: [0; LEAF_ORDER],
-            data: [[0; DATA_SIZE]; LEAF_ORDER],
-            next_leaf: -1,
-            prev_leaf: -1,
-            parent: -1,
+        let mut leaf = LeafNode {
+            page: [0; PAGE_SIZE],
+        };
+        leaf.page[0] = 1;
+        leaf.set_u16(LEAF_HEAP_START, PAGE_SIZE as u16);
+        leaf.set_next_leaf(-1);
+        leaf.set_prev_leaf(-1);
+        leaf.set_parent(-1);
+        leaf
+    }
+
+    fn get_u16(&self, offset: usize) -> u16 {
+        u16::from_le_bytes([self.page[offset], self.page[offset + 1]])
+    }
+
+    fn set_u16(&mut self, offset: usize, value: u16) {
+        self.page[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn get_i32(&self, offset: usize) -> i32 {
+        i32::from_le_bytes(self.page[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn set_i32(&mut self, offset: usize, value: i32) {
+        self.page[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn num_keys(&self) -> usize {
+        self.get_u16(LEAF_NUM_SLOTS) as usize
+    }
+
+    fn next_leaf(&self) -> i32 {
+        self.get_i32(LEAF_NEXT)
+    }
+
+    fn set_next_leaf(&mut self, page: i32) {
+        self.set_i32(LEAF_NEXT, page);
+    }
+
+    fn prev_leaf(&self) -> i32 {
+        self.get_i32(LEAF_PREV)
+    }
+
+    fn set_prev_leaf(&mut self, page: i32) {
+        self.set_i32(LEAF_PREV, page);
+    }
+
+    fn parent(&self) -> i32 {
+        self.get_i32(LEAF_PARENT)
+    }
+
+    fn set_parent(&mut self, page: i32) {
+        self.set_i32(LEAF_PARENT, page);
+    }
+
+    fn prefix_len(&self) -> usize {
+        self.page[LEAF_PREFIX_LEN] as usize
+    }
+
+    fn prefix(&self) -> &[u8] {
+        &self.page[LEAF_PREFIX..LEAF_PREFIX + self.prefix_len()]
+    }
+
+    fn cell_size(&self, i: usize) -> usize {
+        KEY_SIZE - self.prefix_len() + self.inline_len(i) * DATA_SIZE
+    }
+
+    // Bytes taken by the slots and cells.
+    fn used(&self) -> usize {
+        (0..self.num_keys())
+            .map(|i| SLOT_SIZE + self.cell_size(i))
+            .sum()
+    }
+
+    // Values held in the leaf itself, which is what splits and rebalancing go by.
+    fn width(&self) -> usize {
+        (0..self.num_keys()).map(|i| self.inline_len(i)).sum()
+    }
+
+    fn cell_offset(&self, i: usize) -> usize {
+        (self.get_u16(LEAF_HEADER_SIZE + i * SLOT_SIZE) & SLOT_OFFSET) as usize
+    }
+
+    fn inline_len(&self, i: usize) -> usize {
+        let slot = self.get_u16(LEAF_HEADER_SIZE + i * SLOT_SIZE);
+        ((slot & !SLOT_POSTING) >> SLOT_INLINE_SHIFT) as usize + 1
+    }
+
+    fn is_posting(&self, i: usize) -> bool {
+        self.get_u16(LEAF_HEADER_SIZE + i * SLOT_SIZE) & SLOT_POSTING != 0
+    }
+
+    fn set_posting(&mut self, i: usize, posting: bool) {
+        let slot = LEAF_HEADER_SIZE + i * SLOT_SIZE;
+        let offset = self.get_u16(slot) & !SLOT_POSTING;
+        self.set_u16(
+            slot,
+            if posting {
+                offset | SLOT_POSTING
+            } else {
+                offset
+            },
+        );
+    }
+
+    fn key(&self, i: usize) -> i32 {
+        let prefix_len = self.prefix_len();
+        let cell = self.cell_offset(i);
+        let mut bytes = [0u8; KEY_SIZE];
+        bytes[..prefix_len].copy_from_slice(self.prefix());
+        bytes[prefix_len..].copy_from_slice(&self.page[cell..cell + KEY_SIZE - prefix_len]);
+        key_from_bytes(bytes)
+    }
+
+    fn value(&self, i: usize) -> [u8; DATA_SIZE] {
+        let start = self.cell_offset(i) + KEY_SIZE - self.prefix_len();
+        self.page[start..start + DATA_SIZE].try_into().unwrap()
+    }
+
+    fn set_value(&mut self, i: usize, data: &[u8; DATA_SIZE]) {
+        self.set_inline_value(i, 0, data);
+    }
+
+    fn values(&self, i: usize) -> Vec<[u8; DATA_SIZE]> {
+        let start = self.cell_offset(i) + KEY_SIZE - self.prefix_len();
+        (0..self.inline_len(i))
+            .map(|j| {
+                let value = start + j * DATA_SIZE;
+                self.page[value..value + DATA_SIZE].try_into().unwrap()
+            })
+            .collect()
+    }
+
+    fn set_inline_value(&mut self, i: usize, j: usize, data: &[u8; DATA_SIZE]) {
+        let start = self.cell_offset(i) + KEY_SIZE - self.prefix_len() + j * DATA_SIZE;
+        self.page[start..start + DATA_SIZE].copy_from_slice(data);
+    }
+
+    fn search(&self, key: i32) -> std::result::Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.num_keys());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.key(mid).cmp(&key) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    fn cell(&self, i: usize) -> Cell {
+        (self.key(i), self.values(i), self.is_posting(i))
+    }
+
+    fn entries(&self) -> Vec<Cell> {
+        (0..self.num_keys()).map(|i| self.cell(i)).collect()
+    }
+
+    fn shared_prefix_len(entries: &[Cell]) -> usize {
+        match (entries.first(), entries.last()) {
+            (Some(first), Some(last)) => common_prefix(&key_bytes(first.0), &key_bytes(last.0)),
+            _ => 0,
         }
     }
+
+    fn fits(entries: &[Cell]) -> bool {
+        let prefix_len = Self::shared_prefix_len(entries);
+        let cells: usize = entries
+            .iter()
+            .map(|(_, values, _)| SLOT_SIZE + KEY_SIZE - prefix_len + values.len() * DATA_SIZE)
+            .sum();
+        LEAF_HEADER_SIZE + cells <= PAGE_SIZE
+    }
+
+    fn rebuild(&mut self, entries: &[Cell]) {
+        let prefix_len = Self::shared_prefix_len(entries);
+        self.page[LEAF_HEADER_SIZE..].fill(0);
+        self.page[LEAF_PREFIX..LEAF_PREFIX + KEY_SIZE].fill(0);
+        if let Some(first) = entries.first() {
+            self.page[LEAF_PREFIX..LEAF_PREFIX + prefix_len]
+                .copy_from_slice(&key_bytes(first.0)[..prefix_len]);
+        }
+        self.page[LEAF_PREFIX_LEN] = prefix_len as u8;
+        self.set_u16(LEAF_NUM_SLOTS, 0);
+        self.set_u16(LEAF_HEAP_START, PAGE_SIZE as u16);
+        for (i, cell) in entries.iter().enumerate() {
+            self.push_cell(i, cell);
+        }
+    }
+
+    fn push_cell(&mut self, pos: usize, &(key, ref values, posting): &Cell) {
+        let n = self.num_keys();
+        let suffix_len = KEY_SIZE - self.prefix_len();
+        let cell = self.get_u16(LEAF_HEAP_START) as usize - suffix_len - values.len() * DATA_SIZE;
+        self.page[cell..cell + suffix_len]
+            .copy_from_slice(&key_bytes(key)[KEY_SIZE - suffix_len..]);
+        for (j, data) in values.iter().enumerate() {
+            let value = cell + suffix_len + j * DATA_SIZE;
+            self.page[value..value + DATA_SIZE].copy_from_slice(data);
+        }
+        let slots = LEAF_HEADER_SIZE + pos * SLOT_SIZE;
+        self.page
+            .copy_within(slots..LEAF_HEADER_SIZE + n * SLOT_SIZE, slots + SLOT_SIZE);
+        self.set_u16(
+            slots,
+            cell as u16 | ((values.len() - 1) as u16) << SLOT_INLINE_SHIFT,
+        );
+        self.set_posting(pos, posting);
+        self.set_u16(LEAF_HEAP_START, cell as u16);
+        self.set_u16(LEAF_NUM_SLOTS, (n + 1) as u16);
+    }
+
+    fn insert_at(&mut self, pos: usize, cell: Cell) -> bool {
+        let n = self.num_keys();
+        if key_bytes(cell.0)[..self.prefix_len()] != *self.prefix() {
+            let mut entries = self.entries();
+            entries.insert(pos, cell);
+            if !Self::fits(&entries) {
+                return false;
+            }
+            self.rebuild(&entries);
+            return true;
+        }
+
+        let needed = SLOT_SIZE + KEY_SIZE - self.prefix_len() + cell.1.len() * DATA_SIZE;
+        let slots_end = LEAF_HEADER_SIZE + n * SLOT_SIZE;
+        if self.get_u16(LEAF_HEAP_START) as usize - slots_end < needed {
+            if LEAF_HEADER_SIZE + self.used() + needed > PAGE_SIZE {
+                return false;
+            }
+            let entries = self.entries();
+            self.rebuild(&entries);
+            if key_bytes(cell.0)[..self.prefix_len()] != *self.prefix() {
+                return self.insert_at(pos, cell);
+            }
+        }
+        self.push_cell(pos, &cell);
+        true
+    }
+
+    fn remove_at(&mut self, pos: usize) {
+        let n = self.num_keys();
+        let slots = LEAF_HEADER_SIZE + pos * SLOT_SIZE;
+        self.page
+            .copy_within(slots + SLOT_SIZE..LEAF_HEADER_SIZE + n * SLOT_SIZE, slots);
+        self.set_u16(LEAF_NUM_SLOTS, (n - 1) as u16);
+    }
+
+    // Swaps the cell at `pos` for `cell`, which has the same key. A larger cell that does not
+    // fit leaves the leaf as it was; a cell no larger than the old one always fits.
+    fn replace_at(&mut self, pos: usize, cell: Cell) -> bool {
+        let mut replaced = self.clone();
+        replaced.remove_at(pos);
+        if !replaced.insert_at(pos, cell) {
+            return false;
+        }
+        *self = replaced;
+        true
+    }
 }
 
 #[derive(Debug, Clone, Copy, Encode, Decode)]
@@ -42,6 +409,7 @@ struct InternalNode {
     num_keys: usize,
     keys: [i32; INTERNAL_ORDER + 1],
     children: [i32; INTERNAL_ORDER + 2],
+    counts: [u64; INTERNAL_ORDER + 2],
     parent: i32,
 }
 
@@ -52,247 +420,569 @@ impl InternalNode {
             num_keys: 0,
             keys: [0; INTERNAL_ORDER + 1],
             children: [-1; INTERNAL_ORDER + 2],
+            counts: [0; INTERNAL_ORDER + 2],
             parent: -1,
         }
     }
+
+    fn child_index(&self, key: i32) -> usize {
+        let mut i = 0;
+        while i < self.num_keys && key >= self.keys[i] {
+            i += 1;
+        }
+        i
+    }
+
+    fn child_position(&self, child: usize) -> usize {
+        (0..=self.num_keys)
+            .find(|&i| self.children[i] == child as i32)
+            .unwrap()
+    }
+
+    fn total(&self) -> u64 {
+        self.counts[..=self.num_keys].iter().sum()
+    }
 }
 
-pub struct BPlusTree {
-    file: File,
-    mmap: MmapMut,
+struct TreeReader<'a> {
+    pager: &'a RwLock<Pager>,
+    snapshot: Option<u64>,
     root_page: i32,
-    num_pages: usize,
 }
 
-impl BPlusTree {
-    pub fn new() -> Result<Self> {
-        let path = Path::new(INDEX_FILE);
-        let exists = path.exists();
+impl TreeReader<'_> {
+    // A page number past the end of the file reads as not a leaf, so the node read that
+    // follows reports it. Only that read counts toward `page_reads`.
+    fn is_leaf_page(&self, page_num: usize) -> bool {
+        let pager = self.pager.read().unwrap();
+        page_num < pager.num_pages() && pager.peek_page(page_num, self.snapshot)[0] == 1
+    }
 
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(path)?;
+    fn read_page(&self, page_num: usize) -> Result<[u8; PAGE_SIZE]> {
+        let pager = self.pager.read().unwrap();
+        if page_num == 0 || page_num >= pager.num_pages() {
+            return Err(corrupt(page_num, "page is not in the file"));
+        }
+        Ok(pager.get_page(page_num, self.snapshot).try_into().unwrap())
+    }
 
-        let file_len = file.metadata()?.len();
+    fn read_leaf_node(&self, page_num: usize) -> Result<LeafNode> {
+        let page = self.read_page(page_num)?;
+        self.verify_page(page_num, &page, LEAF_CHECKSUM)?;
+        let leaf = LeafNode { page };
+        if page[0] != 1 || !leaf.is_in_bounds() {
+            return Err(corrupt(page_num, "malformed leaf"));
+        }
+        Ok(leaf)
+    }
 
-        let (num_pages, root_page) = if exists && file_len > 0 {
-            ((file_len as usize) / PAGE_SIZE, 0)
+    fn read_internal_node(&self, page_num: usize) -> Result<InternalNode> {
+        let page = self.read_page(page_num)?;
+        self.verify_page(page_num, &page, INTERNAL_CHECKSUM)?;
+        let (node, _): (InternalNode, _) =
+            bincode::decode_from_slice(&page[INTERNAL_BODY..], config::standard())
+                .map_err(|e| corrupt(page_num, e))?;
+        if page[0] != 0 || node.num_keys > INTERNAL_ORDER {
+            return Err(corrupt(page_num, "malformed internal node"));
+        }
+        Ok(node)
+    }
+
+    fn num_pages(&self) -> usize {
+        self.pager.read().unwrap().num_pages()
+    }
+
+    // Goes down from the root to a leaf, taking the child `step` picks at each internal node.
+    // No tree is deeper than the file has pages, so a damaged file whose child pointers lead
+    // back up stops there with an error.
+    fn descend(&self, mut step: impl FnMut(&InternalNode) -> usize) -> Result<usize> {
+        let mut page = self.root_page as usize;
+        for _ in 0..self.num_pages() {
+            if self.is_leaf_page(page) {
+                return Ok(page);
+            }
+            let node = self.read_internal_node(page)?;
+            page = node.children[step(&node)] as usize;
+        }
+        Err(corrupt(page, "child pointers loop without reaching a leaf"))
+    }
+
+    // The leaf after `page` in the chain, if any. `steps` counts the links followed so far; past
+    // one per page in the file the chain has to be going round in a loop.
+    fn next_in_chain(&self, page: usize, link: i32, steps: &mut usize) -> Result<Option<usize>> {
+        *steps += 1;
+        if *steps > self.num_pages() {
+            return Err(corrupt(page, "leaf chain loops"));
+        }
+        Ok((link != -1).then_some(link as usize))
+    }
+
+    fn find_leaf(&self, key: i32) -> Result<usize> {
+        self.descend(|node| node.child_index(key))
+    }
+
+    fn subtree_count(&self, page_num: usize) -> Result<u64> {
+        if self.is_leaf_page(page_num) {
+            Ok(self.read_leaf_node(page_num)?.num_keys() as u64)
         } else {
-            file.set_len(PAGE_SIZE as u64)?;
-            (1, 0)
+            Ok(self.read_internal_node(page_num)?.total())
+        }
+    }
+
+    // Number of keys below `key` (or up to and including it), summing the counts of the
+    // children skipped on the way down.
+    fn count_below(&self, key: i32, inclusive: bool) -> Result<usize> {
+        let mut count = 0u64;
+        let page = self.descend(|node| {
+            let idx = node.child_index(key);
+            count =
+                count.wrapping_add(node.counts[..idx].iter().fold(0, |a, &c| a.wrapping_add(c)));
+            idx
+        })?;
+        let leaf = self.read_leaf_node(page)?;
+        let pos = match leaf.search(key) {
+            Ok(i) if inclusive => i + 1,
+            Ok(i) | Err(i) => i,
         };
+        Ok(count as usize + pos)
+    }
 
-        let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+    fn nth(&self, index: usize) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let mut index = index as u64;
+        let page = self.descend(|node| {
+            let mut i = 0;
+            while i < node.num_keys && index >= node.counts[i] {
+                index -= node.counts[i];
+                i += 1;
+            }
+            i
+        })?;
+        let leaf = self.read_leaf_node(page)?;
+        let i = index as usize;
+        if i >= leaf.num_keys() {
+            return Ok(None);
+        }
+        Ok(Some((leaf.key(i), self.value_at(&leaf, i)?)))
+    }
 
-        let mut tree = BPlusTree {
-            file,
-            mmap,
-            root_page,
-            num_pages,
-        };
+    fn read(&self, key: i32) -> Result<Option<[u8; DATA_SIZE]>> {
+        let leaf_page = self.find_leaf(key)?;
+        let leaf = self.read_leaf_node(leaf_page)?;
+        leaf.search(key)
+            .ok()
+            .map(|i| self.value_at(&leaf, i))
+            .transpose()
+    }
 
-        if !exists || file_len == 0 {
-            let root = LeafNode::new();
-            tree.write_leaf_node(root_page as usize, &root)?;
+    fn edge_leaf(&self, rightmost: bool) -> Result<usize> {
+        self.descend(|node| if rightmost { node.num_keys } else { 0 })
+    }
+
+    // The first entry at or after slot `pos` of `page`, following next links past the leaf end.
+    fn seek_forward(&self, mut page: usize, mut pos: usize) -> Result<Option<(usize, usize)>> {
+        let mut steps = 0;
+        loop {
+            let leaf = self.read_leaf_node(page)?;
+            if pos < leaf.num_keys() {
+                return Ok(Some((page, pos)));
+            }
+            match self.next_in_chain(page, leaf.next_leaf(), &mut steps)? {
+                Some(next) => page = next,
+                None => return Ok(None),
+            }
+            pos = 0;
         }
+    }
 
-        Ok(tree)
+    // The last entry before slot `pos` of `page`, following prev links past the leaf start.
+    fn seek_backward(&self, mut page: usize, mut pos: usize) -> Result<Option<(usize, usize)>> {
+        let mut steps = 0;
+        loop {
+            if pos > 0 {
+                return Ok(Some((page, pos - 1)));
+            }
+            let prev = self.read_leaf_node(page)?.prev_leaf();
+            match self.next_in_chain(page, prev, &mut steps)? {
+                Some(prev) => page = prev,
+                None => return Ok(None),
+            }
+            pos = self.read_leaf_node(page)?.num_keys();
+        }
     }
 
-    pub fn flush(&mut self) -> Result<()> {
-        self.mmap.flush()
+    fn first(&self) -> Result<Option<(usize, usize)>> {
+        self.seek_forward(self.edge_leaf(false)?, 0)
     }
 
-    pub fn read_range_data(&self, start_key: i32, end_key: i32) -> Vec<[u8; DATA_SIZE]> {
+    fn last(&self) -> Result<Option<(usize, usize)>> {
+        let page = self.edge_leaf(true)?;
+        self.seek_backward(page, self.read_leaf_node(page)?.num_keys())
+    }
+
+    // Slot where entries `>= key` (or `> key` when `after` is set) begin in the key's leaf.
+    fn bound(&self, key: i32, after: bool) -> Result<(usize, usize)> {
+        let page = self.find_leaf(key)?;
+        let pos = match self.read_leaf_node(page)?.search(key) {
+            Ok(i) if after => i + 1,
+            Ok(i) | Err(i) => i,
+        };
+        Ok((page, pos))
+    }
+
+    fn entry_at(&self, position: Option<(usize, usize)>) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let Some((page, pos)) = position else {
+            return Ok(None);
+        };
+        let leaf = self.read_leaf_node(page)?;
+        Ok(Some((leaf.key(pos), self.value_at(&leaf, pos)?)))
+    }
+
+    fn read_range_data(&self, start_key: i32, end_key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        Ok(self
+            .read_range_entries(start_key, end_key)?
+            .into_iter()
+            .map(|(_, data)| data)
+            .collect())
+    }
+
+    fn read_range_entries(
+        &self,
+        start_key: i32,
+        end_key: i32,
+    ) -> Result<Vec<(i32, [u8; DATA_SIZE])>> {
         let mut result = Vec::new();
-        let mut page = self.find_leaf(start_key);
+        let mut page = self.find_leaf(start_key)?;
+        let mut steps = 0;
 
         loop {
-            let leaf = self.read_leaf_node(page);
-            for i in 0..leaf.num_keys {
-                let key = leaf.keys[i];
+            let leaf = self.read_leaf_node(page)?;
+            for i in 0..leaf.num_keys() {
+                let key = leaf.key(i);
                 if key >= start_key && key <= end_key {
-                    result.push(leaf.data[i]);
+                    result.extend(
+                        self.values_at(&leaf, i)?
+                            .into_iter()
+                            .map(|data| (key, data)),
+                    );
                 }
                 if key > end_key {
-                    return result;
+                    return Ok(result);
                 }
             }
-            if leaf.next_leaf == -1 {
-                break;
+            match self.next_in_chain(page, leaf.next_leaf(), &mut steps)? {
+                Some(next) => page = next,
+                None => return Ok(result),
             }
-            page = leaf.next_leaf as usize;
         }
+    }
+}
 
+enum Update {
+    Keep,
+    Put([u8; DATA_SIZE]),
+    Remove,
+}
+
+pub struct BPlusTree {
+    pager: Arc<RwLock<Pager>>,
+    root_page: i32,
+    multimap: bool,
+    indexes: Vec<SecondaryIndex>,
+    // The named trees from the catalog this handle is switched to, outermost first. The last
+    // one is the tree reads and writes go to; empty means the main tree.
+    open_trees: Vec<String>,
+}
+
+impl BPlusTree {
+    pub fn new() -> Result<Self> {
+        Self::open(INDEX_FILE)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with_flags(path.as_ref(), 0)
+    }
+
+    // Opens an index that keeps every value written for a key. The mode is fixed when the
+    // file is created; opening an existing unique-key index this way fails.
+    pub fn open_multimap<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let tree = Self::open_with_flags(path.as_ref(), FLAG_MULTIMAP)?;
+        if !tree.multimap {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "index was not created in multimap mode",
+            ));
+        }
+        Ok(tree)
+    }
+
+    // Deletes an index file with its write-ahead log and change log, for starting over at a
+    // path. Files that are already gone are skipped.
+    pub fn remove_files<P: AsRef<Path>>(path: P) -> Result<()> {
+        pager::remove_files(path.as_ref())
+    }
+
+    // A follower's file only opens through `Follower`, which asks for FLAG_FOLLOWER.
+    pub(crate) fn open_with_flags(path: &Path, flags: u32) -> Result<Self> {
+        let mut pager = Pager::open(path)?;
+        let root_page = pager.root_page;
+        if root_page == -1 {
+            pager.set_flags(flags);
+        }
+        if pager.flags & FLAG_FOLLOWER != 0 && flags & FLAG_FOLLOWER == 0 {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "index is a read-only follower; promote it before writing",
+            ));
+        }
+        let multimap = pager.flags & FLAG_MULTIMAP != 0;
+
+        let mut tree = BPlusTree {
+            pager: Arc::new(RwLock::new(pager)),
+            root_page,
+            multimap,
+            indexes: Vec::new(),
+            open_trees: Vec::new(),
+        };
+
+        if root_page == -1 {
+            let page = tree.allocate_page()?;
+            tree.write_leaf_node(page, &LeafNode::new())?;
+            tree.root_page = page as i32;
+            tree.create_catalog()?;
+            tree.commit()?;
+        }
+
+        Ok(tree)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.pager.read().unwrap().flush()
+    }
+
+    pub fn begin(&mut self) -> Result<Transaction<'_>> {
+        Ok(Transaction::new(self))
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        let result = self
+            .save_root()
+            .and_then(|root_page| self.pager.write().unwrap().commit(root_page));
+        if result.is_err() {
+            self.rollback();
+        }
         result
     }
 
-    fn ensure_file_size(&mut self, pages: usize) -> Result<()> {
-        let required_size = pages * PAGE_SIZE;
-        let current_size = self.mmap.len();
+    // The root to record in the meta page. A named tree keeps its own root in the catalog and
+    // leaves the main tree's committed root as it is.
+    fn save_root(&mut self) -> Result<i32> {
+        match self.open_trees.last().cloned() {
+            None => Ok(self.root_page),
+            Some(name) => {
+                self.set_catalog_root(&name, KIND_TREE, self.root_page)?;
+                Ok(self.pager.read().unwrap().root_page)
+            }
+        }
+    }
 
-        if required_size > current_size {
-            self.mmap.flush()?;
-            drop(std::mem::replace(&mut self.mmap, unsafe {
-                MmapOptions::new().len(0).map_mut(&self.file)?
-            }));
-            self.file.set_len(required_size as u64)?;
-            self.mmap = unsafe { MmapOptions::new().map_mut(&self.file)? };
-            self.num_pages = pages;
+    fn rollback(&mut self) {
+        let root_page = self.pager.write().unwrap().rollback();
+        self.root_page = match self.open_trees.last() {
+            None => root_page,
+            // A catalog that cannot be read leaves no root, so the next read fails.
+            Some(name) => self
+                .reader()
+                .catalog_entry(name, KIND_TREE)
+                .map_or(-1, |entry| entry.map_or(-1, |entry| entry.root)),
+        };
+    }
+
+    fn autocommit<R>(&mut self, op: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        match op(self) {
+            Ok(result) => {
+                self.commit()?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.rollback();
+                Err(e)
+            }
         }
+    }
 
-        Ok(())
+    pub fn snapshot(&self) -> Snapshot {
+        let mut pager = self.pager.write().unwrap();
+        let mut snapshot = Snapshot {
+            pager: Arc::clone(&self.pager),
+            id: pager.register_snapshot(),
+            root_page: pager.root_page,
+        };
+        drop(pager);
+        if let Some(name) = self.open_trees.last() {
+            snapshot.root_page = match snapshot.reader().catalog_entry(name, KIND_TREE) {
+                Ok(Some(entry)) => entry.root,
+                // Reads through the snapshot report the damage.
+                _ => -1,
+            };
+        }
+        snapshot
     }
 
-    fn allocate_page(&mut self) -> Result<usize> {
-        let page_num = self.num_pages;
-        self.num_pages += 1;
-        self.ensure_file_size(self.num_pages)?;
-        let start = page_num * PAGE_SIZE;
-        let end = start + PAGE_SIZE;
-        self.mmap[start..end].fill(0);
-        Ok(page_num)
+    pub fn read_range_data(&self, start_key: i32, end_key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        traced!("read_range_data", self.reader(), start_key, end_key = end_key;
+            self.reader().read_range_data(start_key, end_key))
     }
 
-    fn get_page(&self, page_num: usize) -> &[u8] {
-        let start = page_num * PAGE_SIZE;
-        let end = start + PAGE_SIZE;
-        &self.mmap[start..end]
+    fn reader(&self) -> TreeReader<'_> {
+        TreeReader {
+            pager: &self.pager,
+            snapshot: None,
+            root_page: self.root_page,
+        }
     }
 
-    fn get_page_mut(&mut self, page_num: usize) -> &mut [u8] {
-        let start = page_num * PAGE_SIZE;
-        let end = start + PAGE_SIZE;
-        &mut self.mmap[start..end]
+    fn allocate_page(&mut self) -> Result<usize> {
+        self.pager.write().unwrap().allocate_page()
     }
 
     fn is_leaf_page(&self, page_num: usize) -> bool {
-        self.get_page(page_num)[0] == 1
+        self.reader().is_leaf_page(page_num)
     }
 
-    fn read_leaf_node(&self, page_num: usize) -> LeafNode {
-        let bytes = &self.get_page(page_num)[..std::mem::size_of::<LeafNode>()];
-        bincode::decode_from_slice(bytes, config::standard())
-            .unwrap()
-            .0
+    fn read_leaf_node(&self, page_num: usize) -> Result<LeafNode> {
+        self.reader().read_leaf_node(page_num)
     }
 
     fn write_leaf_node(&mut self, page_num: usize, node: &LeafNode) -> Result<()> {
-        let bytes = bincode::encode_to_vec(node, config::standard()).unwrap();
-        self.get_page_mut(page_num)[..bytes.len()].copy_from_slice(&bytes);
-        Ok(())
+        let mut page = node.page;
+        seal(&mut page, LEAF_CHECKSUM);
+        self.pager.write().unwrap().write_page(page_num, &page)
     }
 
-    fn read_internal_node(&self, page_num: usize) -> InternalNode {
-        let bytes = &self.get_page(page_num)[..std::mem::size_of::<InternalNode>()];
-        bincode::decode_from_slice(bytes, config::standard())
-            .unwrap()
-            .0
+    fn read_internal_node(&self, page_num: usize) -> Result<InternalNode> {
+        self.reader().read_internal_node(page_num)
     }
 
     fn write_internal_node(&mut self, page_num: usize, node: &InternalNode) -> Result<()> {
-        let bytes = bincode::encode_to_vec(node, config::standard()).unwrap();
-        self.get_page_mut(page_num)[..bytes.len()].copy_from_slice(&bytes);
+        let mut page = [0u8; PAGE_SIZE];
+        bincode::encode_into_slice(node, &mut page[INTERNAL_BODY..], config::standard()).unwrap();
+        seal(&mut page, INTERNAL_CHECKSUM);
+        self.pager.write().unwrap().write_page(page_num, &page)
+    }
+
+    fn find_leaf(&self, key: i32) -> Result<usize> {
+        self.reader().find_leaf(key)
+    }
+
+    fn parent_of(&self, page_num: usize) -> Result<i32> {
+        if self.is_leaf_page(page_num) {
+            Ok(self.read_leaf_node(page_num)?.parent())
+        } else {
+            Ok(self.read_internal_node(page_num)?.parent)
+        }
+    }
+
+    fn subtree_count(&self, page_num: usize) -> Result<u64> {
+        self.reader().subtree_count(page_num)
+    }
+
+    // Adds `delta` to the count every ancestor keeps for the subtree holding `page_num`.
+    fn adjust_counts(&mut self, page_num: usize, delta: i64) -> Result<()> {
+        let mut child = page_num;
+        let mut parent = self.parent_of(child)?;
+        while parent != -1 {
+            let mut node = self.read_internal_node(parent as usize)?;
+            let idx = node.child_position(child);
+            node.counts[idx] = node.counts[idx].wrapping_add_signed(delta);
+            self.write_internal_node(parent as usize, &node)?;
+            child = parent as usize;
+            parent = node.parent;
+        }
         Ok(())
     }
 
-    fn find_leaf(&self, key: i32) -> usize {
-        let mut page = self.root_page as usize;
-        loop {
-            if self.is_leaf_page(page) {
-                return page;
-            }
-            let node = self.read_internal_node(page);
-            let mut i = 0;
-            while i < node.num_keys && key >= node.keys[i] {
-                i += 1;
-            }
-            page = node.children[i] as usize;
+    fn set_prev_link(&mut self, page_num: i32, prev: usize) -> Result<()> {
+        if page_num == -1 {
+            return Ok(());
+        }
+        let mut leaf = self.read_leaf_node(page_num as usize)?;
+        leaf.set_prev_leaf(prev as i32);
+        self.write_leaf_node(page_num as usize, &leaf)
+    }
+
+    fn set_parent(&mut self, page_num: usize, parent: i32) -> Result<()> {
+        if self.is_leaf_page(page_num) {
+            let mut leaf = self.read_leaf_node(page_num)?;
+            leaf.set_parent(parent);
+            self.write_leaf_node(page_num, &leaf)
+        } else {
+            let mut node = self.read_internal_node(page_num)?;
+            node.parent = parent;
+            self.write_internal_node(page_num, &node)
         }
     }
 
     fn insert_into_leaf(
         &mut self,
         leaf_page: usize,
+        mut leaf: LeafNode,
+        pos: usize,
         key: i32,
         data: &[u8; DATA_SIZE],
     ) -> Result<Option<(i32, usize)>> {
-        let mut leaf = self.read_leaf_node(leaf_page);
-
-        for i in 0..leaf.num_keys {
-            if leaf.keys[i] == key {
-                leaf.data[i].copy_from_slice(data);
-                self.write_leaf_node(leaf_page, &leaf)?;
-                return Ok(None);
-            }
-        }
-
-        let mut pos = 0;
-        while pos < leaf.num_keys && leaf.keys[pos] < key {
-            pos += 1;
-        }
-
-        if leaf.num_keys < LEAF_ORDER {
-            for i in (pos..leaf.num_keys).rev() {
-                leaf.keys[i + 1] = leaf.keys[i];
-                leaf.data[i + 1] = leaf.data[i];
-            }
-            leaf.keys[pos] = key;
-            leaf.data[pos] = *data;
-            leaf.num_keys += 1;
+        if leaf.insert_at(pos, (key, vec![*data], false)) {
             self.write_leaf_node(leaf_page, &leaf)?;
             return Ok(None);
         }
 
+        let mut entries = leaf.entries();
+        entries.insert(pos, (key, vec![*data], false));
+        self.split_leaf(leaf_page, leaf, &entries).map(Some)
+    }
+
+    // Spreads `entries`, which no longer fit in `leaf`, over it and a new right sibling, and
+    // returns the separator and page to insert into the parent.
+    fn split_leaf(
+        &mut self,
+        leaf_page: usize,
+        mut leaf: LeafNode,
+        entries: &[Cell],
+    ) -> Result<(i32, usize)> {
         let new_page = self.allocate_page()?;
+        self.notify(|o| o.on_split(leaf_page, new_page));
         let mut new_leaf = LeafNode::new();
-        let mid = (LEAF_ORDER + 1) / 2;
 
-        let mut keys = vec![0; LEAF_ORDER + 1];
-        let mut datas = vec![[0u8; DATA_SIZE]; LEAF_ORDER + 1];
-        for i in 0..LEAF_ORDER {
-            keys[i] = leaf.keys[i];
-            datas[i] = leaf.data[i];
-        }
-
-        for i in (pos..LEAF_ORDER).rev() {
-            keys[i + 1] = keys[i];
-            datas[i + 1] = datas[i];
-        }
-        keys[pos] = key;
-        datas[pos] = *data;
-
-        leaf.num_keys = mid;
-        for i in 0..mid {
-            leaf.keys[i] = keys[i];
-            leaf.data[i] = datas[i];
-        }
-
-        new_leaf.num_keys = LEAF_ORDER + 1 - mid;
-        for i in 0..new_leaf.num_keys {
-            new_leaf.keys[i] = keys[mid + i];
-            new_leaf.data[i] = datas[mid + i];
-        }
-
-        new_leaf.next_leaf = leaf.next_leaf;
-        new_leaf.prev_leaf = leaf_page as i32;
-        leaf.next_leaf = new_page as i32;
-        new_leaf.parent = leaf.parent;
+        // Halved by values rather than keys, at the cell boundary that leaves the smaller side
+        // the most values, so inline duplicate sets end up split evenly.
+        let width: usize = entries.iter().map(|(_, values, _)| values.len()).sum();
+        let mut seen = 0;
+        let (mid, _) = entries[..entries.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(i, (_, values, _))| {
+                seen += values.len();
+                (i + 1, seen.min(width - seen))
+            })
+            .max_by_key(|&(_, smaller)| smaller)
+            .unwrap();
+
+        leaf.rebuild(&entries[..mid]);
+        new_leaf.rebuild(&entries[mid..]);
+
+        new_leaf.set_next_leaf(leaf.next_leaf());
+        new_leaf.set_prev_leaf(leaf_page as i32);
+        leaf.set_next_leaf(new_page as i32);
+        new_leaf.set_parent(leaf.parent());
 
         self.write_leaf_node(leaf_page, &leaf)?;
         self.write_leaf_node(new_page, &new_leaf)?;
+        self.set_prev_link(new_leaf.next_leaf(), new_page)?;
 
-        Ok(Some((new_leaf.keys[0], new_page)))
+        Ok((separator_key(entries[mid - 1].0, entries[mid].0), new_page))
     }
 
     fn insert_into_parent(&mut self, left_page: usize, key: i32, right_page: usize) -> Result<()> {
-        let left_node_parent = if self.is_leaf_page(left_page) {
-            self.read_leaf_node(left_page).parent
-        } else {
-            self.read_internal_node(left_page).parent
-        };
+        let left_node_parent = self.parent_of(left_page)?;
 
         if left_node_parent == -1 {
             let new_root_page = self.allocate_page()?;
@@ -300,31 +990,34 @@ impl BPlusTree {
             root.keys[0] = key;
             root.children[0] = left_page as i32;
             root.children[1] = right_page as i32;
+            root.counts[0] = self.subtree_count(left_page)?;
+            root.counts[1] = self.subtree_count(right_page)?;
             root.num_keys = 1;
             self.write_internal_node(new_root_page, &root)?;
 
             if self.is_leaf_page(left_page) {
-                let mut ln = self.read_leaf_node(left_page);
-                ln.parent = new_root_page as i32;
+                let mut ln = self.read_leaf_node(left_page)?;
+                ln.set_parent(new_root_page as i32);
                 self.write_leaf_node(left_page, &ln)?;
-                let mut rn = self.read_leaf_node(right_page);
-                rn.parent = new_root_page as i32;
+                let mut rn = self.read_leaf_node(right_page)?;
+                rn.set_parent(new_root_page as i32);
                 self.write_leaf_node(right_page, &rn)?;
             } else {
-                let mut ln = self.read_internal_node(left_page);
+                let mut ln = self.read_internal_node(left_page)?;
                 ln.parent = new_root_page as i32;
                 self.write_internal_node(left_page, &ln)?;
-                let mut rn = self.read_internal_node(right_page);
+                let mut rn = self.read_internal_node(right_page)?;
                 rn.parent = new_root_page as i32;
                 self.write_internal_node(right_page, &rn)?;
             }
 
+            self.notify(|o| o.on_root_change(self.root_page, new_root_page as i32));
             self.root_page = new_root_page as i32;
             return Ok(());
         }
 
         let parent_page = left_node_parent as usize;
-        let mut parent = self.read_internal_node(parent_page);
+        let mut parent = self.read_internal_node(parent_page)?;
 
         let mut pos = 0;
         while pos < parent.num_keys && parent.keys[pos] < key {
@@ -333,19 +1026,22 @@ impl BPlusTree {
         for i in (pos..parent.num_keys).rev() {
             parent.keys[i + 1] = parent.keys[i];
             parent.children[i + 2] = parent.children[i + 1];
+            parent.counts[i + 2] = parent.counts[i + 1];
         }
         parent.keys[pos] = key;
         parent.children[pos + 1] = right_page as i32;
+        parent.counts[pos] = self.subtree_count(left_page)?;
+        parent.counts[pos + 1] = self.subtree_count(right_page)?;
         parent.num_keys += 1;
 
         if parent.num_keys <= INTERNAL_ORDER {
             self.write_internal_node(parent_page, &parent)?;
             if self.is_leaf_page(right_page) {
-                let mut rn = self.read_leaf_node(right_page);
-                rn.parent = parent_page as i32;
+                let mut rn = self.read_leaf_node(right_page)?;
+                rn.set_parent(parent_page as i32);
                 self.write_leaf_node(right_page, &rn)?;
             } else {
-                let mut rn = self.read_internal_node(right_page);
+                let mut rn = self.read_internal_node(right_page)?;
                 rn.parent = parent_page as i32;
                 self.write_internal_node(right_page, &rn)?;
             }
@@ -353,6 +1049,7 @@ impl BPlusTree {
         }
 
         let new_page = self.allocate_page()?;
+        self.notify(|o| o.on_split(parent_page, new_page));
         let mut new_internal = InternalNode::new();
         let mid = parent.num_keys / 2;
         let promote = parent.keys[mid];
@@ -363,6 +1060,7 @@ impl BPlusTree {
         }
         for i in 0..=right_count {
             new_internal.children[i] = parent.children[mid + 1 + i];
+            new_internal.counts[i] = parent.counts[mid + 1 + i];
         }
         new_internal.num_keys = right_count;
         new_internal.parent = parent.parent;
@@ -373,16 +1071,17 @@ impl BPlusTree {
         }
         for i in (parent.num_keys + 1)..(INTERNAL_ORDER + 2) {
             parent.children[i] = -1;
+            parent.counts[i] = 0;
         }
 
         for i in 0..=new_internal.num_keys {
             let child = new_internal.children[i] as usize;
             if self.is_leaf_page(child) {
-                let mut ln = self.read_leaf_node(child);
-                ln.parent = new_page as i32;
+                let mut ln = self.read_leaf_node(child)?;
+                ln.set_parent(new_page as i32);
                 self.write_leaf_node(child, &ln)?;
             } else {
-                let mut in_node = self.read_internal_node(child);
+                let mut in_node = self.read_internal_node(child)?;
                 in_node.parent = new_page as i32;
                 self.write_internal_node(child, &in_node)?;
             }
@@ -394,64 +1093,199 @@ impl BPlusTree {
         self.insert_into_parent(parent_page, promote, new_page)
     }
 
-    pub fn write_data(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
-        let leaf_page = self.find_leaf(key);
-        if let Some((split_key, new_page)) = self.insert_into_leaf(leaf_page, key, data)? {
-            self.insert_into_parent(leaf_page, split_key, new_page)?;
-        }
-        self.mmap.flush()?;
-        Ok(true)
+    #[allow(clippy::type_complexity)]
+    fn locate(&self, key: i32) -> Result<(usize, LeafNode, std::result::Result<usize, usize>)> {
+        let leaf_page = self.find_leaf(key)?;
+        let leaf = self.read_leaf_node(leaf_page)?;
+        let found = leaf.search(key);
+        Ok((leaf_page, leaf, found))
     }
 
-    fn remove_from_leaf(&mut self, leaf_page: usize, key: i32) -> Result<bool> {
-        let mut leaf = self.read_leaf_node(leaf_page);
-        let mut pos = None;
-        for i in 0..leaf.num_keys {
-            if leaf.keys[i] == key {
-                pos = Some(i);
-                break;
+    // Descends once to the key's leaf, lets `decide` inspect the current value and applies the
+    // chosen update at the position already found.
+    fn update_entry<R>(
+        &mut self,
+        key: i32,
+        decide: impl FnOnce(Option<&[u8; DATA_SIZE]>) -> (Update, R),
+    ) -> Result<R> {
+        let (leaf_page, leaf, found) = self.locate(key)?;
+        let current = found
+            .ok()
+            .map(|i| self.reader().value_at(&leaf, i))
+            .transpose()?;
+        let (update, result) = decide(current.as_ref());
+        self.apply_update(leaf_page, leaf, found, key, update)?;
+        Ok(result)
+    }
+
+    fn apply_update(
+        &mut self,
+        leaf_page: usize,
+        mut leaf: LeafNode,
+        found: std::result::Result<usize, usize>,
+        key: i32,
+        update: Update,
+    ) -> Result<()> {
+        match (update, found) {
+            (Update::Keep, _) | (Update::Remove, Err(_)) => {}
+            (Update::Put(data), Ok(i)) => self.overwrite_value(leaf_page, &mut leaf, i, &data)?,
+            (Update::Put(data), Err(pos)) => {
+                self.changed(key, None, Some(&data))?;
+                self.adjust_counts(leaf_page, 1)?;
+                if let Some((split_key, new_page)) =
+                    self.insert_into_leaf(leaf_page, leaf, pos, key, &data)?
+                {
+                    self.insert_into_parent(leaf_page, split_key, new_page)?;
+                }
             }
+            (Update::Remove, Ok(pos)) => self.remove_from_leaf(leaf_page, leaf, pos)?,
         }
-        if pos.is_none() {
-            return Ok(false);
-        }
-        let pos = pos.unwrap();
-        for i in pos..leaf.num_keys - 1 {
-            leaf.keys[i] = leaf.keys[i + 1];
-            leaf.data[i] = leaf.data[i + 1];
+
+        Ok(())
+    }
+
+    fn put_entry(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<Option<[u8; DATA_SIZE]>> {
+        self.update_entry(key, |current| (Update::Put(*data), current.copied()))
+    }
+
+    fn insert_entry_if_absent(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.update_entry(key, |current| match current {
+            None => (Update::Put(*data), true),
+            Some(_) => (Update::Keep, false),
+        })
+    }
+
+    fn update_entry_if_present(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.update_entry(key, |current| match current {
+            Some(_) => (Update::Put(*data), true),
+            None => (Update::Keep, false),
+        })
+    }
+
+    fn compare_and_swap_entry(
+        &mut self,
+        key: i32,
+        expected: Option<&[u8; DATA_SIZE]>,
+        new: Option<&[u8; DATA_SIZE]>,
+    ) -> Result<std::result::Result<(), Option<[u8; DATA_SIZE]>>> {
+        self.update_entry(key, |current| {
+            if current != expected {
+                return (Update::Keep, Err(current.copied()));
+            }
+            match new {
+                Some(data) => (Update::Put(*data), Ok(())),
+                None => (Update::Remove, Ok(())),
+            }
+        })
+    }
+
+    fn remove_entry(&mut self, key: i32) -> Result<Option<[u8; DATA_SIZE]>> {
+        self.update_entry(key, |current| (Update::Remove, current.copied()))
+    }
+
+    pub fn entry(&mut self, key: i32) -> Result<Entry<'_>> {
+        Entry::new(self, key, true)
+    }
+
+    // Returns whether the key was new; an overwrite returns false. In multimap mode this adds
+    // another value for the key instead of replacing it, even one equal to a value the key
+    // already holds, and likewise returns whether the key was new.
+    pub fn write_data(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        traced!("write_data", self.reader(), key; {
+            if self.multimap {
+                self.autocommit(|tree| tree.add_value(key, data))
+            } else {
+                Ok(self.put(key, data)?.is_none())
+            }
+        })
+    }
+
+    pub fn is_multimap(&self) -> bool {
+        self.multimap
+    }
+
+    fn add_value(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        if !self.multimap {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "index is not in multimap mode",
+            ));
         }
-        leaf.num_keys -= 1;
+        self.append_value(key, data)
+    }
+
+    pub fn insert_value(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<()> {
+        self.autocommit(|tree| tree.add_value(key, data).map(|_| ()))
+    }
+
+    pub fn get_all(&self, key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        self.reader().get_all(key)
+    }
+
+    pub fn delete_one(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.autocommit(|tree| tree.remove_value(key, data))
+    }
+
+    pub fn delete_all(&mut self, key: i32) -> Result<usize> {
+        self.autocommit(|tree| tree.remove_all(key))
+    }
+
+    pub fn put(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<Option<[u8; DATA_SIZE]>> {
+        self.autocommit(|tree| tree.put_entry(key, data))
+    }
+
+    pub fn insert_if_absent(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.autocommit(|tree| tree.insert_entry_if_absent(key, data))
+    }
+
+    pub fn update_if_present(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.autocommit(|tree| tree.update_entry_if_present(key, data))
+    }
+
+    // Applies `new` (None deletes) only if the current value equals `expected` (None means
+    // absent). On mismatch nothing changes and the current value is returned as the error.
+    pub fn compare_and_swap(
+        &mut self,
+        key: i32,
+        expected: Option<&[u8; DATA_SIZE]>,
+        new: Option<&[u8; DATA_SIZE]>,
+    ) -> Result<std::result::Result<(), Option<[u8; DATA_SIZE]>>> {
+        self.autocommit(|tree| tree.compare_and_swap_entry(key, expected, new))
+    }
+
+    fn remove_from_leaf(&mut self, leaf_page: usize, mut leaf: LeafNode, pos: usize) -> Result<()> {
+        let old = self.reader().value_at(&leaf, pos)?;
+        self.changed(leaf.key(pos), Some(&old), None)?;
+        self.free_postings(&leaf, pos)?;
+        self.adjust_counts(leaf_page, -1)?;
+        leaf.remove_at(pos);
         self.write_leaf_node(leaf_page, &leaf)?;
+        self.rebalance_if_underfull(leaf_page, &leaf)?;
+
+        Ok(())
+    }
 
-        if leaf.num_keys < (LEAF_ORDER + 1) / 2 && leaf.parent != -1 {
+    // Called after a write that leaves `leaf` holding fewer values: removing a key, removing
+    // one of a key's inline values, or moving a key's values out to a posting list.
+    fn rebalance_if_underfull(&mut self, leaf_page: usize, leaf: &LeafNode) -> Result<()> {
+        if leaf.width() < LEAF_MIN_WIDTH && leaf.parent() != -1 {
             self.rebalance_after_delete(leaf_page)?;
         }
-
-        Ok(true)
+        Ok(())
     }
 
     fn rebalance_after_delete(&mut self, page: usize) -> Result<()> {
-        let parent_page = if self.is_leaf_page(page) {
-            self.read_leaf_node(page).parent
-        } else {
-            self.read_internal_node(page).parent
-        };
+        let parent_page = self.parent_of(page)?;
 
         if parent_page == -1 {
             if !self.is_leaf_page(page) {
-                let node = self.read_internal_node(page);
+                let node = self.read_internal_node(page)?;
                 if node.num_keys == 0 {
+                    self.notify(|o| o.on_root_change(page as i32, node.children[0]));
                     self.root_page = node.children[0];
                     if self.root_page != -1 {
-                        if self.is_leaf_page(self.root_page as usize) {
-                            let mut ln = self.read_leaf_node(self.root_page as usize);
-                            ln.parent = -1;
-                            self.write_leaf_node(self.root_page as usize, &ln)?;
-                        } else {
-                            let mut in_node = self.read_internal_node(self.root_page as usize);
-                            in_node.parent = -1;
-                            self.write_internal_node(self.root_page as usize, &in_node)?;
-                        }
+                        self.set_parent(self.root_page as usize, -1)?;
+                        self.pager.write().unwrap().free_page(page);
                     }
                 }
             }
@@ -459,7 +1293,7 @@ impl BPlusTree {
         }
 
         let parent_page_usize = parent_page as usize;
-        let parent = self.read_internal_node(parent_page_usize);
+        let parent = self.read_internal_node(parent_page_usize)?;
         let mut idx = 0;
         while idx <= parent.num_keys && parent.children[idx] != page as i32 {
             idx += 1;
@@ -477,15 +1311,15 @@ impl BPlusTree {
         };
 
         if let Some(ls) = left_sibling {
-            if self.can_borrow(ls) {
+            if self.can_borrow(ls, true)? {
                 self.borrow_from_left(page, ls, parent_page_usize, idx)?;
-                return Ok(());
+                return self.borrow_again_if_short(page);
             }
         }
         if let Some(rs) = right_sibling {
-            if self.can_borrow(rs) {
+            if self.can_borrow(rs, false)? {
                 self.borrow_from_right(page, rs, parent_page_usize, idx)?;
-                return Ok(());
+                return self.borrow_again_if_short(page);
             }
         }
 
@@ -498,12 +1332,30 @@ impl BPlusTree {
         Ok(())
     }
 
-    fn can_borrow(&self, sibling: usize) -> bool {
+    // Whether `sibling` stays at least half full after giving up its last entry (`from_left`)
+    // or its first. A leaf gives a whole cell, which may hold several values.
+    fn can_borrow(&self, sibling: usize, from_left: bool) -> Result<bool> {
         if self.is_leaf_page(sibling) {
-            self.read_leaf_node(sibling).num_keys > (LEAF_ORDER + 1) / 2
+            let leaf = self.read_leaf_node(sibling)?;
+            let moved = if from_left {
+                leaf.num_keys().saturating_sub(1)
+            } else {
+                0
+            };
+            Ok(leaf.num_keys() > 0 && leaf.width() - leaf.inline_len(moved) >= LEAF_MIN_WIDTH)
         } else {
-            self.read_internal_node(sibling).num_keys > (INTERNAL_ORDER + 1) / 2
+            Ok(self.read_internal_node(sibling)?.num_keys > INTERNAL_ORDER.div_ceil(2))
+        }
+    }
+
+    // A borrowed cell may hold fewer values than a leaf is short of, so it keeps borrowing,
+    // or merges once neither sibling can spare a cell.
+    fn borrow_again_if_short(&mut self, page: usize) -> Result<()> {
+        if self.is_leaf_page(page) {
+            let leaf = self.read_leaf_node(page)?;
+            self.rebalance_if_underfull(page, &leaf)?;
         }
+        Ok(())
     }
 
     fn borrow_from_left(
@@ -513,46 +1365,51 @@ impl BPlusTree {
         parent_page: usize,
         idx_in_parent: usize,
     ) -> Result<()> {
+        self.notify(|o| o.on_borrow(page, left));
         if self.is_leaf_page(page) {
-            let mut leaf = self.read_leaf_node(page);
-            let mut l = self.read_leaf_node(left);
-            for i in (0..leaf.num_keys).rev() {
-                leaf.keys[i + 1] = leaf.keys[i];
-                leaf.data[i + 1] = leaf.data[i];
-            }
-            leaf.keys[0] = l.keys[l.num_keys - 1];
-            leaf.data[0] = l.data[l.num_keys - 1];
-            leaf.num_keys += 1;
-            l.num_keys -= 1;
+            let mut leaf = self.read_leaf_node(page)?;
+            let mut l = self.read_leaf_node(left)?;
+            let last = l.num_keys() - 1;
+            if !leaf.insert_at(0, l.cell(last)) {
+                return Err(corrupt(page, "borrowed cell does not fit"));
+            }
+            l.remove_at(last);
 
             self.write_leaf_node(page, &leaf)?;
             self.write_leaf_node(left, &l)?;
 
-            let mut parent = self.read_internal_node(parent_page);
-            parent.keys[idx_in_parent - 1] = leaf.keys[0];
+            let mut parent = self.read_internal_node(parent_page)?;
+            parent.keys[idx_in_parent - 1] = leaf.key(0);
+            parent.counts[idx_in_parent - 1] -= 1;
+            parent.counts[idx_in_parent] += 1;
             self.write_internal_node(parent_page, &parent)?;
         } else {
-            let mut node = self.read_internal_node(page);
-            let mut l = self.read_internal_node(left);
+            let mut node = self.read_internal_node(page)?;
+            let mut l = self.read_internal_node(left)?;
+            let mut parent = self.read_internal_node(parent_page)?;
             for i in (0..node.num_keys).rev() {
                 node.keys[i + 1] = node.keys[i];
-                node.children[i + 2] = node.children[i + 1];
             }
-            node.keys[0] = l.keys[l.num_keys - 1];
+            for i in (0..=node.num_keys).rev() {
+                node.children[i + 1] = node.children[i];
+                node.counts[i + 1] = node.counts[i];
+            }
+            let moved = l.counts[l.num_keys];
+            node.keys[0] = parent.keys[idx_in_parent - 1];
             node.children[0] = l.children[l.num_keys];
+            node.counts[0] = moved;
             node.num_keys += 1;
+            parent.keys[idx_in_parent - 1] = l.keys[l.num_keys - 1];
+            parent.counts[idx_in_parent - 1] -= moved;
+            parent.counts[idx_in_parent] += moved;
+            l.children[l.num_keys] = -1;
+            l.counts[l.num_keys] = 0;
             l.num_keys -= 1;
 
             self.write_internal_node(page, &node)?;
             self.write_internal_node(left, &l)?;
-
-            let mut child = self.read_internal_node(node.children[0] as usize);
-            child.parent = page as i32;
-            self.write_internal_node(node.children[0] as usize, &child)?;
-
-            let mut parent = self.read_internal_node(parent_page);
-            parent.keys[idx_in_parent - 1] = node.keys[0];
             self.write_internal_node(parent_page, &parent)?;
+            self.set_parent(node.children[0] as usize, page as i32)?;
         }
         Ok(())
     }
@@ -564,50 +1421,52 @@ impl BPlusTree {
         parent_page: usize,
         idx_in_parent: usize,
     ) -> Result<()> {
+        self.notify(|o| o.on_borrow(page, right));
         if self.is_leaf_page(page) {
-            let mut leaf = self.read_leaf_node(page);
-            let mut r = self.read_leaf_node(right);
-            leaf.keys[leaf.num_keys] = r.keys[0];
-            leaf.data[leaf.num_keys] = r.data[0];
-            leaf.num_keys += 1;
-
-            for i in 0..r.num_keys - 1 {
-                r.keys[i] = r.keys[i + 1];
-                r.data[i] = r.data[i + 1];
+            let mut leaf = self.read_leaf_node(page)?;
+            let mut r = self.read_leaf_node(right)?;
+            if !leaf.insert_at(leaf.num_keys(), r.cell(0)) {
+                return Err(corrupt(page, "borrowed cell does not fit"));
             }
-            r.num_keys -= 1;
+            r.remove_at(0);
 
             self.write_leaf_node(page, &leaf)?;
             self.write_leaf_node(right, &r)?;
 
-            let mut parent = self.read_internal_node(parent_page);
-            parent.keys[idx_in_parent] = r.keys[0];
+            let mut parent = self.read_internal_node(parent_page)?;
+            parent.keys[idx_in_parent] = r.key(0);
+            parent.counts[idx_in_parent] += 1;
+            parent.counts[idx_in_parent + 1] -= 1;
             self.write_internal_node(parent_page, &parent)?;
         } else {
-            let mut node = self.read_internal_node(page);
-            let mut r = self.read_internal_node(right);
+            let mut node = self.read_internal_node(page)?;
+            let mut r = self.read_internal_node(right)?;
+            let mut parent = self.read_internal_node(parent_page)?;
 
-            node.keys[node.num_keys] = self.read_internal_node(right).keys[0];
+            let moved = r.counts[0];
+            node.keys[node.num_keys] = parent.keys[idx_in_parent];
             node.children[node.num_keys + 1] = r.children[0];
+            node.counts[node.num_keys + 1] = moved;
             node.num_keys += 1;
+            parent.keys[idx_in_parent] = r.keys[0];
+            parent.counts[idx_in_parent] += moved;
+            parent.counts[idx_in_parent + 1] -= moved;
 
             for i in 0..r.num_keys - 1 {
                 r.keys[i] = r.keys[i + 1];
+            }
+            for i in 0..r.num_keys {
                 r.children[i] = r.children[i + 1];
+                r.counts[i] = r.counts[i + 1];
             }
-            r.children[r.num_keys - 1] = r.children[r.num_keys];
+            r.children[r.num_keys] = -1;
+            r.counts[r.num_keys] = 0;
             r.num_keys -= 1;
 
             self.write_internal_node(page, &node)?;
             self.write_internal_node(right, &r)?;
-
-            let mut child = self.read_internal_node(node.children[node.num_keys] as usize);
-            child.parent = page as i32;
-            self.write_internal_node(node.children[node.num_keys] as usize, &child)?;
-
-            let mut parent = self.read_internal_node(parent_page);
-            parent.keys[idx_in_parent] = r.keys[0];
             self.write_internal_node(parent_page, &parent)?;
+            self.set_parent(node.children[node.num_keys] as usize, page as i32)?;
         }
         Ok(())
     }
@@ -619,56 +1478,57 @@ impl BPlusTree {
         parent_page: usize,
         idx_in_parent: usize,
     ) -> Result<()> {
+        self.notify(|o| o.on_merge(left, right));
         if self.is_leaf_page(left) {
-            let mut l = self.read_leaf_node(left);
-            let r = self.read_leaf_node(right);
-            for i in 0..r.num_keys {
-                l.keys[l.num_keys + i] = r.keys[i];
-                l.data[l.num_keys + i] = r.data[i];
+            let mut l = self.read_leaf_node(left)?;
+            let r = self.read_leaf_node(right)?;
+            let mut entries = l.entries();
+            entries.extend(r.entries());
+            if !LeafNode::fits(&entries) {
+                return Err(corrupt(left, "merged leaves do not fit"));
             }
-            l.num_keys += r.num_keys;
-            l.next_leaf = r.next_leaf;
+            l.rebuild(&entries);
+            l.set_next_leaf(r.next_leaf());
             self.write_leaf_node(left, &l)?;
+            self.set_prev_link(r.next_leaf(), left)?;
         } else {
-            let mut l = self.read_internal_node(left);
-            let r = self.read_internal_node(right);
+            let mut l = self.read_internal_node(left)?;
+            let r = self.read_internal_node(right)?;
 
-            l.keys[l.num_keys] = self.read_internal_node(parent_page).keys[idx_in_parent];
+            l.keys[l.num_keys] = self.read_internal_node(parent_page)?.keys[idx_in_parent];
             l.num_keys += 1;
             for i in 0..r.num_keys {
                 l.keys[l.num_keys + i] = r.keys[i];
                 l.children[l.num_keys + i] = r.children[i];
+                l.counts[l.num_keys + i] = r.counts[i];
             }
             l.children[l.num_keys + r.num_keys] = r.children[r.num_keys];
+            l.counts[l.num_keys + r.num_keys] = r.counts[r.num_keys];
             l.num_keys += r.num_keys;
 
             for i in 0..=l.num_keys {
-                let child = l.children[i] as usize;
-                if self.is_leaf_page(child) {
-                    let mut ln = self.read_leaf_node(child);
-                    ln.parent = left as i32;
-                    self.write_leaf_node(child, &ln)?;
-                } else {
-                    let mut in_node = self.read_internal_node(child);
-                    in_node.parent = left as i32;
-                    self.write_internal_node(child, &in_node)?;
-                }
+                self.set_parent(l.children[i] as usize, left as i32)?;
             }
 
             self.write_internal_node(left, &l)?;
         }
+        self.pager.write().unwrap().free_page(right);
 
-        let mut parent = self.read_internal_node(parent_page);
+        let mut parent = self.read_internal_node(parent_page)?;
+        parent.counts[idx_in_parent] += parent.counts[idx_in_parent + 1];
         for i in idx_in_parent..parent.num_keys - 1 {
             parent.keys[i] = parent.keys[i + 1];
             parent.children[i + 1] = parent.children[i + 2];
+            parent.counts[i + 1] = parent.counts[i + 2];
         }
         parent.num_keys -= 1;
         parent.children[parent.num_keys + 1] = -1;
+        parent.counts[parent.num_keys + 1] = 0;
 
         self.write_internal_node(parent_page, &parent)?;
 
-        if parent.num_keys < (INTERNAL_ORDER + 1) / 2 && parent.parent != -1 {
+        // For the root this only collapses it once its last separator is gone.
+        if parent.num_keys < INTERNAL_ORDER.div_ceil(2) {
             self.rebalance_after_delete(parent_page)?;
         }
 
@@ -676,20 +1536,328 @@ impl BPlusTree {
     }
 
     pub fn delete(&mut self, key: i32) -> Result<bool> {
-        let leaf_page = self.find_leaf(key);
-        let result = self.remove_from_leaf(leaf_page, key)?;
-        self.mmap.flush()?;
-        Ok(result)
+        traced!("delete", self.reader(), key;
+            Ok(self.autocommit(|tree| tree.remove_entry(key))?.is_some()))
     }
 
-    pub fn read(&self, key: i32) -> Option<[u8; DATA_SIZE]> {
-        let leaf_page = self.find_leaf(key);
-        let leaf = self.read_leaf_node(leaf_page);
-        for i in 0..leaf.num_keys {
-            if leaf.keys[i] == key {
-                return Some(leaf.data[i]);
+    // Unhooks every subtree lying wholly inside the range without visiting its keys one by one,
+    // trims the two boundary leaves, then repairs underflow along the two boundary paths only.
+    // The records are only read first when indexes or the change feed need their values.
+    fn remove_range(&mut self, range: impl RangeBounds<i32>) -> Result<usize> {
+        let Some((start, end)) = inclusive_bounds(range) else {
+            return Ok(0);
+        };
+        if !self.indexes.is_empty() || self.is_capturing() {
+            for (key, data) in self.reader().read_range_entries(start, end)? {
+                self.changed(key, Some(&data), None)?;
+            }
+        }
+        let first_leaf = self.find_leaf(start)?;
+        let last_leaf = self.find_leaf(end)?;
+
+        let removed = self.drop_range(self.root_page as usize, Some(start), Some(end))?;
+
+        if first_leaf != last_leaf {
+            let mut first = self.read_leaf_node(first_leaf)?;
+            first.set_next_leaf(last_leaf as i32);
+            self.write_leaf_node(first_leaf, &first)?;
+            self.set_prev_link(last_leaf as i32, first_leaf)?;
+        }
+
+        self.repair_path(start)?;
+        self.repair_path(end)?;
+        Ok(removed)
+    }
+
+    // Removes keys in the range below `page`; a missing bound means the subtree lies entirely
+    // past it. Only the leaves holding `start` and `end` survive, the rest are freed.
+    fn drop_range(&mut self, page: usize, start: Option<i32>, end: Option<i32>) -> Result<usize> {
+        let in_range = |key: i32| start.is_none_or(|s| key >= s) && end.is_none_or(|e| key <= e);
+
+        if self.is_leaf_page(page) {
+            let mut leaf = self.read_leaf_node(page)?;
+            let entries = leaf.entries();
+            for i in (0..entries.len()).filter(|&i| in_range(entries[i].0)) {
+                self.free_postings(&leaf, i)?;
+            }
+            let kept: Vec<_> = entries
+                .iter()
+                .filter(|(key, _, _)| !in_range(*key))
+                .cloned()
+                .collect();
+            if kept.len() < entries.len() {
+                leaf.rebuild(&kept);
+                self.write_leaf_node(page, &leaf)?;
             }
+            return Ok(entries.len() - kept.len());
         }
-        None
+
+        let mut node = self.read_internal_node(page)?;
+        let first = start.map(|key| node.child_index(key));
+        let last = end.map(|key| node.child_index(key));
+        let mut removed = 0;
+
+        let mut kept = Vec::new();
+        for i in 0..=node.num_keys {
+            let covered = first.is_none_or(|f| i > f) && last.is_none_or(|l| i < l);
+            if covered {
+                removed += node.counts[i] as usize;
+                self.drop_subtree(node.children[i] as usize)?;
+            } else {
+                kept.push(i);
+            }
+        }
+
+        let old = node;
+        node.children = [-1; INTERNAL_ORDER + 2];
+        node.counts = [0; INTERNAL_ORDER + 2];
+        let (first, last) = (first.unwrap_or(usize::MAX), last.unwrap_or(usize::MAX));
+        for (n, &i) in kept.iter().enumerate() {
+            if n > 0 {
+                node.keys[n - 1] = old.keys[i - 1];
+            }
+            let child = old.children[i] as usize;
+            let dropped = if i == first && i == last {
+                self.drop_range(child, start, end)?
+            } else if i == first {
+                self.drop_range(child, start, None)?
+            } else if i == last {
+                self.drop_range(child, None, end)?
+            } else {
+                0
+            };
+            node.children[n] = child as i32;
+            node.counts[n] = old.counts[i] - dropped as u64;
+            removed += dropped;
+        }
+        node.num_keys = kept.len() - 1;
+
+        if removed > 0 || node.num_keys < old.num_keys {
+            self.write_internal_node(page, &node)?;
+        }
+        Ok(removed)
+    }
+
+    fn drop_subtree(&mut self, page: usize) -> Result<()> {
+        if !self.is_leaf_page(page) {
+            let node = self.read_internal_node(page)?;
+            for i in 0..=node.num_keys {
+                self.drop_subtree(node.children[i] as usize)?;
+            }
+        } else if self.multimap {
+            let leaf = self.read_leaf_node(page)?;
+            for i in 0..leaf.num_keys() {
+                self.free_postings(&leaf, i)?;
+            }
+        }
+        self.pager.write().unwrap().free_page(page);
+        Ok(())
+    }
+
+    // Rebalances the deepest underfull node on the path to `key` until none is left. A node
+    // whose parent has a single child is skipped until the parent itself has been fixed.
+    fn repair_path(&mut self, key: i32) -> Result<()> {
+        loop {
+            let mut path = vec![self.root_page as usize];
+            while !self.is_leaf_page(*path.last().unwrap()) {
+                let node = self.read_internal_node(*path.last().unwrap())?;
+                path.push(node.children[node.child_index(key)] as usize);
+            }
+
+            let mut target = None;
+            for depth in (0..path.len()).rev() {
+                let page = path[depth];
+                let underfull = if self.is_leaf_page(page) {
+                    depth > 0 && self.read_leaf_node(page)?.width() < LEAF_MIN_WIDTH
+                } else {
+                    let num_keys = self.read_internal_node(page)?.num_keys;
+                    num_keys == 0 || (depth > 0 && num_keys < INTERNAL_ORDER.div_ceil(2))
+                };
+                let has_sibling =
+                    depth == 0 || self.read_internal_node(path[depth - 1])?.num_keys > 0;
+                if underfull && has_sibling {
+                    target = Some(page);
+                    break;
+                }
+            }
+
+            match target {
+                Some(page) => self.rebalance_after_delete(page)?,
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn rewrite_range(
+        &mut self,
+        range: impl RangeBounds<i32>,
+        mut f: impl FnMut(i32, &mut [u8; DATA_SIZE]),
+    ) -> Result<usize> {
+        let Some((start, end)) = inclusive_bounds(range) else {
+            return Ok(0);
+        };
+        let mut page = self.find_leaf(start)?;
+        let mut updated = 0;
+        let mut steps = 0;
+
+        loop {
+            let mut leaf = self.read_leaf_node(page)?;
+            let mut changed = false;
+            for i in 0..leaf.num_keys() {
+                let key = leaf.key(i);
+                if key > end {
+                    break;
+                }
+                if key < start {
+                    continue;
+                }
+                if leaf.is_posting(i) {
+                    updated += self.rewrite_postings(&leaf, i, |data| f(key, data))?;
+                    continue;
+                }
+                for (j, old) in leaf.values(i).into_iter().enumerate() {
+                    let mut data = old;
+                    f(key, &mut data);
+                    if data != old {
+                        self.changed(key, Some(&old), Some(&data))?;
+                        leaf.set_inline_value(i, j, &data);
+                        changed = true;
+                        updated += 1;
+                    }
+                }
+            }
+            if changed {
+                self.write_leaf_node(page, &leaf)?;
+            }
+            let last = leaf.num_keys().checked_sub(1).map(|i| leaf.key(i));
+            if last.is_some_and(|key| key >= end) {
+                return Ok(updated);
+            }
+            match self
+                .reader()
+                .next_in_chain(page, leaf.next_leaf(), &mut steps)?
+            {
+                Some(next) => page = next,
+                None => return Ok(updated),
+            }
+        }
+    }
+
+    pub fn delete_range(&mut self, range: impl RangeBounds<i32>) -> Result<usize> {
+        self.autocommit(|tree| tree.remove_range(range))
+    }
+
+    // Calls `f` on every value in the range and writes back the ones it changed. Returns how
+    // many values were rewritten.
+    pub fn update_range(
+        &mut self,
+        range: impl RangeBounds<i32>,
+        f: impl FnMut(i32, &mut [u8; DATA_SIZE]),
+    ) -> Result<usize> {
+        self.autocommit(|tree| tree.rewrite_range(range, f))
+    }
+
+    // Number of keys; in multimap mode a key counts once however many values it holds.
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.reader().subtree_count(self.root_page as usize)? as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    // Number of keys in the range, counted like `len`.
+    pub fn count_range(&self, range: impl RangeBounds<i32>) -> Result<usize> {
+        match inclusive_bounds(range) {
+            Some((start, end)) => {
+                let reader = self.reader();
+                Ok(reader.count_below(end, true)? - reader.count_below(start, false)?)
+            }
+            None => Ok(0),
+        }
+    }
+
+    // Number of keys strictly less than `key`, i.e. the index it has or would have.
+    pub fn rank(&self, key: i32) -> Result<usize> {
+        self.reader().count_below(key, false)
+    }
+
+    // The entry at zero-based position `index` in key order.
+    pub fn nth(&self, index: usize) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        self.reader().nth(index)
+    }
+
+    pub fn first(&self) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let reader = self.reader();
+        reader.entry_at(reader.first()?)
+    }
+
+    pub fn last(&self) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let reader = self.reader();
+        reader.entry_at(reader.last()?)
+    }
+
+    // The first entry with a key `>= key`.
+    pub fn lower_bound(&self, key: i32) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let reader = self.reader();
+        let (page, pos) = reader.bound(key, false)?;
+        reader.entry_at(reader.seek_forward(page, pos)?)
+    }
+
+    // The first entry with a key `> key`.
+    pub fn upper_bound(&self, key: i32) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let reader = self.reader();
+        let (page, pos) = reader.bound(key, true)?;
+        reader.entry_at(reader.seek_forward(page, pos)?)
+    }
+
+    // The entry with the largest key `<= key`.
+    pub fn floor(&self, key: i32) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let reader = self.reader();
+        let (page, pos) = reader.bound(key, true)?;
+        reader.entry_at(reader.seek_backward(page, pos)?)
+    }
+
+    // The entry with the smallest key `>= key`; the same entry as `lower_bound`.
+    pub fn ceiling(&self, key: i32) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        self.lower_bound(key)
+    }
+
+    fn pop_entry(&mut self, last: bool) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        let reader = self.reader();
+        let position = if last {
+            reader.last()?
+        } else {
+            reader.first()?
+        };
+        let Some((page, pos)) = position else {
+            return Ok(None);
+        };
+        let leaf = self.read_leaf_node(page)?;
+        let values = self.reader().values_at(&leaf, pos)?;
+        if values.len() > 1 {
+            let value = if last {
+                values[values.len() - 1]
+            } else {
+                values[0]
+            };
+            self.remove_value(leaf.key(pos), &value)?;
+            return Ok(Some((leaf.key(pos), value)));
+        }
+        let entry = (leaf.key(pos), leaf.value(pos));
+        self.remove_from_leaf(page, leaf, pos)?;
+        Ok(Some(entry))
+    }
+
+    pub fn pop_first(&mut self) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        self.autocommit(|tree| tree.pop_entry(false))
+    }
+
+    pub fn pop_last(&mut self) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        self.autocommit(|tree| tree.pop_entry(true))
+    }
+
+    pub fn read(&self, key: i32) -> Result<Option<[u8; DATA_SIZE]>> {
+        traced!("read", self.reader(), key; self.reader().read(key))
     }
 }
@@ -0,0 +1,59 @@
+use crate::verify::corrupt;
+use crate::BPlusTree;
+use std::collections::HashSet;
+use std::io::Result;
+
+impl BPlusTree {
+    // Re-derives every parent pointer and leaf link of the current tree from a descent through
+    // the child pointers, which are the only structural state the lookups rely on. A page
+    // reached twice means the child pointers loop or share a subtree, and the caller rolls back.
+    pub(crate) fn relink(&mut self) -> Result<usize> {
+        if self.root_page == -1 {
+            return Ok(0);
+        }
+        let mut rewritten = 0;
+        let mut leaves = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![(self.root_page as usize, -1)];
+        while let Some((page_num, parent)) = stack.pop() {
+            if !seen.insert(page_num) {
+                return Err(corrupt(page_num, "reachable twice"));
+            }
+            if self.is_leaf_page(page_num) {
+                leaves.push((page_num, parent));
+                continue;
+            }
+            let mut node = self.read_internal_node(page_num)?;
+            if node.parent != parent {
+                node.parent = parent;
+                self.write_internal_node(page_num, &node)?;
+                rewritten += 1;
+            }
+            // Pushed in reverse so leaves come off the stack in key order.
+            for &child in node.children[..=node.num_keys].iter().rev() {
+                stack.push((child as usize, page_num as i32));
+            }
+        }
+
+        for (i, &(page_num, parent)) in leaves.iter().enumerate() {
+            let prev = if i == 0 { -1 } else { leaves[i - 1].0 as i32 };
+            let next = leaves.get(i + 1).map_or(-1, |&(page, _)| page as i32);
+            let mut leaf = self.read_leaf_node(page_num)?;
+            if leaf.parent() == parent && leaf.prev_leaf() == prev && leaf.next_leaf() == next {
+                continue;
+            }
+            leaf.set_parent(parent);
+            leaf.set_prev_leaf(prev);
+            leaf.set_next_leaf(next);
+            self.write_leaf_node(page_num, &leaf)?;
+            rewritten += 1;
+        }
+        Ok(rewritten)
+    }
+
+    // Rebuilds the parent pointers and sibling links of this tree and its registered indexes
+    // in one commit. Returns the number of pages that had to be rewritten.
+    pub fn rebuild_links(&mut self) -> Result<usize> {
+        self.autocommit(|tree| Ok(tree.relink()? + tree.relink_indexes()?))
+    }
+}
@@ -1,4 +1,6 @@
-use bptree::BPlusTree;
+use bplus_tree_index::{BPlusTree, Change, ChangeLog, ChecksumPolicy, Entry, Follower, Observer};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 const DATA_SIZE: usize = 100;
@@ -20,15 +22,15 @@ fn test_basic_operations() {
     assert!(tree.write_data(20, &data2).unwrap());
     assert!(tree.write_data(15, &data3).unwrap());
 
-    let result = tree.read(10).expect("Key 10 not found");
+    let result = tree.read(10).unwrap().expect("Key 10 not found");
     assert_eq!(&result[..20], &data1[..20]);
     println!("✓ Read key 10: {}", String::from_utf8_lossy(&result[..20]));
 
-    let result = tree.read(20).expect("Key 20 not found");
+    let result = tree.read(20).unwrap().expect("Key 20 not found");
     assert_eq!(&result[..20], &data2[..20]);
     println!("✓ Read key 20: {}", String::from_utf8_lossy(&result[..20]));
 
-    let result = tree.read(15).expect("Key 15 not found");
+    let result = tree.read(15).unwrap().expect("Key 15 not found");
     assert_eq!(&result[..20], &data3[..20]);
     println!("✓ Read key 15: {}", String::from_utf8_lossy(&result[..20]));
 
@@ -40,7 +42,7 @@ fn test_non_existent_key() {
 
     let tree = BPlusTree::new().expect("Failed to create tree");
 
-    let result = tree.read(999);
+    let result = tree.read(999).unwrap();
     assert!(result.is_none());
     println!("✓ Non-existent key returns None\n");
 }
@@ -57,20 +59,100 @@ fn test_update() {
     data2[..12].copy_from_slice(b"Updated data");
 
     assert!(tree.write_data(30, &data1).unwrap());
-    let result = tree.read(30).expect("Key 30 not found");
+    let result = tree.read(30).unwrap().expect("Key 30 not found");
     assert_eq!(&result[..13], &data1[..13]);
     println!("✓ Original: {}", String::from_utf8_lossy(&result[..13]));
 
-    assert!(tree.write_data(30, &data2).unwrap());
-    let result = tree.read(30).expect("Key 30 not found");
+    assert!(!tree.write_data(30, &data2).unwrap());
+    let result = tree.read(30).unwrap().expect("Key 30 not found");
     assert_eq!(&result[..12], &data2[..12]);
     println!("✓ Updated: {}", String::from_utf8_lossy(&result[..12]));
 
     println!("✓ Update test passed!\n");
 }
 
+fn test_conditional_writes() {
+    println!("=== Test 4: Conditional Writes ===");
+
+    let mut tree = BPlusTree::new().expect("Failed to create tree");
+
+    let mut data1 = [0u8; DATA_SIZE];
+    let mut data2 = [0u8; DATA_SIZE];
+    data1[..7].copy_from_slice(b"Version");
+    data2[..9].copy_from_slice(b"Version 2");
+
+    let _ = tree.delete(35);
+    assert!(tree.insert_if_absent(35, &data1).unwrap());
+    assert!(!tree.insert_if_absent(35, &data2).unwrap());
+    println!("✓ insert_if_absent only inserts once");
+
+    assert_eq!(tree.put(35, &data2).unwrap(), Some(data1));
+    println!("✓ put returns the replaced value");
+
+    assert_eq!(
+        tree.compare_and_swap(35, Some(&data1), Some(&data1))
+            .unwrap(),
+        Err(Some(data2))
+    );
+    assert!(tree
+        .compare_and_swap(35, Some(&data2), None)
+        .unwrap()
+        .is_ok());
+    assert!(tree.read(35).unwrap().is_none());
+    println!("✓ compare_and_swap rejects stale values and applies matching ones");
+
+    assert!(!tree.update_if_present(35, &data1).unwrap());
+    println!("✓ update_if_present skips missing keys");
+
+    println!("✓ Conditional writes test passed!\n");
+}
+
+fn test_entry_api() {
+    println!("=== Test 5: Entry API ===");
+
+    let mut tree = BPlusTree::new().expect("Failed to create tree");
+
+    let mut data1 = [0u8; DATA_SIZE];
+    let mut data2 = [0u8; DATA_SIZE];
+    data1[..7].copy_from_slice(b"Counter");
+    data2[..7].copy_from_slice(b"Default");
+
+    let _ = tree.delete(36);
+    assert_eq!(tree.entry(36).unwrap().or_insert(data2).unwrap(), data2);
+    assert_eq!(tree.entry(36).unwrap().or_insert(data1).unwrap(), data2);
+    println!("✓ or_insert keeps an existing value");
+
+    let value = tree
+        .entry(36)
+        .unwrap()
+        .and_modify(|v| v[0] = b'd')
+        .unwrap()
+        .or_insert_with(|| data1)
+        .unwrap();
+    assert_eq!(value[0], b'd');
+    assert_eq!(tree.read(36).unwrap().unwrap()[0], b'd');
+    println!("✓ and_modify updates the value in place");
+
+    match tree.entry(36).unwrap() {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.insert(data1).unwrap()[0], b'd');
+            assert_eq!(entry.remove().unwrap(), data1);
+        }
+        Entry::Vacant(_) => panic!("key 36 should be present"),
+    }
+    assert!(tree.read(36).unwrap().is_none());
+    match tree.entry(36).unwrap() {
+        Entry::Vacant(entry) => entry.insert(data1).unwrap(),
+        Entry::Occupied(_) => panic!("key 36 should be absent"),
+    }
+    assert_eq!(tree.read(36).unwrap(), Some(data1));
+    println!("✓ occupied and vacant entries insert and remove");
+
+    println!("✓ Entry API test passed!\n");
+}
+
 fn test_delete() {
-    println!("=== Test 4: Delete Operation ===");
+    println!("=== Test 6: Delete Operation ===");
 
     let mut tree = BPlusTree::new().expect("Failed to create tree");
 
@@ -79,14 +161,14 @@ fn test_delete() {
     data[..s.len()].copy_from_slice(s);
 
     assert!(tree.write_data(40, &data).unwrap());
-    let result = tree.read(40).expect("Key 40 not found");
+    let result = tree.read(40).unwrap().expect("Key 40 not found");
     println!(
         "✓ Before delete: {}",
         String::from_utf8_lossy(&result[..s.len()])
     );
 
     assert!(tree.delete(40).unwrap());
-    let result = tree.read(40);
+    let result = tree.read(40).unwrap();
     assert!(result.is_none());
     println!("✓ After delete: key not found");
 
@@ -97,7 +179,7 @@ fn test_delete() {
 }
 
 fn test_range_query() {
-    println!("=== Test 5: Range Query ===");
+    println!("=== Test 7: Range Query ===");
 
     let mut tree = BPlusTree::new().expect("Failed to create tree");
 
@@ -108,7 +190,7 @@ fn test_range_query() {
         assert!(tree.write_data(i, &data).unwrap());
     }
 
-    let results = tree.read_range_data(52, 57);
+    let results = tree.read_range_data(52, 57).unwrap();
 
     println!("Range [52, 57] returned {} results:", results.len());
     assert_eq!(results.len(), 6);
@@ -121,8 +203,821 @@ fn test_range_query() {
     println!("✓ Range query test passed!\n");
 }
 
+fn test_range_delete_update() {
+    println!("=== Test 8: Range Delete and Update ===");
+
+    let mut tree = BPlusTree::new().expect("Failed to create tree");
+
+    for i in 5000..8000 {
+        let mut data = [0u8; DATA_SIZE];
+        data[0] = 1;
+        tree.write_data(i, &data).unwrap();
+    }
+
+    let updated = tree
+        .update_range(5000..6000, |key, value| value[0] = (key % 7) as u8)
+        .unwrap();
+    assert_eq!(tree.read(5006).unwrap().unwrap()[0], 1);
+    assert_eq!(tree.read(5004).unwrap().unwrap()[0], 6);
+    println!("✓ update_range rewrote {} values", updated);
+
+    assert_eq!(tree.delete_range(5100..=7899).unwrap(), 2800);
+    assert!(tree.read(5100).unwrap().is_none() && tree.read(7899).unwrap().is_none());
+    assert!(tree.read(5099).unwrap().is_some() && tree.read(7900).unwrap().is_some());
+    assert_eq!(tree.read_range_data(5000, 7999).unwrap().len(), 200);
+    println!("✓ delete_range removed 2800 keys and kept both edges");
+
+    assert_eq!(tree.delete_range(5000..8000).unwrap(), 200);
+    assert!(tree.read_range_data(5000, 7999).unwrap().is_empty());
+
+    println!("✓ Range delete and update test passed!\n");
+}
+
+fn test_order_statistics() {
+    println!("=== Test 9: Order Statistics ===");
+
+    let mut tree = BPlusTree::new().expect("Failed to create tree");
+
+    tree.delete_range(9000..10000).unwrap();
+    for i in (9000..10000).step_by(2) {
+        let data = [0u8; DATA_SIZE];
+        tree.write_data(i, &data).unwrap();
+    }
+
+    assert_eq!(tree.count_range(9000..10000).unwrap(), 500);
+    assert_eq!(tree.count_range(9100..=9199).unwrap(), 50);
+    println!("✓ count_range counts 500 keys");
+
+    let base = tree.rank(9000).unwrap();
+    assert_eq!(tree.rank(9100).unwrap(), base + 50);
+    assert_eq!(tree.rank(9101).unwrap(), base + 51);
+    assert_eq!(tree.nth(base + 50).unwrap().unwrap().0, 9100);
+    assert_eq!(tree.nth(tree.len().unwrap()).unwrap(), None);
+    println!("✓ rank and nth agree");
+
+    tree.delete_range(9000..9500).unwrap();
+    assert_eq!(tree.count_range(9000..10000).unwrap(), 250);
+    assert_eq!(tree.rank(10000).unwrap(), base + 250);
+    println!("✓ Order statistics test passed!\n");
+}
+
+fn test_first_last() {
+    println!("=== Test 10: First, Last and Bounds ===");
+
+    let _ = BPlusTree::remove_files("bptree_bounds.dat");
+    let mut tree = BPlusTree::open("bptree_bounds.dat").expect("Failed to create tree");
+
+    for i in (0..500i32).map(|i| i * 10) {
+        let mut data = [0u8; DATA_SIZE];
+        data[..4].copy_from_slice(&i.to_le_bytes());
+        tree.write_data(i, &data).unwrap();
+    }
+
+    assert_eq!(tree.first().unwrap().unwrap().0, 0);
+    assert_eq!(tree.last().unwrap().unwrap().0, 4990);
+    println!("✓ first and last found without a search key");
+
+    assert_eq!(tree.lower_bound(20).unwrap().unwrap().0, 20);
+    assert_eq!(tree.upper_bound(20).unwrap().unwrap().0, 30);
+    assert_eq!(tree.floor(25).unwrap().unwrap().0, 20);
+    assert_eq!(tree.ceiling(25).unwrap().unwrap().0, 30);
+    assert!(tree.floor(-1).unwrap().is_none() && tree.ceiling(4991).unwrap().is_none());
+    println!("✓ lower_bound, upper_bound, floor and ceiling");
+
+    assert_eq!(tree.pop_first().unwrap().unwrap().0, 0);
+    assert_eq!(tree.pop_last().unwrap().unwrap().0, 4990);
+    assert_eq!(tree.first().unwrap().unwrap().0, 10);
+    assert_eq!(tree.len().unwrap(), 498);
+    println!("✓ pop_first and pop_last remove the ends");
+
+    let mut drained = 0;
+    while tree.pop_first().unwrap().is_some() {
+        drained += 1;
+    }
+    assert_eq!(drained, 498);
+    assert!(tree.is_empty().unwrap() && tree.last().unwrap().is_none());
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_bounds.dat");
+
+    println!("✓ First, last and bounds test passed!\n");
+}
+
+fn test_multimap() {
+    println!("=== Test 11: Multimap Mode ===");
+
+    let _ = BPlusTree::remove_files("bptree_multi.dat");
+    let mut tree = BPlusTree::open_multimap("bptree_multi.dat").expect("Failed to create tree");
+
+    let value = |n: u8| {
+        let mut data = [0u8; DATA_SIZE];
+        data[0] = n;
+        data
+    };
+
+    for n in 0..100 {
+        assert_eq!(tree.write_data(7, &value(n)).unwrap(), n == 0);
+    }
+    // An equal value is still added; only a new key returns true.
+    assert!(tree.write_data(8, &value(1)).unwrap());
+    assert!(!tree.write_data(8, &value(1)).unwrap());
+    assert_eq!(tree.get_all(8).unwrap(), vec![value(1), value(1)]);
+    assert!(tree.delete_one(8, &value(1)).unwrap());
+
+    let all = tree.get_all(7).unwrap();
+    assert_eq!(all.len(), 100);
+    assert!(all.iter().enumerate().all(|(i, v)| v[0] == i as u8));
+    assert_eq!(tree.read(7).unwrap().unwrap()[0], 0);
+    println!("✓ 100 values for one key kept in insertion order");
+
+    assert!(tree.delete_one(7, &value(42)).unwrap());
+    assert!(!tree.delete_one(7, &value(42)).unwrap());
+    assert_eq!(tree.get_all(7).unwrap().len(), 99);
+    println!("✓ delete_one removes a single value");
+
+    assert_eq!(tree.delete_all(7).unwrap(), 99);
+    assert!(tree.get_all(7).unwrap().is_empty());
+    assert_eq!(tree.get_all(8).unwrap(), vec![value(1)]);
+    println!("✓ delete_all removes every value of a key");
+
+    drop(tree);
+    let tree = BPlusTree::open("bptree_multi.dat").unwrap();
+    assert!(tree.is_multimap());
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_multi.dat");
+    println!("✓ Mode is kept in the file header");
+
+    println!("✓ Multimap test passed!\n");
+}
+
+fn test_secondary_index() {
+    println!("=== Test 12: Secondary Indexes ===");
+
+    let _ = BPlusTree::remove_files("bptree_secondary.dat");
+    let mut tree = BPlusTree::open("bptree_secondary.dat").expect("Failed to create tree");
+
+    // Byte 0 holds a city code the records are looked up by
+    let record = |city: u8, n: u8| {
+        let mut data = [0u8; DATA_SIZE];
+        data[0] = city;
+        data[1] = n;
+        data
+    };
+    let city = |data: &[u8; DATA_SIZE]| data[0] as i32;
+
+    for key in 0..300 {
+        tree.write_data(key, &record((key % 7) as u8, 0)).unwrap();
+    }
+    tree.register_index("city", city).unwrap();
+    assert_eq!(tree.lookup_by("city", 3).unwrap().len(), 43);
+    println!("✓ New index filled from existing records");
+
+    tree.write_data(3, &record(5, 1)).unwrap();
+    tree.delete(10).unwrap();
+    tree.write_data(1000, &record(3, 2)).unwrap();
+    let keys: Vec<i32> = tree
+        .lookup_by("city", 3)
+        .unwrap()
+        .iter()
+        .map(|(key, _)| *key)
+        .collect();
+    assert_eq!(keys.len(), 42);
+    assert!(!keys.contains(&3) && !keys.contains(&10) && keys.contains(&1000));
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+    tree.verify_index("city").unwrap();
+    println!("✓ Index follows overwrites and deletes");
+
+    drop(tree);
+    let mut tree = BPlusTree::open("bptree_secondary.dat").unwrap();
+    tree.register_index("city", city).unwrap();
+    tree.verify_index("city").unwrap();
+    assert!(tree.lookup_by("zip", 1).is_err());
+    assert!(tree.drop_index("city").unwrap());
+    assert!(tree.lookup_by("city", 3).is_err());
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_secondary.dat");
+    println!("✓ Index survives reopen and can be dropped");
+
+    println!("✓ Secondary index test passed!\n");
+}
+
+fn test_named_trees() {
+    println!("=== Test 13: Named Trees ===");
+
+    let _ = BPlusTree::remove_files("bptree_named.dat");
+    let mut db = BPlusTree::open("bptree_named.dat").expect("Failed to create tree");
+
+    db.create_tree("users").unwrap();
+    db.create_tree("orders").unwrap();
+    assert!(db.create_tree("users").is_err());
+    assert_eq!(db.list_trees().unwrap(), vec!["users", "orders"]);
+    println!("✓ Trees created in the catalog");
+
+    let mut data = [0u8; DATA_SIZE];
+    data[0] = 1;
+    db.write_data(1, &data).unwrap();
+    {
+        let mut users = db.open_tree("users").unwrap();
+        data[0] = 2;
+        for key in 0..1000 {
+            users.write_data(key, &data).unwrap();
+        }
+    }
+    {
+        let mut orders = db.open_tree("orders").unwrap();
+        data[0] = 3;
+        orders.write_data(1, &data).unwrap();
+    }
+    assert_eq!(db.len().unwrap(), 1);
+    assert_eq!(db.read(1).unwrap().unwrap()[0], 1);
+    assert_eq!(db.open_tree("users").unwrap().len().unwrap(), 1000);
+    assert_eq!(
+        db.open_tree("orders").unwrap().read(1).unwrap().unwrap()[0],
+        3
+    );
+    println!("✓ Trees are independent of each other and of the main tree");
+
+    drop(db);
+    let mut db = BPlusTree::open("bptree_named.dat").unwrap();
+    assert_eq!(
+        db.open_tree("users").unwrap().read(999).unwrap().unwrap()[0],
+        2
+    );
+    assert!(db.drop_tree("users").unwrap());
+    assert!(db.open_tree("users").is_err());
+    assert_eq!(db.list_trees().unwrap(), vec!["orders"]);
+    println!("✓ Trees survive reopen and can be dropped");
+
+    let first_byte = |data: &[u8; DATA_SIZE]| data[0] as i32;
+    db.register_index("first", first_byte).unwrap();
+    drop(db);
+    let mut db = BPlusTree::open("bptree_named.dat").unwrap();
+    db.register_index("first", first_byte).unwrap();
+    {
+        let mut orders = db.open_tree("orders").unwrap();
+        assert_eq!(
+            orders
+                .register_index("orders_first", first_byte)
+                .unwrap_err()
+                .kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        data[0] = 1;
+        orders.write_data(2, &data).unwrap();
+    }
+    data[0] = 1;
+    db.write_data(5, &data).unwrap();
+    let keys: Vec<i32> = db
+        .lookup_by("first", 1)
+        .unwrap()
+        .iter()
+        .map(|(key, _)| *key)
+        .collect();
+    assert_eq!(keys, vec![1, 5]);
+    db.verify_index("first").unwrap();
+    drop(db);
+    let _ = BPlusTree::remove_files("bptree_named.dat");
+    println!("✓ Indexes stay on the main tree while a named tree is open");
+
+    println!("✓ Named trees test passed!\n");
+}
+
+#[derive(Default)]
+struct DamageLog {
+    pages: Mutex<Vec<usize>>,
+}
+
+impl Observer for DamageLog {
+    fn on_checksum_failure(&self, page: usize) {
+        self.pages.lock().unwrap().push(page);
+    }
+}
+
+fn test_checksums() {
+    println!("=== Test 14: Page Checksums ===");
+
+    let _ = BPlusTree::remove_files("bptree_checksum.dat");
+    let mut tree = BPlusTree::open("bptree_checksum.dat").expect("Failed to create tree");
+    let data = [9u8; DATA_SIZE];
+    for key in 0..1000 {
+        tree.write_data(key, &data).unwrap();
+    }
+    assert_eq!(tree.read_range_data(0, 999).unwrap().len(), 1000);
+    assert_eq!(tree.checksum_failures(), 0);
+    drop(tree);
+    println!("✓ Clean file reads without checksum failures");
+
+    // Flip one bit in the value area of the first leaf page after the meta page
+    let mut bytes = std::fs::read("bptree_checksum.dat").unwrap();
+    let leaf = (1..bytes.len() / 4096)
+        .find(|page| bytes[page * 4096] == 1)
+        .unwrap();
+    bytes[leaf * 4096 + 4000] ^= 0x01;
+    std::fs::write("bptree_checksum.dat", &bytes).unwrap();
+
+    let mut tree = BPlusTree::open("bptree_checksum.dat").unwrap();
+    let damage = Arc::new(DamageLog::default());
+    tree.register_observer(damage.clone());
+    let err = tree.read_range_data(0, 999).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains(&format!("page {leaf}")));
+    assert_eq!(tree.checksum_failures(), 1);
+    println!("✓ Corrupted page fails the read: {err}");
+
+    tree.set_checksum_policy(ChecksumPolicy::Log);
+    assert_eq!(tree.read_range_data(0, 999).unwrap().len(), 1000);
+    assert_eq!(tree.checksum_failures(), 2);
+    assert_eq!(*damage.pages.lock().unwrap(), vec![leaf, leaf]);
+    println!("✓ Log policy reads on and reports the page to observers");
+
+    tree.set_checksum_policy(ChecksumPolicy::Salvage);
+    assert_eq!(tree.read_range_data(0, 999).unwrap().len(), 1000);
+    assert_eq!(tree.checksum_failures(), 2);
+    drop(tree);
+    println!("✓ Salvage mode reads without verifying");
+
+    let mut bytes = std::fs::read("bptree_checksum.dat").unwrap();
+    bytes[2000] ^= 0x01;
+    std::fs::write("bptree_checksum.dat", &bytes).unwrap();
+    let err = BPlusTree::open("bptree_checksum.dat").err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    let _ = BPlusTree::remove_files("bptree_checksum.dat");
+    println!("✓ Damaged meta page fails open: {err}");
+
+    println!("✓ Checksum test passed!\n");
+}
+
+fn test_repair() {
+    println!("=== Test 15: Repair ===");
+
+    let _ = BPlusTree::remove_files("bptree_damaged.dat");
+    let _ = BPlusTree::remove_files("bptree_repaired.dat");
+    let mut tree = BPlusTree::open("bptree_damaged.dat").expect("Failed to create tree");
+    for key in 0..1000 {
+        tree.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+    drop(tree);
+
+    let mut bytes = std::fs::read("bptree_damaged.dat").unwrap();
+    let leaf = (1..bytes.len() / 4096)
+        .find(|page| bytes[page * 4096] == 1)
+        .unwrap();
+    bytes[leaf * 4096 + 4000] ^= 0x01;
+    std::fs::write("bptree_damaged.dat", &bytes).unwrap();
+
+    let report = BPlusTree::repair("bptree_damaged.dat", "bptree_repaired.dat").unwrap();
+    assert_eq!(report.damaged_pages, vec![leaf]);
+    assert_eq!(report.lost.len(), 1);
+    println!("✓ Damaged leaf reported");
+
+    let repaired = BPlusTree::open("bptree_repaired.dat").unwrap();
+    let lost = &report.lost[0];
+    let missing = (0..1000).filter(|&key| repaired.read(key).unwrap().is_none());
+    assert_eq!(missing.count() as u64, lost.expected.unwrap());
+    assert_eq!(
+        repaired.len().unwrap() + lost.expected.unwrap() as usize,
+        1000
+    );
+    for key in 0..1000 {
+        if let Some(data) = repaired.read(key).unwrap() {
+            assert_eq!(data, [key as u8; DATA_SIZE]);
+        }
+    }
+    println!(
+        "✓ Remaining {} records copied intact",
+        repaired.len().unwrap()
+    );
+
+    assert!(BPlusTree::repair("bptree_damaged.dat", "bptree_repaired.dat").is_err());
+    drop(repaired);
+    for path in ["bptree_damaged.dat", "bptree_repaired.dat"] {
+        let _ = BPlusTree::remove_files(path);
+    }
+    println!("✓ Existing output is not overwritten");
+
+    println!("✓ Repair test passed!\n");
+}
+
+fn test_rebuild_links() {
+    println!("=== Test 16: Rebuild Links ===");
+
+    let _ = BPlusTree::remove_files("bptree_links.dat");
+    let mut tree = BPlusTree::open("bptree_links.dat").expect("Failed to create tree");
+    let data = [3u8; DATA_SIZE];
+    for key in 0..5000 {
+        tree.write_data(key, &data).unwrap();
+    }
+    assert_eq!(tree.rebuild_links().unwrap(), 0);
+    println!("✓ Links consistent after splits");
+
+    tree.delete_range(1000..3000).unwrap();
+    for key in (3000..5000).step_by(2) {
+        tree.delete(key).unwrap();
+    }
+    assert_eq!(tree.rebuild_links().unwrap(), 0);
+    assert_eq!(tree.len().unwrap(), 2000);
+    assert_eq!(tree.last().unwrap().unwrap().0, 4999);
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_links.dat");
+    println!("✓ Links consistent after merges");
+
+    println!("✓ Rebuild links test passed!\n");
+}
+
+fn test_stats() {
+    println!("=== Test 17: Tree Statistics ===");
+
+    let _ = BPlusTree::remove_files("bptree_stats.dat");
+    let mut tree = BPlusTree::open("bptree_stats.dat").expect("Failed to create tree");
+    let flushes = tree.stats().unwrap().flushes;
+    let data = [5u8; DATA_SIZE];
+    for key in 0..5000 {
+        tree.write_data(key, &data).unwrap();
+    }
+    let stats = tree.stats().unwrap();
+    assert_eq!(stats.keys, 5000);
+    assert_eq!(stats.height, stats.levels.len());
+    assert!(stats.height >= 2);
+    assert_eq!(stats.levels[0].pages, 1);
+    assert_eq!(stats.levels.last().unwrap().pages, stats.leaf_pages);
+    assert!(stats.splits > 0 && stats.root_changes > 0);
+    assert_eq!(stats.merges, 0);
+    assert_eq!(stats.flushes - flushes, 5000);
+    println!(
+        "✓ Height {}, {} leaves, {} internal pages, leaf fill {:.2} (min {:.2})",
+        stats.height,
+        stats.leaf_pages,
+        stats.internal_pages,
+        stats.levels.last().unwrap().avg_fill,
+        stats.levels.last().unwrap().min_fill
+    );
+
+    tree.delete_range(0..4500).unwrap();
+    let after = tree.stats().unwrap();
+    assert_eq!(after.keys, 500);
+    assert!(after.merges > 0);
+    assert!(after.free_pages > 0);
+    assert!(after.leaf_pages < stats.leaf_pages);
+    assert_eq!(after.file_size, stats.file_size);
+    assert!(after.bytes_synced > stats.bytes_synced);
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_stats.dat");
+    println!(
+        "✓ {} merges, {} borrows, {} free pages after deletes",
+        after.merges, after.borrows, after.free_pages
+    );
+
+    println!("✓ Tree statistics test passed!\n");
+}
+
+fn test_export() {
+    println!("=== Test 18: Tree Export ===");
+
+    let _ = BPlusTree::remove_files("bptree_export.dat");
+    let mut tree = BPlusTree::open("bptree_export.dat").expect("Failed to create tree");
+    let data = [6u8; DATA_SIZE];
+    for key in 0..2000 {
+        tree.write_data(key, &data).unwrap();
+    }
+    let stats = tree.stats().unwrap();
+    let json = tree.export_json(None, None).unwrap();
+    assert_eq!(json.matches("\"kind\": \"leaf\"").count(), stats.leaf_pages);
+    assert_eq!(
+        json.matches("\"kind\": \"internal\"").count(),
+        stats.internal_pages
+    );
+    assert!(!json.contains("\"violations\": [\""));
+    let dot = tree.export_dot(None, Some(0)).unwrap();
+    assert_eq!(dot.matches("[label=\"{").count(), 1);
+    assert!(tree.export_dot(Some(1_000_000), None).is_err());
+    println!(
+        "✓ Exported {} leaves and {} internal pages",
+        stats.leaf_pages, stats.internal_pages
+    );
+    drop(tree);
+
+    let mut bytes = std::fs::read("bptree_export.dat").unwrap();
+    let leaf = (1..bytes.len() / 4096)
+        .find(|page| bytes[page * 4096] == 1)
+        .unwrap();
+    bytes[leaf * 4096 + 4000] ^= 0x01;
+    std::fs::write("bptree_export.dat", &bytes).unwrap();
+    let tree = BPlusTree::open("bptree_export.dat").unwrap();
+    let dot = tree.export_dot(None, None).unwrap();
+    let red: Vec<_> = dot
+        .lines()
+        .filter(|line| line.contains("color=red"))
+        .collect();
+    assert_eq!(red.len(), 1);
+    assert!(red[0].starts_with(&format!("  p{leaf} ")));
+    assert!(red[0].contains("checksum mismatch"));
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_export.dat");
+    println!("✓ Damaged leaf {leaf} highlighted");
+
+    println!("✓ Tree export test passed!\n");
+}
+
+#[derive(Default)]
+struct SplitAlarm {
+    splits: AtomicU64,
+    merges: AtomicU64,
+    roots: Mutex<Vec<i32>>,
+}
+
+impl Observer for SplitAlarm {
+    fn on_split(&self, _page: usize, _new_page: usize) {
+        self.splits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_merge(&self, _left: usize, _right: usize) {
+        self.merges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_root_change(&self, _old_root: i32, new_root: i32) {
+        self.roots.lock().unwrap().push(new_root);
+    }
+}
+
+fn test_observer() {
+    println!("=== Test 19: Observers ===");
+
+    let _ = BPlusTree::remove_files("bptree_observer.dat");
+    let mut tree = BPlusTree::open("bptree_observer.dat").expect("Failed to create tree");
+    let alarm = Arc::new(SplitAlarm::default());
+    tree.register_observer(alarm.clone());
+    let data = [7u8; DATA_SIZE];
+    for key in 0..3000 {
+        tree.write_data(key, &data).unwrap();
+    }
+    let stats = tree.stats().unwrap();
+    assert_eq!(alarm.splits.load(Ordering::Relaxed), stats.splits);
+    assert!(alarm.splits.load(Ordering::Relaxed) > 0);
+    assert!(!alarm.roots.lock().unwrap().is_empty());
+    println!(
+        "✓ Observed {} splits and {} root changes",
+        alarm.splits.load(Ordering::Relaxed),
+        alarm.roots.lock().unwrap().len()
+    );
+
+    for key in 0..3000 {
+        tree.delete(key).unwrap();
+    }
+    assert_eq!(
+        alarm.merges.load(Ordering::Relaxed),
+        tree.stats().unwrap().merges
+    );
+    assert!(alarm.merges.load(Ordering::Relaxed) > 0);
+    let roots = alarm.roots.lock().unwrap().len() as u64;
+    assert_eq!(roots, tree.stats().unwrap().root_changes);
+    assert!(roots >= 2);
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_observer.dat");
+    println!(
+        "✓ Observed {} merges down to an empty tree",
+        alarm.merges.load(Ordering::Relaxed)
+    );
+
+    println!("✓ Observer test passed!\n");
+}
+
+fn test_change_feed() {
+    println!("=== Test 20: Change Feed ===");
+
+    let _ = BPlusTree::remove_files("bptree_feed.dat");
+    let mut tree = BPlusTree::open("bptree_feed.dat").expect("Failed to create tree");
+    tree.enable_change_log().unwrap();
+    let feed = tree.subscribe().unwrap();
+    let (a, b) = ([1u8; DATA_SIZE], [2u8; DATA_SIZE]);
+    tree.write_data(1, &a).unwrap();
+    tree.write_data(1, &b).unwrap();
+    tree.write_data(2, &a).unwrap();
+    tree.delete(1).unwrap();
+    tree.delete(3).unwrap();
+
+    let events: Vec<_> = feed.try_iter().collect();
+    let expected = [
+        Change::Put {
+            key: 1,
+            old: None,
+            new: a,
+        },
+        Change::Put {
+            key: 1,
+            old: Some(a),
+            new: b,
+        },
+        Change::Put {
+            key: 2,
+            old: None,
+            new: a,
+        },
+        Change::Delete { key: 1, old: b },
+    ];
+    assert_eq!(events.len(), expected.len());
+    for (i, (event, change)) in events.iter().zip(&expected).enumerate() {
+        assert_eq!(event.sequence, i as u64 + 1);
+        assert_eq!(&event.change, change);
+    }
+    assert_eq!(tree.sequence(), 4);
+    println!("✓ Subscriber saw {} ordered changes", events.len());
+
+    let mut tx = tree.begin().unwrap();
+    tx.put(10, &a).unwrap();
+    let savepoint = tx.savepoint();
+    tx.put(11, &a).unwrap();
+    tx.rollback_to(&savepoint).unwrap();
+    tx.commit().unwrap();
+    let mut tx = tree.begin().unwrap();
+    tx.put(12, &a).unwrap();
+    tx.rollback();
+    tree.delete_range(0..=2).unwrap();
+    let keys: Vec<_> = feed.try_iter().map(|event| event.change.key()).collect();
+    assert_eq!(keys, vec![10, 2]);
+    println!("✓ Rolled back changes are not published");
+
+    let mut log = ChangeLog::open("bptree_feed.dat").unwrap();
+    let logged = log.poll().unwrap();
+    assert_eq!(logged.len(), 6);
+    assert_eq!(&logged[..4], &events[..]);
+    assert!(log.poll().unwrap().is_empty());
+    tree.write_data(20, &b).unwrap();
+    let tail = log.poll().unwrap();
+    assert_eq!(tail.len(), 1);
+    assert_eq!(tail[0].sequence, 7);
+    log.seek(5).unwrap();
+    assert_eq!(log.poll().unwrap().len(), 3);
+    println!("✓ Change log tails committed changes from any position");
+
+    drop(tree);
+    let mut bytes = std::fs::read("bptree_feed.dat.changes").unwrap();
+    let record = bytes[bytes.len() - 220..].to_vec();
+    bytes.extend_from_slice(&record);
+    std::fs::write("bptree_feed.dat.changes", &bytes).unwrap();
+    let mut tree = BPlusTree::open("bptree_feed.dat").unwrap();
+    assert_eq!(tree.sequence(), 7);
+    tree.delete(20).unwrap();
+    let mut log = ChangeLog::open("bptree_feed.dat").unwrap();
+    log.seek(8).unwrap();
+    let after = log.poll().unwrap();
+    assert_eq!(after.len(), 1);
+    assert_eq!(after[0].change, Change::Delete { key: 20, old: b });
+    drop(tree);
+    let _ = BPlusTree::remove_files("bptree_feed.dat");
+    println!("✓ Uncommitted log tail dropped on open");
+
+    println!("✓ Change feed test passed!\n");
+}
+
+fn test_replication() {
+    println!("=== Test 21: Replication ===");
+
+    let files = ["bptree_primary.dat", "bptree_follower.dat"];
+    for path in files {
+        let _ = BPlusTree::remove_files(path);
+    }
+    let mut primary = BPlusTree::open("bptree_primary.dat").expect("Failed to create tree");
+    primary.enable_change_log().unwrap();
+    for key in 0..200 {
+        primary.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+    primary.delete_range(50..100).unwrap();
+    primary.write_data(7, &[0xAA; DATA_SIZE]).unwrap();
+
+    let mut follower = Follower::open("bptree_follower.dat", "bptree_primary.dat").unwrap();
+    assert_eq!(follower.applied(), 0);
+    assert_eq!(follower.catch_up().unwrap(), 251);
+    assert_eq!(follower.applied(), primary.sequence());
+    assert_eq!(follower.tree().read(7).unwrap(), Some([0xAA; DATA_SIZE]));
+    assert_eq!(follower.tree().read(60).unwrap(), None);
+    assert_eq!(follower.tree().len().unwrap(), 150);
+    assert_eq!(follower.catch_up().unwrap(), 0);
+    println!("✓ Follower applied {} changes", follower.applied());
+
+    assert!(BPlusTree::open("bptree_follower.dat").is_err());
+    primary.delete(199).unwrap();
+    drop(follower);
+    let mut follower = Follower::open("bptree_follower.dat", "bptree_primary.dat").unwrap();
+    assert_eq!(follower.catch_up().unwrap(), 1);
+    assert_eq!(follower.tree().read(199).unwrap(), None);
+    println!("✓ Follower is read-only and resumes from its applied sequence");
+
+    let mut promoted = follower.promote().unwrap();
+    promoted.write_data(500, &[5u8; DATA_SIZE]).unwrap();
+    drop(promoted);
+    let reopened = BPlusTree::open("bptree_follower.dat").unwrap();
+    assert_eq!(reopened.read(500).unwrap(), Some([5u8; DATA_SIZE]));
+    assert_eq!(reopened.len().unwrap(), 150);
+    drop(reopened);
+    drop(primary);
+    for path in files {
+        let _ = BPlusTree::remove_files(path);
+    }
+    println!("✓ Promoted follower takes writes");
+
+    println!("✓ Replication test passed!\n");
+}
+
+fn test_backup() {
+    println!("=== Test 22: Backup and Restore ===");
+
+    let files = [
+        "bptree_live.dat",
+        "bptree_full.bak",
+        "bptree_incr1.bak",
+        "bptree_incr2.bak",
+        "bptree_restored.dat",
+        "bptree_pitr.dat",
+    ];
+    for path in files {
+        let _ = BPlusTree::remove_files(path);
+    }
+    let mut tree = BPlusTree::open("bptree_live.dat").expect("Failed to create tree");
+    tree.enable_change_log().unwrap();
+    for key in 0..2000 {
+        tree.write_data(key, &[1u8; DATA_SIZE]).unwrap();
+    }
+
+    // The backup runs on its own thread while this one keeps writing.
+    let snapshot = tree.snapshot();
+    let full = std::thread::scope(|scope| {
+        let backup = scope.spawn(|| snapshot.backup_to("bptree_full.bak").unwrap());
+        for key in 2000..2500 {
+            tree.write_data(key, &[2u8; DATA_SIZE]).unwrap();
+        }
+        backup.join().unwrap()
+    });
+    drop(snapshot);
+    assert_eq!(full, 2000);
+    assert!(tree.backup_to("bptree_full.bak").is_err());
+    let copy = BPlusTree::open("bptree_full.bak").unwrap();
+    assert_eq!(copy.len().unwrap(), 2000);
+    copy.verify().unwrap();
+    drop(copy);
+    let _ = std::fs::remove_file("bptree_full.bak.wal");
+    let _ = std::fs::remove_file("bptree_full.bak.changes");
+    println!("✓ Full backup at sequence {full} opens as an index");
+
+    tree.write_data(5, &[3u8; DATA_SIZE]).unwrap();
+    tree.delete(6).unwrap();
+    let first = tree
+        .backup_incremental_to("bptree_full.bak", "bptree_incr1.bak")
+        .unwrap();
+    tree.write_data(7, &[4u8; DATA_SIZE]).unwrap();
+    let second = tree
+        .backup_incremental_to("bptree_incr1.bak", "bptree_incr2.bak")
+        .unwrap();
+    let full_size = std::fs::metadata("bptree_full.bak").unwrap().len();
+    let incr_size = std::fs::metadata("bptree_incr2.bak").unwrap().len();
+    assert!(incr_size * 10 < full_size);
+    assert_eq!((first, second), (2502, 2503));
+    println!("✓ Incremental backup is {incr_size} bytes against {full_size}");
+
+    let restored = BPlusTree::restore(
+        &["bptree_full.bak", "bptree_incr1.bak", "bptree_incr2.bak"],
+        "bptree_restored.dat",
+    )
+    .unwrap();
+    assert_eq!(restored, second);
+    let copy = BPlusTree::open("bptree_restored.dat").unwrap();
+    copy.verify().unwrap();
+    assert_eq!(copy.read(5).unwrap(), Some([3u8; DATA_SIZE]));
+    assert_eq!(copy.read(6).unwrap(), None);
+    assert_eq!(copy.read(7).unwrap(), Some([4u8; DATA_SIZE]));
+    assert_eq!(copy.sequence(), second);
+    drop(copy);
+    assert!(
+        BPlusTree::restore(&["bptree_full.bak", "bptree_incr2.bak"], "bptree_pitr.dat").is_err()
+    );
+    assert!(!std::path::Path::new("bptree_pitr.dat").exists());
+    println!("✓ Restored full and incremental backups");
+
+    let restored = BPlusTree::restore_until(
+        &["bptree_full.bak", "bptree_incr1.bak", "bptree_incr2.bak"],
+        "bptree_live.dat",
+        2501,
+        "bptree_pitr.dat",
+    )
+    .unwrap();
+    assert_eq!(restored, 2501);
+    let copy = BPlusTree::open("bptree_pitr.dat").unwrap();
+    copy.verify().unwrap();
+    assert_eq!(copy.read(5).unwrap(), Some([3u8; DATA_SIZE]));
+    assert_eq!(copy.read(6).unwrap(), Some([1u8; DATA_SIZE]));
+    assert_eq!(copy.read(7).unwrap(), Some([1u8; DATA_SIZE]));
+    assert_eq!(copy.read(2400).unwrap(), Some([2u8; DATA_SIZE]));
+    assert_eq!(copy.sequence(), 2501);
+    drop(copy);
+    drop(tree);
+    for path in files {
+        let _ = BPlusTree::remove_files(path);
+    }
+    println!("✓ Point-in-time restore replayed the change log to sequence 2501");
+
+    println!("✓ Backup test passed!\n");
+}
+
 fn test_bulk_insert() {
-    println!("=== Test 6: Bulk Insert (1000 entries) ===");
+    println!("=== Test 23: Bulk Insert (1000 entries) ===");
 
     let mut tree = BPlusTree::new().expect("Failed to create tree");
 
@@ -137,13 +1032,13 @@ fn test_bulk_insert() {
 
     println!("✓ Inserted 1000 entries in {:?}", duration);
 
-    let result = tree.read(125).expect("Key 125 not found");
+    let result = tree.read(125).unwrap().expect("Key 125 not found");
     println!(
         "✓ Read key 125: {}",
         String::from_utf8_lossy(&result[..20]).trim_end_matches('\0')
     );
 
-    let result = tree.read(875).expect("Key 875 not found");
+    let result = tree.read(875).unwrap().expect("Key 875 not found");
     println!(
         "✓ Read key 875: {}",
         String::from_utf8_lossy(&result[..20]).trim_end_matches('\0')
@@ -153,7 +1048,7 @@ fn test_bulk_insert() {
 }
 
 fn test_negative_keys() {
-    println!("=== Test 7: Negative Keys ===");
+    println!("=== Test 24: Negative Keys ===");
 
     let mut tree = BPlusTree::new().expect("Failed to create tree");
 
@@ -162,7 +1057,7 @@ fn test_negative_keys() {
 
     assert!(tree.write_data(-100, &data).unwrap());
 
-    let result = tree.read(-100).expect("Key -100 not found");
+    let result = tree.read(-100).unwrap().expect("Key -100 not found");
     assert_eq!(&result[..17], &data[..17]);
     println!(
         "✓ Read negative key -100: {}",
@@ -173,7 +1068,7 @@ fn test_negative_keys() {
 }
 
 fn test_persistence() {
-    println!("=== Test 8: Persistence Check ===");
+    println!("=== Test 25: Persistence Check ===");
 
     {
         let mut tree = BPlusTree::new().expect("Failed to create tree");
@@ -187,7 +1082,10 @@ fn test_persistence() {
 
     {
         let tree = BPlusTree::new().expect("Failed to create tree");
-        let result = tree.read(9999).expect("Key 9999 not found after restart");
+        let result = tree
+            .read(9999)
+            .unwrap()
+            .expect("Key 9999 not found after restart");
         assert_eq!(&result[..16], b"Persistent data!");
         println!(
             "✓ Read key 9999 after restart: {}",
@@ -195,13 +1093,101 @@ fn test_persistence() {
         );
     }
 
+    // Only an empty file counts as new; a partial meta page is a damaged one
+    let _ = BPlusTree::remove_files("bptree_short.dat");
+    std::fs::write("bptree_short.dat", [0u8; 100]).unwrap();
+    let err = BPlusTree::open("bptree_short.dat").err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(std::fs::metadata("bptree_short.dat").unwrap().len(), 100);
+    let _ = BPlusTree::remove_files("bptree_short.dat");
+    println!("✓ File shorter than a page is rejected, not reinitialized");
+
     println!("✓ Persistence test passed!\n");
 }
 
+fn test_snapshot() {
+    println!("=== Test 26: Snapshot Isolation ===");
+
+    let _ = BPlusTree::remove_files("bptree_index.dat");
+    let mut tree = BPlusTree::new().expect("Failed to create tree");
+
+    for i in 2000..2100 {
+        let mut data = [0u8; DATA_SIZE];
+        let text = format!("Snapshot data {}", i);
+        data[..text.len()].copy_from_slice(text.as_bytes());
+        tree.write_data(i, &data).unwrap();
+    }
+
+    let snapshot = tree.snapshot();
+
+    for i in 2000..2050 {
+        tree.delete(i).unwrap();
+    }
+    for i in 2100..2500 {
+        let data = [1u8; DATA_SIZE];
+        tree.write_data(i, &data).unwrap();
+    }
+
+    let results = snapshot.read_range_data(2000, 2499).unwrap();
+    assert_eq!(results.len(), 100);
+    assert!(snapshot.read(2010).unwrap().is_some());
+    assert!(snapshot.read(2200).unwrap().is_none());
+    println!("✓ Snapshot still sees {} original entries", results.len());
+
+    assert!(tree.read(2010).unwrap().is_none());
+    assert!(tree.read(2200).unwrap().is_some());
+    println!("✓ Tree sees writes made after the snapshot");
+
+    drop(snapshot);
+    println!("✓ Snapshot test passed!\n");
+}
+
+fn test_transaction() {
+    println!("=== Test 27: Transactions ===");
+
+    let mut tree = BPlusTree::new().expect("Failed to create tree");
+
+    let mut data = [0u8; DATA_SIZE];
+    data[..16].copy_from_slice(b"Committed record");
+
+    let mut tx = tree.begin().unwrap();
+    tx.put(3000, &data).unwrap();
+    tx.put(3001, &data).unwrap();
+    assert!(tx.get(3000).unwrap().is_some());
+    tx.commit().unwrap();
+    println!("✓ Committed keys 3000 and 3001");
+
+    let mut tx = tree.begin().unwrap();
+    tx.delete(3000).unwrap();
+    tx.put(3002, &data).unwrap();
+    assert!(tx.get(3000).unwrap().is_none());
+    tx.rollback();
+
+    assert!(tree.read(3000).unwrap().is_some());
+    assert!(tree.read(3002).unwrap().is_none());
+    println!("✓ Rolled back delete of 3000 and insert of 3002");
+
+    let mut tx = tree.begin().unwrap();
+    tx.put(3003, &data).unwrap();
+    let savepoint = tx.savepoint();
+    for i in 3100..3300 {
+        tx.put(i, &data).unwrap();
+    }
+    tx.rollback_to(&savepoint).unwrap();
+    tx.release(savepoint).unwrap();
+    tx.commit().unwrap();
+
+    assert!(tree.read(3003).unwrap().is_some());
+    assert!(tree.read(3150).unwrap().is_none());
+    println!("✓ Rolled back to savepoint, keeping key 3003");
+
+    println!("✓ Transaction test passed!\n");
+}
+
 fn test_stress() {
-    println!("=== Test 9: Stress Test (10000 operations) ===");
+    println!("=== Test 28: Stress Test (10000 operations) ===");
 
-    let _ = std::fs::remove_file("bptree_index.dat");
+    let _ = BPlusTree::remove_files("bptree_index.dat");
     let mut tree = BPlusTree::new().expect("Failed to create tree");
 
     let start = Instant::now();
@@ -221,7 +1207,7 @@ fn test_stress() {
     // Read & verify safely
     let mut failed = 0;
     for i in 10000..20000 {
-        match tree.read(i) {
+        match tree.read(i).unwrap() {
             Some(_data) => {} // ok
             None => {
                 failed += 1;
@@ -236,6 +1222,8 @@ fn test_stress() {
     if failed == 0 {
         println!("✓ 10000 inserts + 10000 reads in {:?}", duration);
         println!("✓ Average time per operation: {:?}", duration / 20000);
+        tree.verify().expect("Tree structure is inconsistent");
+        println!("✓ Tree structure verified");
         println!("✓ Stress test passed!\n");
     } else {
         println!("✗ {} keys were not found out of 10000", failed);
@@ -263,7 +1251,7 @@ fn benchmark_operations() {
     let start = Instant::now();
     let mut read_failures = 0;
     for i in 0..n {
-        if tree.read(i).is_none() {
+        if tree.read(i).unwrap().is_none() {
             read_failures += 1;
         }
     }
@@ -279,7 +1267,7 @@ fn benchmark_operations() {
     // Range benchmark
     let start = Instant::now();
     for _ in 0..100 {
-        tree.read_range_data(100, 200);
+        tree.read_range_data(100, 200).unwrap();
     }
     let range_duration = start.elapsed();
 
@@ -300,22 +1288,80 @@ fn benchmark_operations() {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 4 && args[1] == "repair" {
+        match BPlusTree::repair(&args[2], &args[3]) {
+            Ok(report) => print!("{report}"),
+            Err(e) => {
+                eprintln!("repair failed: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    // restore <output> <full> [<incremental>...] [--until <primary> <sequence>]
+    if args.len() >= 4 && args[1] == "restore" {
+        let mut backups = &args[3..];
+        let result = match backups.iter().position(|arg| arg == "--until") {
+            Some(i) if backups.len() == i + 3 => {
+                let (primary, sequence) = (&backups[i + 1], &backups[i + 2]);
+                backups = &backups[..i];
+                match sequence.parse() {
+                    Ok(sequence) => BPlusTree::restore_until(backups, primary, sequence, &args[2]),
+                    Err(e) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)),
+                }
+            }
+            Some(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "--until takes the primary index and a sequence number",
+            )),
+            None => BPlusTree::restore(backups, &args[2]),
+        };
+        match result {
+            Ok(sequence) => println!("restored {} to sequence {sequence}", args[2]),
+            Err(e) => {
+                eprintln!("restore failed: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     println!("========================================");
     println!("   B+ Tree Index Driver Test Program   ");
     println!("   (Rust Implementation)               ");
     println!("========================================\n");
 
     // Remove old index file for fresh start
-    let _ = std::fs::remove_file("bptree_index.dat");
+    let _ = BPlusTree::remove_files("bptree_index.dat");
 
     test_basic_operations();
     test_non_existent_key();
     test_update();
+    test_conditional_writes();
+    test_entry_api();
     test_delete();
     test_range_query();
+    test_range_delete_update();
+    test_order_statistics();
+    test_first_last();
+    test_multimap();
+    test_secondary_index();
+    test_named_trees();
+    test_checksums();
+    test_repair();
+    test_rebuild_links();
+    test_stats();
+    test_export();
+    test_observer();
+    test_change_feed();
+    test_replication();
+    test_backup();
     test_bulk_insert();
     test_negative_keys();
     test_persistence();
+    test_snapshot();
+    test_transaction();
     test_stress();
     benchmark_operations();
 
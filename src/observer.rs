@@ -0,0 +1,51 @@
+use crate::pager::Pager;
+use crate::BPlusTree;
+use std::sync::Arc;
+
+// Told about structural changes as they happen, for logging or alerting on things like split
+// storms. Observers see every tree in the file, and changes a transaction later rolls back are
+// reported too. Callbacks run with the tree locked and must not call back into it.
+pub trait Observer: Send + Sync {
+    // `page` overflowed and its upper half moved to `new_page`.
+    fn on_split(&self, _page: usize, _new_page: usize) {}
+
+    // `right` was merged into its left sibling `left` and freed.
+    fn on_merge(&self, _left: usize, _right: usize) {}
+
+    // `page` took an entry from its sibling `from`.
+    fn on_borrow(&self, _page: usize, _from: usize) {}
+
+    // -1 stands for an empty tree.
+    fn on_root_change(&self, _old_root: i32, _new_root: i32) {}
+
+    fn on_page_alloc(&self, _page: usize) {}
+
+    // A commit synced `bytes` of WAL record and pages. An explicit `flush` reports 0, since its
+    // pages were already synced by their commits.
+    fn on_flush(&self, _bytes: u64) {}
+
+    // A node read found `page` did not match its checksum. Under `ChecksumPolicy::Log` this is
+    // the only report of it.
+    fn on_checksum_failure(&self, _page: usize) {}
+}
+
+impl Pager {
+    // The stats counters first, then the registered observers in the order they were added.
+    pub(crate) fn notify(&self, event: impl Fn(&dyn Observer)) {
+        event(&self.counters);
+        for observer in &self.observers {
+            event(observer.as_ref());
+        }
+    }
+}
+
+impl BPlusTree {
+    pub(crate) fn notify(&self, event: impl Fn(&dyn Observer)) {
+        self.pager.read().unwrap().notify(event);
+    }
+
+    // Adds an observer for every tree in the file, for as long as it stays open.
+    pub fn register_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.pager.write().unwrap().observers.push(observer);
+    }
+}
@@ -0,0 +1,740 @@
+use crate::changes::ChangeFeed;
+use crate::checksum::{page_checksum, seal};
+use crate::stats::Counters;
+use crate::verify::corrupt;
+use crate::{ChecksumPolicy, Observer, PAGE_SIZE};
+use memmap2::{MmapMut, MmapOptions};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
+
+const META_PAGE: usize = 0;
+const META_MAGIC: &[u8; 8] = b"BPTREE07";
+pub(crate) const META_ROOT: usize = 8;
+pub(crate) const META_NUM_PAGES: usize = 12;
+const META_FREE_COUNT: usize = 16;
+const META_FREE_TRUNK: usize = 20;
+const META_FLAGS: usize = 24;
+pub(crate) const META_CATALOG: usize = 28;
+const META_SEQUENCE: usize = 32;
+pub(crate) const META_CHECKSUM: usize = 40;
+const META_HEADER_SIZE: usize = 44;
+
+pub(crate) const FLAG_MULTIMAP: u32 = 1;
+pub(crate) const FLAG_CHANGE_LOG: u32 = 2;
+pub(crate) const FLAG_FOLLOWER: u32 = 4;
+
+const TRUNK_NEXT: usize = 0;
+const TRUNK_COUNT: usize = 4;
+const TRUNK_CHECKSUM: usize = 8;
+const TRUNK_HEADER_SIZE: usize = 12;
+
+// Free page numbers that fit on the meta page and on each trunk page.
+const META_CAPACITY: usize = (PAGE_SIZE - META_HEADER_SIZE) / 4;
+const TRUNK_CAPACITY: usize = (PAGE_SIZE - TRUNK_HEADER_SIZE) / 4;
+
+const WAL_MAGIC: u32 = 0x5741_4c31;
+
+fn get_u32(page: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap())
+}
+
+fn set_u32(page: &mut [u8], offset: usize, value: u32) {
+    page[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+pub(crate) struct Meta {
+    pub(crate) root_page: i32,
+    pub(crate) num_pages: usize,
+    pub(crate) flags: u32,
+    pub(crate) catalog: i32,
+    pub(crate) sequence: u64,
+    pub(crate) free_pages: Vec<usize>,
+    // The first of the meta and trunk pages read whose checksum does not match.
+    pub(crate) damaged: Option<usize>,
+}
+
+fn checksum_matches(page: &[u8], offset: usize) -> bool {
+    let page: &[u8; PAGE_SIZE] = page.try_into().unwrap();
+    get_u32(page, offset) == page_checksum(page, offset)
+}
+
+// Decodes the meta page and the free-list trunks behind it, trunk pages included. `page`
+// returns None past the end of the file; a trunk chain that runs off it or loops is cut short.
+pub(crate) fn parse_meta<'a>(page: impl Fn(usize) -> Option<&'a [u8]>) -> Option<Meta> {
+    let meta = page(META_PAGE)?;
+    if &meta[..META_MAGIC.len()] != META_MAGIC {
+        return None;
+    }
+    let mut damaged = (!checksum_matches(meta, META_CHECKSUM)).then_some(META_PAGE);
+
+    let count = (get_u32(meta, META_FREE_COUNT) as usize).min(META_CAPACITY);
+    let mut free_pages: Vec<usize> = (0..count)
+        .map(|i| get_u32(meta, META_HEADER_SIZE + i * 4) as usize)
+        .collect();
+    let mut trunk = get_u32(meta, META_FREE_TRUNK);
+    while trunk != u32::MAX && !free_pages.contains(&(trunk as usize)) {
+        let Some(page) = page(trunk as usize) else {
+            break;
+        };
+        if damaged.is_none() && !checksum_matches(page, TRUNK_CHECKSUM) {
+            damaged = Some(trunk as usize);
+        }
+        free_pages.push(trunk as usize);
+        let count = (get_u32(page, TRUNK_COUNT) as usize).min(TRUNK_CAPACITY);
+        for i in 0..count {
+            free_pages.push(get_u32(page, TRUNK_HEADER_SIZE + i * 4) as usize);
+        }
+        trunk = get_u32(page, TRUNK_NEXT);
+    }
+
+    Some(Meta {
+        root_page: get_u32(meta, META_ROOT) as i32,
+        num_pages: get_u32(meta, META_NUM_PAGES) as usize,
+        flags: get_u32(meta, META_FLAGS),
+        catalog: get_u32(meta, META_CATALOG) as i32,
+        sequence: u64::from_le_bytes(meta[META_SEQUENCE..META_SEQUENCE + 8].try_into().unwrap()),
+        free_pages,
+        damaged,
+    })
+}
+
+// The files kept next to an index: its write-ahead log and its change log.
+pub(crate) const SIDECARS: [&str; 2] = [".wal", ".changes"];
+
+pub(crate) fn sidecar(path: &Path, extension: &str) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(extension);
+    PathBuf::from(sidecar)
+}
+
+// Deletes an index file with its write-ahead log and change log. Files that are already gone
+// are skipped.
+pub(crate) fn remove_files(path: &Path) -> Result<()> {
+    let sidecars = SIDECARS.map(|extension| sidecar(path, extension));
+    for file in std::iter::once(path.to_path_buf()).chain(sidecars) {
+        match std::fs::remove_file(&file) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Page images from the intact records at the start of a write-ahead log, oldest first. A torn
+// or partly written record and everything after it is ignored.
+pub(crate) fn wal_pages(log: &[u8]) -> Vec<(usize, &[u8])> {
+    let record_page = 4 + PAGE_SIZE;
+    let mut pages = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= log.len() {
+        if get_u32(log, offset) != WAL_MAGIC {
+            break;
+        }
+        let count = get_u32(log, offset + 4) as usize;
+        let end = offset + 8 + count * record_page;
+        if end + 4 > log.len() || crc32c::crc32c(&log[offset..end]) != get_u32(log, end) {
+            break;
+        }
+        for i in 0..count {
+            let start = offset + 8 + i * record_page;
+            pages.push((
+                get_u32(log, start) as usize,
+                &log[start + 4..start + record_page],
+            ));
+        }
+        offset = end + 4;
+    }
+    pages
+}
+
+// What reached the file layer, in order, for the crash tests to replay onto a simulated disk.
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub(crate) enum IoEvent {
+    WalWrite(Vec<u8>),
+    WalTruncate,
+    WalSync,
+    PageWrite(usize, Vec<u8>),
+    // One page, or the whole file.
+    PageSync(Option<usize>),
+}
+
+struct SavepointState {
+    id: u64,
+    root_page: i32,
+    num_pages: usize,
+    free_pages: Vec<usize>,
+    changes: usize,
+    undo: HashMap<usize, Option<Box<[u8; PAGE_SIZE]>>>,
+}
+
+struct SnapshotPages {
+    num_pages: usize,
+    remap: HashMap<usize, usize>,
+}
+
+// Page 0 is the meta page holding the committed root, page count, mode flags, catalog page,
+// last change sequence and free list. All changes are staged in `dirty` and reach the file only
+// through `commit`, which first writes the page images to the write-ahead log so a crash either
+// loses the whole batch or replays all of it.
+pub(crate) struct Pager {
+    file: File,
+    wal: File,
+    mmap: MmapMut,
+    pub(crate) root_page: i32,
+    pub(crate) flags: u32,
+    pub(crate) catalog: i32,
+    pub(crate) checksum_policy: ChecksumPolicy,
+    pub(crate) checksum_failures: AtomicU64,
+    pub(crate) counters: Counters,
+    pub(crate) observers: Vec<Arc<dyn Observer>>,
+    pub(crate) changes: ChangeFeed,
+    num_pages: usize,
+    free_pages: Vec<usize>,
+    committed_num_pages: usize,
+    committed_free_pages: Vec<usize>,
+    free_list_changed: bool,
+    dirty: HashMap<usize, Box<[u8; PAGE_SIZE]>>,
+    savepoints: Vec<SavepointState>,
+    next_savepoint_id: u64,
+    next_snapshot_id: u64,
+    snapshots: HashMap<u64, SnapshotPages>,
+    pub(crate) shadow_refs: HashMap<usize, usize>,
+    #[cfg(test)]
+    pub(crate) journal: Mutex<Option<Vec<IoEvent>>>,
+}
+
+impl Pager {
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let wal = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(sidecar(path, ".wal"))?;
+
+        let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+
+        let mut pager = Pager {
+            file,
+            wal,
+            mmap,
+            root_page: -1,
+            flags: 0,
+            catalog: -1,
+            checksum_policy: ChecksumPolicy::Error,
+            checksum_failures: AtomicU64::new(0),
+            counters: Counters::default(),
+            observers: Vec::new(),
+            changes: ChangeFeed::new(path),
+            num_pages: 1,
+            free_pages: Vec::new(),
+            committed_num_pages: 1,
+            committed_free_pages: Vec::new(),
+            free_list_changed: true,
+            dirty: HashMap::new(),
+            savepoints: Vec::new(),
+            next_savepoint_id: 0,
+            next_snapshot_id: 0,
+            snapshots: HashMap::new(),
+            shadow_refs: HashMap::new(),
+            #[cfg(test)]
+            journal: Mutex::new(None),
+        };
+
+        pager.recover()?;
+
+        // Only an empty file is new. Anything shorter than the meta page is damaged, and
+        // starting it over would lose whatever it held.
+        match pager.mmap.len() {
+            0 => {}
+            len if len < PAGE_SIZE => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{len}-byte file is too short for a B+ tree index"),
+                ));
+            }
+            _ => pager.load_meta()?,
+        }
+        if pager.flags & FLAG_CHANGE_LOG != 0 {
+            pager.changes.open_log()?;
+        }
+
+        Ok(pager)
+    }
+
+    fn recover(&mut self) -> Result<()> {
+        let mut log = Vec::new();
+        (&self.wal).read_to_end(&mut log)?;
+
+        let pages = wal_pages(&log);
+        for (page_num, image) in &pages {
+            self.ensure_file_size(page_num + 1)?;
+            self.committed_page_mut(*page_num).copy_from_slice(image);
+            #[cfg(test)]
+            self.record(|| IoEvent::PageWrite(*page_num, image.to_vec()));
+        }
+
+        if !pages.is_empty() {
+            self.mmap.flush()?;
+            #[cfg(test)]
+            self.record(|| IoEvent::PageSync(None));
+        }
+        if !log.is_empty() {
+            self.wal.set_len(0)?;
+            self.wal.sync_all()?;
+            #[cfg(test)]
+            {
+                self.record(|| IoEvent::WalTruncate);
+                self.record(|| IoEvent::WalSync);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_meta(&mut self) -> Result<()> {
+        let meta = parse_meta(|page_num| {
+            let start = page_num * PAGE_SIZE;
+            self.mmap.get(start..start + PAGE_SIZE)
+        })
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "not a B+ tree index file"))?;
+        if let Some(page) = meta.damaged {
+            return Err(corrupt(page, "checksum mismatch"));
+        }
+
+        // Every page number the meta page hands out has to exist in the file, or the first read
+        // or allocation through it would run past the mapping.
+        let in_file = |page: i32| page >= 1 && (page as usize) < meta.num_pages;
+        if meta.num_pages == 0
+            || meta.num_pages * PAGE_SIZE > self.mmap.len()
+            || (meta.root_page != -1 && !in_file(meta.root_page))
+            || (meta.catalog != -1 && !in_file(meta.catalog))
+            || meta.free_pages.iter().any(|&page| !in_file(page as i32))
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "corrupt meta page"));
+        }
+
+        self.root_page = meta.root_page;
+        self.flags = meta.flags;
+        self.catalog = meta.catalog;
+        self.changes.sequence = meta.sequence;
+        self.changes.following = meta.flags & FLAG_FOLLOWER != 0;
+        self.num_pages = meta.num_pages;
+        self.free_pages = meta.free_pages;
+        self.committed_num_pages = self.num_pages;
+        self.committed_free_pages = self.free_pages.clone();
+        self.free_list_changed = false;
+        Ok(())
+    }
+
+    fn ensure_file_size(&mut self, pages: usize) -> Result<()> {
+        let required_size = pages * PAGE_SIZE;
+        let current_size = self.mmap.len();
+
+        if required_size > current_size {
+            self.mmap.flush()?;
+            #[cfg(test)]
+            self.record(|| IoEvent::PageSync(None));
+            drop(std::mem::replace(&mut self.mmap, unsafe {
+                MmapOptions::new().len(0).map_mut(&self.file)?
+            }));
+            self.file.set_len(required_size as u64)?;
+            self.mmap = unsafe { MmapOptions::new().map_mut(&self.file)? };
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn allocate_page(&mut self) -> Result<usize> {
+        let page_num = match self.free_pages.pop() {
+            Some(page_num) => page_num,
+            None => {
+                self.num_pages += 1;
+                self.num_pages - 1
+            }
+        };
+        self.record_undo(page_num);
+        self.dirty.insert(page_num, Box::new([0; PAGE_SIZE]));
+        self.notify(|o| o.on_page_alloc(page_num));
+        Ok(page_num)
+    }
+
+    pub(crate) fn free_page(&mut self, page_num: usize) {
+        self.free_pages.push(page_num);
+    }
+
+    pub(crate) fn num_pages(&self) -> usize {
+        self.num_pages
+    }
+
+    pub(crate) fn free_pages(&self) -> &[usize] {
+        &self.free_pages
+    }
+
+    pub(crate) fn file_size(&self) -> u64 {
+        self.mmap.len() as u64
+    }
+
+    fn committed_page(&self, page_num: usize) -> &[u8] {
+        let start = page_num * PAGE_SIZE;
+        let end = start + PAGE_SIZE;
+        &self.mmap[start..end]
+    }
+
+    fn committed_page_mut(&mut self, page_num: usize) -> &mut [u8] {
+        let start = page_num * PAGE_SIZE;
+        let end = start + PAGE_SIZE;
+        &mut self.mmap[start..end]
+    }
+
+    pub(crate) fn get_page(&self, page_num: usize, snapshot: Option<u64>) -> &[u8] {
+        self.counters.page_reads.fetch_add(1, Ordering::Relaxed);
+        let page = self.peek_page(page_num, snapshot);
+        if snapshot.is_some() || !self.dirty.contains_key(&page_num) {
+            self.counters.file_reads.fetch_add(1, Ordering::Relaxed);
+        }
+        page
+    }
+
+    // `get_page` without counting a read, for looking at a page the caller reads in full next.
+    pub(crate) fn peek_page(&self, page_num: usize, snapshot: Option<u64>) -> &[u8] {
+        match snapshot {
+            Some(id) => {
+                let remapped = self
+                    .snapshots
+                    .get(&id)
+                    .and_then(|s| s.remap.get(&page_num).copied());
+                self.committed_page(remapped.unwrap_or(page_num))
+            }
+            None => match self.dirty.get(&page_num) {
+                Some(page) => &page[..],
+                None => self.committed_page(page_num),
+            },
+        }
+    }
+
+    fn get_page_mut(&mut self, page_num: usize) -> &mut [u8] {
+        self.record_undo(page_num);
+        if !self.dirty.contains_key(&page_num) {
+            let mut page = Box::new([0; PAGE_SIZE]);
+            if (page_num + 1) * PAGE_SIZE <= self.mmap.len() {
+                page.copy_from_slice(self.committed_page(page_num));
+            }
+            self.dirty.insert(page_num, page);
+        }
+        &mut self.dirty.get_mut(&page_num).unwrap()[..]
+    }
+
+    pub(crate) fn write_page(&mut self, page_num: usize, bytes: &[u8]) -> Result<()> {
+        self.get_page_mut(page_num)[..bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn record_undo(&mut self, page_num: usize) {
+        if let Some(savepoint) = self.savepoints.last_mut() {
+            savepoint
+                .undo
+                .entry(page_num)
+                .or_insert_with(|| self.dirty.get(&page_num).cloned());
+        }
+    }
+
+    pub(crate) fn savepoint(&mut self, root_page: i32) -> u64 {
+        let id = self.next_savepoint_id;
+        self.next_savepoint_id += 1;
+        self.savepoints.push(SavepointState {
+            id,
+            root_page,
+            num_pages: self.num_pages,
+            free_pages: self.free_pages.clone(),
+            changes: self.changes.pending.len(),
+            undo: HashMap::new(),
+        });
+        id
+    }
+
+    // Restores the dirty pages, allocator and root to their state when the savepoint was taken.
+    // The savepoint stays active; any savepoints taken after it are discarded.
+    pub(crate) fn rollback_to_savepoint(&mut self, id: u64) -> Option<i32> {
+        let index = self.savepoints.iter().position(|s| s.id == id)?;
+        for savepoint in self.savepoints.drain(index + 1..).rev() {
+            for (page_num, image) in savepoint.undo {
+                match image {
+                    Some(image) => self.dirty.insert(page_num, image),
+                    None => self.dirty.remove(&page_num),
+                };
+            }
+        }
+        let savepoint = &mut self.savepoints[index];
+        for (page_num, image) in savepoint.undo.drain() {
+            match image {
+                Some(image) => self.dirty.insert(page_num, image),
+                None => self.dirty.remove(&page_num),
+            };
+        }
+        self.num_pages = savepoint.num_pages;
+        self.free_pages = savepoint.free_pages.clone();
+        self.changes.pending.truncate(savepoint.changes);
+        Some(savepoint.root_page)
+    }
+
+    // Forgets the savepoint and any taken after it, folding their undo records into the
+    // enclosing savepoint so it can still roll back past them.
+    pub(crate) fn release_savepoint(&mut self, id: u64) -> bool {
+        let Some(index) = self.savepoints.iter().position(|s| s.id == id) else {
+            return false;
+        };
+        let released: Vec<SavepointState> = self.savepoints.drain(index..).collect();
+        if let Some(enclosing) = self.savepoints.last_mut() {
+            for savepoint in released {
+                for (page_num, image) in savepoint.undo {
+                    enclosing.undo.entry(page_num).or_insert(image);
+                }
+            }
+        }
+        true
+    }
+
+    // Mode flags reach the file with the next commit.
+    pub(crate) fn set_flags(&mut self, flags: u32) {
+        self.flags = flags;
+        self.changes.following = flags & FLAG_FOLLOWER != 0;
+        self.get_page_mut(META_PAGE);
+    }
+
+    // Shadow pages go into the free list on disk while they stay off the one in memory. Only a
+    // live snapshot reads them, and none survives a crash, so reopening the file frees them.
+    fn write_meta(&mut self, root_page: i32, sequence: u64) {
+        let free_changed = self.free_list_changed || self.free_pages != self.committed_free_pages;
+        let flags = self.flags;
+        let catalog = self.catalog;
+        let mut shadows: Vec<usize> = self.shadow_refs.keys().copied().collect();
+        shadows.sort_unstable();
+        let mut free_pages = shadows;
+        free_pages.extend(&self.free_pages);
+
+        let meta = self.get_page_mut(META_PAGE);
+        meta[..META_MAGIC.len()].copy_from_slice(META_MAGIC);
+        set_u32(meta, META_ROOT, root_page as u32);
+        set_u32(meta, META_FLAGS, flags);
+        set_u32(meta, META_CATALOG, catalog as u32);
+        meta[META_SEQUENCE..META_SEQUENCE + 8].copy_from_slice(&sequence.to_le_bytes());
+        if free_changed {
+            let in_meta: Vec<usize> = free_pages
+                .drain(..free_pages.len().min(META_CAPACITY))
+                .collect();
+            set_u32(meta, META_FREE_COUNT, in_meta.len() as u32);
+            for (i, page) in in_meta.iter().enumerate() {
+                set_u32(meta, META_HEADER_SIZE + i * 4, *page as u32);
+            }
+
+            let mut trunks = Vec::new();
+            let mut link = (META_PAGE, META_FREE_TRUNK);
+            while let Some(&last) = free_pages.last() {
+                // Trunks come off the end, where the free pages are. Once only shadows are left,
+                // which a snapshot still reads, the trunk is a new page instead.
+                let trunk = if self.shadow_refs.contains_key(&last) {
+                    self.num_pages += 1;
+                    self.free_pages.push(self.num_pages - 1);
+                    self.num_pages - 1
+                } else {
+                    free_pages.pop();
+                    last
+                };
+                let entries: Vec<usize> = free_pages
+                    .drain(..free_pages.len().min(TRUNK_CAPACITY))
+                    .collect();
+                set_u32(self.get_page_mut(link.0), link.1, trunk as u32);
+                let page = self.get_page_mut(trunk);
+                page.fill(0);
+                set_u32(page, TRUNK_COUNT, entries.len() as u32);
+                for (i, entry) in entries.iter().enumerate() {
+                    set_u32(page, TRUNK_HEADER_SIZE + i * 4, *entry as u32);
+                }
+                link = (trunk, TRUNK_NEXT);
+                trunks.push(trunk);
+            }
+            set_u32(self.get_page_mut(link.0), link.1, u32::MAX);
+            // Each trunk's next link is only known once the one after it is placed.
+            for trunk in trunks {
+                seal(self.get_page_mut(trunk).try_into().unwrap(), TRUNK_CHECKSUM);
+            }
+        }
+        let num_pages = self.num_pages;
+        set_u32(
+            self.get_page_mut(META_PAGE),
+            META_NUM_PAGES,
+            num_pages as u32,
+        );
+        seal(
+            self.get_page_mut(META_PAGE).try_into().unwrap(),
+            META_CHECKSUM,
+        );
+    }
+
+    pub(crate) fn commit(&mut self, root_page: i32) -> Result<()> {
+        if self.dirty.is_empty() && root_page == self.root_page && !self.free_list_changed {
+            self.savepoints.clear();
+            return Ok(());
+        }
+        let sequence = self.changes.stage()?;
+        self.write_meta(root_page, sequence);
+
+        let mut pages: Vec<(usize, Box<[u8; PAGE_SIZE]>)> = self.dirty.drain().collect();
+        pages.sort_by_key(|(page_num, _)| *page_num);
+
+        let mut record = Vec::with_capacity(12 + pages.len() * (4 + PAGE_SIZE));
+        record.extend_from_slice(&WAL_MAGIC.to_le_bytes());
+        record.extend_from_slice(&(pages.len() as u32).to_le_bytes());
+        for (page_num, page) in &pages {
+            record.extend_from_slice(&(*page_num as u32).to_le_bytes());
+            record.extend_from_slice(&page[..]);
+        }
+        record.extend_from_slice(&crc32c::crc32c(&record).to_le_bytes());
+
+        self.savepoints.clear();
+        self.wal.set_len(0)?;
+        self.wal.write_all_at(&record, 0)?;
+        self.wal.sync_data()?;
+        #[cfg(test)]
+        {
+            self.record(|| IoEvent::WalTruncate);
+            self.record(|| IoEvent::WalWrite(record.clone()));
+            self.record(|| IoEvent::WalSync);
+        }
+
+        self.ensure_file_size(self.num_pages)?;
+        let shadows = self.shadow_refs.len();
+        for (page_num, page) in &pages {
+            self.preserve(*page_num)?;
+            self.committed_page_mut(*page_num)
+                .copy_from_slice(&page[..]);
+            #[cfg(test)]
+            self.record(|| IoEvent::PageWrite(*page_num, page.to_vec()));
+        }
+        self.counters
+            .page_writes
+            .fetch_add(pages.len() as u64, Ordering::Relaxed);
+        for (page_num, _) in &pages {
+            self.mmap.flush_range(page_num * PAGE_SIZE, PAGE_SIZE)?;
+            #[cfg(test)]
+            self.record(|| IoEvent::PageSync(Some(*page_num)));
+        }
+        self.wal.set_len(0)?;
+        #[cfg(test)]
+        self.record(|| IoEvent::WalTruncate);
+        let bytes = (record.len() + pages.len() * PAGE_SIZE) as u64;
+        self.notify(|o| o.on_flush(bytes));
+
+        self.changes.publish(sequence);
+        self.root_page = root_page;
+        self.committed_num_pages = self.num_pages;
+        self.committed_free_pages = self.free_pages.clone();
+        // Shadows taken by this commit sit past the page count its meta page holds, so they are
+        // only listed as free by the next one.
+        self.free_list_changed = self.shadow_refs.len() != shadows;
+        Ok(())
+    }
+
+    pub(crate) fn rollback(&mut self) -> i32 {
+        self.dirty.clear();
+        self.changes.pending.clear();
+        self.savepoints.clear();
+        self.num_pages = self.committed_num_pages;
+        self.free_pages = self.committed_free_pages.clone();
+        self.root_page
+    }
+
+    pub(crate) fn flush(&self) -> Result<()> {
+        self.mmap.flush()?;
+        self.notify(|o| o.on_flush(0));
+        #[cfg(test)]
+        self.record(|| IoEvent::PageSync(None));
+        Ok(())
+    }
+
+    #[cfg(test)]
+    fn record(&self, event: impl FnOnce() -> IoEvent) {
+        if let Some(journal) = self.journal.lock().unwrap().as_mut() {
+            journal.push(event());
+        }
+    }
+
+    // Copies the committed page aside for every live snapshot that can still see it. Shadow
+    // pages always come from the end of the file so they never overwrite a page a snapshot
+    // might reach through the free list.
+    fn preserve(&mut self, page_num: usize) -> Result<()> {
+        let waiting: Vec<u64> = self
+            .snapshots
+            .iter()
+            .filter(|(_, s)| page_num < s.num_pages && !s.remap.contains_key(&page_num))
+            .map(|(id, _)| *id)
+            .collect();
+        if waiting.is_empty() {
+            return Ok(());
+        }
+
+        let shadow = self.num_pages;
+        self.num_pages += 1;
+        self.committed_num_pages = self.committed_num_pages.max(self.num_pages);
+        self.ensure_file_size(self.num_pages)?;
+        let start = page_num * PAGE_SIZE;
+        self.mmap
+            .copy_within(start..start + PAGE_SIZE, shadow * PAGE_SIZE);
+        #[cfg(test)]
+        self.record(|| IoEvent::PageWrite(shadow, self.committed_page(shadow).to_vec()));
+        for id in &waiting {
+            if let Some(snapshot) = self.snapshots.get_mut(id) {
+                snapshot.remap.insert(page_num, shadow);
+            }
+        }
+        self.shadow_refs.insert(shadow, waiting.len());
+        Ok(())
+    }
+
+    pub(crate) fn register_snapshot(&mut self) -> u64 {
+        let id = self.next_snapshot_id;
+        self.next_snapshot_id += 1;
+        self.snapshots.insert(
+            id,
+            SnapshotPages {
+                num_pages: self.committed_num_pages,
+                remap: HashMap::new(),
+            },
+        );
+        id
+    }
+
+    pub(crate) fn release_snapshot(&mut self, id: u64) {
+        let Some(snapshot) = self.snapshots.remove(&id) else {
+            return;
+        };
+        for shadow in snapshot.remap.into_values() {
+            let refs = self.shadow_refs.get_mut(&shadow).unwrap();
+            *refs -= 1;
+            if *refs == 0 {
+                self.shadow_refs.remove(&shadow);
+                self.free_pages.push(shadow);
+                self.committed_free_pages.push(shadow);
+                for savepoint in &mut self.savepoints {
+                    savepoint.free_pages.push(shadow);
+                }
+                self.free_list_changed = true;
+            }
+        }
+    }
+}
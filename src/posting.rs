@@ -0,0 +1,478 @@
+use crate::checksum::seal;
+use crate::verify::corrupt;
+use crate::{BPlusTree, LeafNode, TreeReader, Update, DATA_SIZE, INLINE_VALUES, PAGE_SIZE};
+use std::io::{Error, ErrorKind, Result};
+
+pub(crate) const POSTING_PAGE_TYPE: u8 = 2;
+const POSTING_COUNT: usize = 2;
+const POSTING_NEXT: usize = 4;
+pub(crate) const POSTING_CHECKSUM: usize = 8;
+const POSTING_HEADER_SIZE: usize = 12;
+const POSTING_CAPACITY: usize = (PAGE_SIZE - POSTING_HEADER_SIZE) / DATA_SIZE;
+
+// Stored in the leaf in place of the values once a key has more than fit inline. The values
+// live in a chain of posting pages in insertion order; new ones are appended to the tail page.
+#[derive(Clone, Copy)]
+struct Postings {
+    count: usize,
+    head: usize,
+    tail: usize,
+}
+
+impl Postings {
+    fn decode(value: &[u8; DATA_SIZE]) -> Self {
+        let field = |i: usize| u32::from_le_bytes(value[i * 4..i * 4 + 4].try_into().unwrap());
+        Postings {
+            count: field(0) as usize,
+            head: field(1) as usize,
+            tail: field(2) as usize,
+        }
+    }
+
+    fn encode(&self) -> [u8; DATA_SIZE] {
+        let mut value = [0u8; DATA_SIZE];
+        for (i, field) in [self.count, self.head, self.tail].iter().enumerate() {
+            value[i * 4..i * 4 + 4].copy_from_slice(&(*field as u32).to_le_bytes());
+        }
+        value
+    }
+}
+
+struct PostingPage {
+    page: [u8; PAGE_SIZE],
+}
+
+impl PostingPage {
+    fn new() -> Self {
+        let mut page = [0u8; PAGE_SIZE];
+        page[0] = POSTING_PAGE_TYPE;
+        page[POSTING_NEXT..POSTING_NEXT + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        PostingPage { page }
+    }
+
+    fn count(&self) -> usize {
+        u16::from_le_bytes([self.page[POSTING_COUNT], self.page[POSTING_COUNT + 1]]) as usize
+    }
+
+    fn set_count(&mut self, count: usize) {
+        self.page[POSTING_COUNT..POSTING_COUNT + 2].copy_from_slice(&(count as u16).to_le_bytes());
+    }
+
+    fn next(&self) -> i32 {
+        i32::from_le_bytes(
+            self.page[POSTING_NEXT..POSTING_NEXT + 4]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    fn set_next(&mut self, page: i32) {
+        self.page[POSTING_NEXT..POSTING_NEXT + 4].copy_from_slice(&page.to_le_bytes());
+    }
+
+    fn offset(i: usize) -> usize {
+        POSTING_HEADER_SIZE + i * DATA_SIZE
+    }
+
+    fn value(&self, i: usize) -> [u8; DATA_SIZE] {
+        self.page[Self::offset(i)..Self::offset(i) + DATA_SIZE]
+            .try_into()
+            .unwrap()
+    }
+
+    fn set_value(&mut self, i: usize, data: &[u8; DATA_SIZE]) {
+        self.page[Self::offset(i)..Self::offset(i) + DATA_SIZE].copy_from_slice(data);
+    }
+
+    fn push(&mut self, data: &[u8; DATA_SIZE]) {
+        let n = self.count();
+        self.set_value(n, data);
+        self.set_count(n + 1);
+    }
+
+    fn remove(&mut self, i: usize) {
+        let n = self.count();
+        self.page
+            .copy_within(Self::offset(i + 1)..Self::offset(n), Self::offset(i));
+        self.page[Self::offset(n - 1)..Self::offset(n)].fill(0);
+        self.set_count(n - 1);
+    }
+}
+
+// Follows the posting chain of a cell read straight from a possibly damaged file, stopping at
+// the first page that does not look like a posting page. Returns the values found and whether
+// the whole chain was intact.
+pub(crate) fn salvage_postings<'a>(
+    descriptor: &[u8; DATA_SIZE],
+    page: impl Fn(usize) -> Option<&'a [u8]>,
+) -> (Vec<[u8; DATA_SIZE]>, bool) {
+    let postings = Postings::decode(descriptor);
+    let mut values = Vec::new();
+    let mut visited = Vec::new();
+    let mut page_num = postings.head as i32;
+    while page_num != -1 && !visited.contains(&page_num) {
+        let Some(bytes) = page(page_num as usize) else {
+            break;
+        };
+        let posting = PostingPage {
+            page: bytes.try_into().unwrap(),
+        };
+        if posting.page[0] != POSTING_PAGE_TYPE || posting.count() > POSTING_CAPACITY {
+            break;
+        }
+        values.extend((0..posting.count()).map(|j| posting.value(j)));
+        visited.push(page_num);
+        page_num = posting.next();
+    }
+    let intact = page_num == -1 && values.len() == postings.count;
+    (values, intact)
+}
+
+impl TreeReader<'_> {
+    fn read_posting_page(&self, page_num: usize) -> Result<PostingPage> {
+        let pager = self.pager.read().unwrap();
+        if page_num == 0 || page_num >= pager.num_pages() {
+            return Err(corrupt(page_num, "page is not in the file"));
+        }
+        let posting = PostingPage {
+            page: pager.get_page(page_num, self.snapshot).try_into().unwrap(),
+        };
+        drop(pager);
+        self.verify_page(page_num, &posting.page, POSTING_CHECKSUM)?;
+        if posting.page[0] != POSTING_PAGE_TYPE {
+            return Err(corrupt(page_num, "not a posting page"));
+        }
+        if posting.count() > POSTING_CAPACITY {
+            return Err(corrupt(page_num, format!("{} postings", posting.count())));
+        }
+        Ok(posting)
+    }
+
+    // The pages of the posting chain behind slot `i` of `leaf_page`, after checking what the
+    // multimap operations rely on: pages in range, of the posting type and never empty, no loops,
+    // and a descriptor whose count and tail match the chain.
+    pub(crate) fn posting_chain(
+        &self,
+        leaf_page: usize,
+        leaf: &LeafNode,
+        i: usize,
+        num_pages: usize,
+    ) -> Result<Vec<usize>> {
+        let postings = Postings::decode(&leaf.value(i));
+        let mut pages: Vec<usize> = Vec::new();
+        let mut values = 0;
+        let mut page_num = postings.head as i32;
+        while page_num != -1 {
+            let page = page_num as usize;
+            if page == 0 || page >= num_pages || pages.contains(&page) {
+                return Err(corrupt(leaf_page, "posting chain leaves the file or loops"));
+            }
+            let posting = self.read_posting_page(page)?;
+            if posting.count() == 0 {
+                return Err(corrupt(page, format!("{} postings", posting.count())));
+            }
+            values += posting.count();
+            pages.push(page);
+            page_num = posting.next();
+        }
+        if postings.count < 2 || postings.count != values || pages.last() != Some(&postings.tail) {
+            return Err(corrupt(
+                leaf_page,
+                "posting descriptor does not match its chain",
+            ));
+        }
+        Ok(pages)
+    }
+
+    // The first value stored for the key in slot `i`.
+    pub(crate) fn value_at(&self, leaf: &LeafNode, i: usize) -> Result<[u8; DATA_SIZE]> {
+        if !leaf.is_posting(i) {
+            return Ok(leaf.value(i));
+        }
+        let postings = Postings::decode(&leaf.value(i));
+        let head = self.read_posting_page(postings.head)?;
+        if head.count() == 0 {
+            return Err(corrupt(postings.head, "0 postings"));
+        }
+        Ok(head.value(0))
+    }
+
+    pub(crate) fn get_all(&self, key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        let leaf = self.read_leaf_node(self.find_leaf(key)?)?;
+        match leaf.search(key) {
+            Ok(i) => self.values_at(&leaf, i),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+
+    // Stops with an error instead of looping when a damaged chain points back into itself.
+    pub(crate) fn values_at(&self, leaf: &LeafNode, i: usize) -> Result<Vec<[u8; DATA_SIZE]>> {
+        if !leaf.is_posting(i) {
+            return Ok(leaf.values(i));
+        }
+        let postings = Postings::decode(&leaf.value(i));
+        let mut values = Vec::with_capacity(postings.count.min(POSTING_CAPACITY));
+        let mut page = postings.head as i32;
+        let mut pages = 0;
+        while page != -1 {
+            let posting = self.read_posting_page(page as usize)?;
+            values.extend((0..posting.count()).map(|j| posting.value(j)));
+            pages += 1;
+            if values.len() > postings.count || pages > self.num_pages() {
+                return Err(corrupt(
+                    page as usize,
+                    "posting chain is longer than its count",
+                ));
+            }
+            page = posting.next();
+        }
+        Ok(values)
+    }
+}
+
+impl BPlusTree {
+    fn read_posting_page(&self, page_num: usize) -> Result<PostingPage> {
+        self.reader().read_posting_page(page_num)
+    }
+
+    fn write_posting_page(&mut self, page_num: usize, posting: &PostingPage) -> Result<()> {
+        let mut page = posting.page;
+        seal(&mut page, POSTING_CHECKSUM);
+        self.pager.write().unwrap().write_page(page_num, &page)
+    }
+
+    // Returns the posting pages behind slot `i` to the free list, if it has any.
+    pub(crate) fn free_postings(&mut self, leaf: &LeafNode, i: usize) -> Result<()> {
+        if !leaf.is_posting(i) {
+            return Ok(());
+        }
+        let mut page = Postings::decode(&leaf.value(i)).head as i32;
+        while page != -1 {
+            let next = self.read_posting_page(page as usize)?.next();
+            self.pager.write().unwrap().free_page(page as usize);
+            page = next;
+        }
+        Ok(())
+    }
+
+    // Makes `data` the only value of slot `i`.
+    pub(crate) fn replace_value(
+        &mut self,
+        leaf_page: usize,
+        leaf: &mut LeafNode,
+        i: usize,
+        data: &[u8; DATA_SIZE],
+    ) -> Result<()> {
+        let old = self.reader().value_at(leaf, i)?;
+        let mut replaced = leaf.clone();
+        if !replaced.replace_at(i, (leaf.key(i), vec![*data], false)) {
+            return Err(corrupt(leaf_page, "replacement cell does not fit"));
+        }
+        self.changed(leaf.key(i), Some(&old), Some(data))?;
+        self.free_postings(leaf, i)?;
+        *leaf = replaced;
+        self.write_leaf_node(leaf_page, leaf)
+    }
+
+    // Backs `put` and the other writes that replace a key's value. In multimap mode they are
+    // refused instead of dropping every other value the key holds.
+    pub(crate) fn overwrite_value(
+        &mut self,
+        leaf_page: usize,
+        leaf: &mut LeafNode,
+        i: usize,
+        data: &[u8; DATA_SIZE],
+    ) -> Result<()> {
+        if self.multimap {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "cannot overwrite a key in multimap mode; use insert_value and delete_one",
+            ));
+        }
+        self.replace_value(leaf_page, leaf, i, data)
+    }
+
+    // Adds `data` after the values `key` already holds, even an equal one, in the same descent
+    // that finds the key. Returns whether the key is new.
+    pub(crate) fn append_value(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        let (leaf_page, leaf, found) = self.locate(key)?;
+        match found {
+            Ok(i) => self.append_to_cell(leaf_page, leaf, i, key, data)?,
+            Err(_) => self.apply_update(leaf_page, leaf, found, key, Update::Put(*data))?,
+        }
+        Ok(found.is_err())
+    }
+
+    fn append_to_cell(
+        &mut self,
+        leaf_page: usize,
+        mut leaf: LeafNode,
+        i: usize,
+        key: i32,
+        data: &[u8; DATA_SIZE],
+    ) -> Result<()> {
+        if !leaf.is_posting(i) {
+            let mut values = leaf.values(i);
+            values.push(*data);
+            if values.len() <= INLINE_VALUES {
+                let cell = (key, values, false);
+                if leaf.replace_at(i, cell.clone()) {
+                    return self.write_leaf_node(leaf_page, &leaf);
+                }
+                let mut entries = leaf.entries();
+                entries[i] = cell;
+                let (split_key, new_page) = self.split_leaf(leaf_page, leaf, &entries)?;
+                return self.insert_into_parent(leaf_page, split_key, new_page);
+            }
+            // Past the threshold the values move out to a posting page and the cell shrinks to
+            // the descriptor.
+            let page = self.allocate_page()?;
+            let mut posting = PostingPage::new();
+            for value in &values {
+                posting.push(value);
+            }
+            self.write_posting_page(page, &posting)?;
+            let postings = Postings {
+                count: values.len(),
+                head: page,
+                tail: page,
+            };
+            if !leaf.replace_at(i, (key, vec![postings.encode()], true)) {
+                return Err(corrupt(leaf_page, "posting descriptor does not fit"));
+            }
+            self.write_leaf_node(leaf_page, &leaf)?;
+            return self.rebalance_if_underfull(leaf_page, &leaf);
+        }
+
+        let mut postings = Postings::decode(&leaf.value(i));
+        let mut tail = self.read_posting_page(postings.tail)?;
+        if tail.count() < POSTING_CAPACITY {
+            tail.push(data);
+            self.write_posting_page(postings.tail, &tail)?;
+        } else {
+            let page = self.allocate_page()?;
+            let mut posting = PostingPage::new();
+            posting.push(data);
+            self.write_posting_page(page, &posting)?;
+            tail.set_next(page as i32);
+            self.write_posting_page(postings.tail, &tail)?;
+            postings.tail = page;
+        }
+        postings.count += 1;
+
+        leaf.set_value(i, &postings.encode());
+        self.write_leaf_node(leaf_page, &leaf)
+    }
+
+    // Removes the first occurrence of `data` under `key`. A key left with a single value
+    // moves it back inline and frees its last posting page.
+    pub(crate) fn remove_value(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        let (leaf_page, mut leaf, found) = self.locate(key)?;
+        let Ok(i) = found else {
+            return Ok(false);
+        };
+        if !leaf.is_posting(i) {
+            let mut values = leaf.values(i);
+            let Some(j) = values.iter().position(|value| value == data) else {
+                return Ok(false);
+            };
+            if values.len() == 1 {
+                self.remove_from_leaf(leaf_page, leaf, i)?;
+                return Ok(true);
+            }
+            values.remove(j);
+            if !leaf.replace_at(i, (key, values, false)) {
+                return Err(corrupt(leaf_page, "shrunk cell does not fit"));
+            }
+            self.write_leaf_node(leaf_page, &leaf)?;
+            self.rebalance_if_underfull(leaf_page, &leaf)?;
+            return Ok(true);
+        }
+
+        let mut postings = Postings::decode(&leaf.value(i));
+        let mut prev = -1;
+        let mut page = postings.head as i32;
+        loop {
+            if page == -1 {
+                return Ok(false);
+            }
+            let mut posting = self.read_posting_page(page as usize)?;
+            if let Some(j) = (0..posting.count()).find(|&j| posting.value(j) == *data) {
+                posting.remove(j);
+                if posting.count() > 0 {
+                    self.write_posting_page(page as usize, &posting)?;
+                } else {
+                    if prev == -1 {
+                        postings.head = posting.next() as usize;
+                    } else {
+                        let mut before = self.read_posting_page(prev as usize)?;
+                        before.set_next(posting.next());
+                        self.write_posting_page(prev as usize, &before)?;
+                    }
+                    if postings.tail == page as usize {
+                        postings.tail = prev as usize;
+                    }
+                    self.pager.write().unwrap().free_page(page as usize);
+                }
+                break;
+            }
+            prev = page;
+            page = posting.next();
+        }
+
+        postings.count -= 1;
+        leaf.set_value(i, &postings.encode());
+        if postings.count == 1 {
+            let last = self.read_posting_page(postings.head)?.value(0);
+            self.replace_value(leaf_page, &mut leaf, i, &last)?;
+        } else {
+            self.write_leaf_node(leaf_page, &leaf)?;
+        }
+        Ok(true)
+    }
+
+    // Removes `key` with all its values and returns how many there were.
+    pub(crate) fn remove_all(&mut self, key: i32) -> Result<usize> {
+        let (leaf_page, leaf, found) = self.locate(key)?;
+        let Ok(i) = found else {
+            return Ok(0);
+        };
+        let count = if leaf.is_posting(i) {
+            Postings::decode(&leaf.value(i)).count
+        } else {
+            leaf.inline_len(i)
+        };
+        self.remove_from_leaf(leaf_page, leaf, i)?;
+        Ok(count)
+    }
+
+    // Runs `f` over every value in the posting list of slot `i`, writing back changed pages.
+    pub(crate) fn rewrite_postings(
+        &mut self,
+        leaf: &LeafNode,
+        i: usize,
+        mut f: impl FnMut(&mut [u8; DATA_SIZE]),
+    ) -> Result<usize> {
+        let mut updated = 0;
+        let mut page = Postings::decode(&leaf.value(i)).head as i32;
+        while page != -1 {
+            let mut posting = self.read_posting_page(page as usize)?;
+            let mut changed = false;
+            for j in 0..posting.count() {
+                let old = posting.value(j);
+                let mut data = old;
+                f(&mut data);
+                if data != old {
+                    posting.set_value(j, &data);
+                    changed = true;
+                    updated += 1;
+                }
+            }
+            if changed {
+                self.write_posting_page(page as usize, &posting)?;
+            }
+            page = posting.next();
+        }
+        Ok(updated)
+    }
+}
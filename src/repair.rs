@@ -0,0 +1,425 @@
+use crate::catalog::{salvage_catalog, CatalogEntry, KIND_TREE};
+use crate::checksum::page_checksum;
+use crate::pager::{parse_meta, wal_pages, FLAG_MULTIMAP};
+use crate::posting::salvage_postings;
+use crate::{
+    BPlusTree, InternalNode, LeafNode, DATA_SIZE, INTERNAL_BODY, INTERNAL_CHECKSUM, INTERNAL_ORDER,
+    LEAF_CHECKSUM, PAGE_SIZE,
+};
+use bincode::config;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Bound;
+use std::path::Path;
+
+// Parent pointers are followed at most this far when looking for the tree an orphaned leaf
+// belongs to.
+const MAX_CLIMB: usize = 64;
+
+// Keys that were under a damaged page, bounded by the separators of its last intact parent.
+#[derive(Debug, Clone)]
+pub struct LostRange {
+    // None for the main tree.
+    pub tree: Option<String>,
+    // Inclusive start and exclusive end; None where the range is open.
+    pub start: Option<i32>,
+    pub end: Option<i32>,
+    // Entry count the parent kept for the damaged child, if the parent was intact.
+    pub expected: Option<u64>,
+    // Keys in the range that still turned up in orphaned leaves.
+    pub recovered: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    pub pages_scanned: usize,
+    // Intact leaves whose records were copied, reachable or orphaned.
+    pub leaves_recovered: usize,
+    // Intact leaves no intact parent points to, matched to a tree by their parent pointers.
+    pub orphaned_leaves: Vec<usize>,
+    // Orphaned leaves that could not be matched to a tree and were left out.
+    pub unattributed_leaves: Vec<usize>,
+    // Node pages failing their checksum or structure checks.
+    pub damaged_pages: Vec<usize>,
+    pub records_recovered: usize,
+    // Keys found in more than one leaf; the copy reachable from the root wins.
+    pub duplicates_dropped: usize,
+    // Multimap keys whose posting chain was cut short.
+    pub broken_postings: usize,
+    // No intact catalog page was found, so named trees were not recovered; their leaves are
+    // unattributed.
+    pub catalog_lost: bool,
+    pub lost: Vec<LostRange>,
+}
+
+impl fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "pages scanned:       {}", self.pages_scanned)?;
+        writeln!(f, "leaves recovered:    {}", self.leaves_recovered)?;
+        writeln!(f, "records recovered:   {}", self.records_recovered)?;
+        writeln!(f, "orphaned leaves:     {:?}", self.orphaned_leaves)?;
+        writeln!(f, "unattributed leaves: {:?}", self.unattributed_leaves)?;
+        writeln!(f, "damaged pages:       {:?}", self.damaged_pages)?;
+        writeln!(f, "duplicates dropped:  {}", self.duplicates_dropped)?;
+        writeln!(f, "broken postings:     {}", self.broken_postings)?;
+        if self.catalog_lost {
+            writeln!(f, "catalog lost")?;
+        }
+        for lost in &self.lost {
+            let bound = |b: Option<i32>| b.map_or("..".to_string(), |k| k.to_string());
+            let expected = lost.expected.map_or("?".to_string(), |n| n.to_string());
+            writeln!(
+                f,
+                "lost in {} [{}, {}): {} expected, {} recovered",
+                lost.tree.as_deref().unwrap_or("main"),
+                bound(lost.start),
+                bound(lost.end),
+                expected,
+                lost.recovered
+            )?;
+        }
+        Ok(())
+    }
+}
+
+enum Node {
+    Leaf(LeafNode),
+    Internal(InternalNode),
+}
+
+fn decode_node(bytes: &[u8], num_pages: usize) -> Option<Node> {
+    let page: [u8; PAGE_SIZE] = bytes.try_into().ok()?;
+    let stored = |offset: usize| u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap());
+    match page[0] {
+        1 if stored(LEAF_CHECKSUM) == page_checksum(&page, LEAF_CHECKSUM) => {
+            let leaf = LeafNode { page };
+            leaf.is_well_formed().then_some(Node::Leaf(leaf))
+        }
+        0 if stored(INTERNAL_CHECKSUM) == page_checksum(&page, INTERNAL_CHECKSUM) => {
+            let (node, _): (InternalNode, _) =
+                bincode::decode_from_slice(&page[INTERNAL_BODY..], config::standard()).ok()?;
+            let well_formed = node.num_keys <= INTERNAL_ORDER
+                && (1..node.num_keys).all(|i| node.keys[i - 1] < node.keys[i])
+                && node.children[..=node.num_keys]
+                    .iter()
+                    .all(|&child| child > 0 && (child as usize) < num_pages);
+            well_formed.then_some(Node::Internal(node))
+        }
+        _ => None,
+    }
+}
+
+struct Tree {
+    name: Option<String>,
+    // Secondary indexes are walked only so their pages are not taken for orphans; register_index
+    // rebuilds them from the records.
+    keep: bool,
+    records: BTreeMap<i32, Vec<[u8; DATA_SIZE]>>,
+}
+
+struct Salvage<'a> {
+    image: &'a [u8],
+    num_pages: usize,
+    trees: Vec<Tree>,
+    claimed: HashSet<usize>,
+    internal_owner: HashMap<usize, usize>,
+    report: RepairReport,
+}
+
+impl<'a> Salvage<'a> {
+    fn page(&self, page_num: usize) -> Option<&'a [u8]> {
+        self.image
+            .get(page_num * PAGE_SIZE..(page_num + 1) * PAGE_SIZE)
+    }
+
+    fn node(&self, page_num: usize) -> Option<Node> {
+        decode_node(self.page(page_num)?, self.num_pages)
+    }
+
+    // Descends from the tree's root through intact internal nodes, copying every intact leaf
+    // and recording the key range under each damaged child.
+    fn walk(&mut self, tree: usize, root: i32) {
+        let mut stack = vec![(root, None, None, None)];
+        while let Some((page_num, start, end, expected)) = stack.pop() {
+            if page_num <= 0 || !self.claimed.insert(page_num as usize) {
+                continue;
+            }
+            match self.node(page_num as usize) {
+                Some(Node::Leaf(leaf)) => self.copy_leaf(tree, &leaf, false),
+                Some(Node::Internal(node)) => {
+                    self.internal_owner.insert(page_num as usize, tree);
+                    for i in 0..=node.num_keys {
+                        let low = if i == 0 {
+                            start
+                        } else {
+                            Some(node.keys[i - 1])
+                        };
+                        let high = if i == node.num_keys {
+                            end
+                        } else {
+                            Some(node.keys[i])
+                        };
+                        stack.push((node.children[i], low, high, Some(node.counts[i])));
+                    }
+                }
+                None => {
+                    // Leaves below still point at the damaged page as their parent.
+                    self.internal_owner.insert(page_num as usize, tree);
+                    self.report.damaged_pages.push(page_num as usize);
+                    self.report.lost.push(LostRange {
+                        tree: self.trees[tree].name.clone(),
+                        start,
+                        end,
+                        expected,
+                        recovered: 0,
+                    });
+                }
+            }
+        }
+    }
+
+    // An orphaned leaf may also be a stale page left over from before a crash, so only its keys
+    // inside one of the tree's lost ranges are taken.
+    fn copy_leaf(&mut self, tree: usize, leaf: &LeafNode, orphan: bool) {
+        if !self.trees[tree].keep {
+            return;
+        }
+        self.report.leaves_recovered += 1;
+        for i in 0..leaf.num_keys() {
+            if orphan && !self.in_lost_range(tree, leaf.key(i)) {
+                continue;
+            }
+            let values = if leaf.is_posting(i) {
+                let (values, intact) = salvage_postings(&leaf.value(i), |p| self.page(p));
+                if !intact {
+                    self.report.broken_postings += 1;
+                }
+                values
+            } else {
+                leaf.values(i)
+            };
+            let records = &mut self.trees[tree].records;
+            if values.is_empty() {
+                continue;
+            }
+            if records.contains_key(&leaf.key(i)) {
+                self.report.duplicates_dropped += 1;
+                continue;
+            }
+            records.insert(leaf.key(i), values);
+        }
+    }
+
+    fn in_lost_range(&self, tree: usize, key: i32) -> bool {
+        self.report.lost.iter().any(|lost| {
+            lost.tree == self.trees[tree].name
+                && lost.start.is_none_or(|start| key >= start)
+                && lost.end.is_none_or(|end| key < end)
+        })
+    }
+
+    // Picks the main tree's root when the meta page is lost: the node without a parent that no
+    // catalog entry claims, preferring the internal node holding the most entries, since a root
+    // freed when the tree shrank keeps its old contents too.
+    fn guess_root(&self, catalog: &[CatalogEntry]) -> i32 {
+        (1..self.num_pages)
+            .filter(|&page_num| catalog.iter().all(|entry| entry.root != page_num as i32))
+            .filter_map(|page_num| match self.node(page_num) {
+                Some(Node::Internal(node)) if node.parent == -1 => {
+                    Some(((1, node.total()), page_num))
+                }
+                Some(Node::Leaf(leaf)) if leaf.parent() == -1 => {
+                    Some(((0, leaf.num_keys() as u64), page_num))
+                }
+                _ => None,
+            })
+            .max_by_key(|(rank, _)| *rank)
+            .map_or(-1, |(_, page_num)| page_num as i32)
+    }
+
+    // Follows parent pointers up to a node reached by one of the walks.
+    fn owner_of(&self, leaf: &LeafNode) -> Option<usize> {
+        let mut parent = leaf.parent();
+        for _ in 0..MAX_CLIMB {
+            if parent <= 0 {
+                return None;
+            }
+            if let Some(&tree) = self.internal_owner.get(&(parent as usize)) {
+                return Some(tree);
+            }
+            match self.node(parent as usize) {
+                Some(Node::Internal(node)) => parent = node.parent,
+                _ => return None,
+            }
+        }
+        None
+    }
+}
+
+// Writes the salvaged records into `tree`, or into the named tree it holds, in one commit.
+fn fill(tree: &mut BPlusTree, records: &BTreeMap<i32, Vec<[u8; DATA_SIZE]>>) -> Result<()> {
+    let multimap = tree.is_multimap();
+    let mut tx = tree.begin()?;
+    for (key, values) in records {
+        if multimap {
+            for value in values {
+                tx.insert_value(*key, value)?;
+            }
+        } else {
+            tx.put(*key, &values[0])?;
+        }
+    }
+    tx.commit()
+}
+
+impl BPlusTree {
+    // Salvages what it can from a damaged index file and its write-ahead log into a fresh file
+    // at `output`, which must not exist yet. The damaged file is only read. Every page is
+    // checked on its own, so records survive broken internal nodes, parent pointers and leaf
+    // links. Secondary indexes are not copied and have to be registered again.
+    pub fn repair<P: AsRef<Path>, Q: AsRef<Path>>(damaged: P, output: Q) -> Result<RepairReport> {
+        let output = output.as_ref();
+        if output.exists() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", output.display()),
+            ));
+        }
+
+        let mut image = std::fs::read(damaged.as_ref())?;
+        let mut wal_path = damaged.as_ref().as_os_str().to_owned();
+        wal_path.push(".wal");
+        let log = std::fs::read(wal_path).unwrap_or_default();
+        for (page_num, bytes) in wal_pages(&log) {
+            let start = page_num * PAGE_SIZE;
+            if image.len() < start + PAGE_SIZE {
+                image.resize(start + PAGE_SIZE, 0);
+            }
+            image[start..start + PAGE_SIZE].copy_from_slice(bytes);
+        }
+        image.truncate(image.len() / PAGE_SIZE * PAGE_SIZE);
+
+        let num_pages = image.len() / PAGE_SIZE;
+        let page = |page_num: usize| image.get(page_num * PAGE_SIZE..(page_num + 1) * PAGE_SIZE);
+        let meta = parse_meta(page);
+        // Without a meta page, the first page that decodes as a catalog stands in for it.
+        let catalog_page = match &meta {
+            Some(meta) => Some(meta.catalog as usize),
+            None => (1..num_pages).find(|&p| page(p).and_then(salvage_catalog).is_some()),
+        };
+        let catalog = catalog_page.and_then(page).and_then(salvage_catalog);
+        let catalog_lost = catalog.is_none();
+        let catalog = catalog.unwrap_or_default();
+        let free: HashSet<usize> = meta
+            .as_ref()
+            .map(|meta| meta.free_pages.iter().copied().collect())
+            .unwrap_or_default();
+
+        let mut salvage = Salvage {
+            image: &image,
+            num_pages,
+            trees: Vec::new(),
+            claimed: HashSet::new(),
+            internal_owner: HashMap::new(),
+            report: RepairReport {
+                pages_scanned: num_pages,
+                catalog_lost,
+                ..RepairReport::default()
+            },
+        };
+        salvage.claimed.insert(0);
+        salvage.claimed.extend(catalog_page);
+
+        let main_root = match &meta {
+            Some(meta) => meta.root_page,
+            None => salvage.guess_root(&catalog),
+        };
+        if main_root <= 0 && meta.is_none() {
+            salvage.report.lost.push(LostRange {
+                tree: None,
+                start: None,
+                end: None,
+                expected: None,
+                recovered: 0,
+            });
+        }
+        let mut roots = vec![(None, true, main_root)];
+        roots.extend(catalog.iter().map(|entry| {
+            (
+                Some(entry.name.clone()),
+                entry.kind == KIND_TREE,
+                entry.root,
+            )
+        }));
+        for (tree, (name, keep, root)) in roots.into_iter().enumerate() {
+            salvage.trees.push(Tree {
+                name,
+                keep,
+                records: BTreeMap::new(),
+            });
+            salvage.walk(tree, root);
+        }
+
+        // Leaves no walk reached: under a damaged internal node, or left behind by a crash.
+        for page_num in 1..num_pages {
+            if salvage.claimed.contains(&page_num) || free.contains(&page_num) {
+                continue;
+            }
+            match salvage.node(page_num) {
+                Some(Node::Leaf(leaf)) => {
+                    let owner = salvage
+                        .owner_of(&leaf)
+                        .or((salvage.trees.len() == 1).then_some(0));
+                    match owner {
+                        Some(tree) => {
+                            salvage.report.orphaned_leaves.push(page_num);
+                            salvage.copy_leaf(tree, &leaf, true);
+                        }
+                        None => salvage.report.unattributed_leaves.push(page_num),
+                    }
+                }
+                Some(Node::Internal(_)) => {}
+                None if image[page_num * PAGE_SIZE] == 1 => {
+                    salvage.report.damaged_pages.push(page_num)
+                }
+                None => {}
+            }
+        }
+
+        let Salvage {
+            trees, mut report, ..
+        } = salvage;
+        for lost in &mut report.lost {
+            let Some(tree) = trees.iter().find(|tree| tree.name == lost.tree) else {
+                continue;
+            };
+            let start = lost.start.map_or(Bound::Unbounded, Bound::Included);
+            let end = lost.end.map_or(Bound::Unbounded, Bound::Excluded);
+            lost.recovered = tree.records.range((start, end)).count();
+        }
+        report.damaged_pages.sort_unstable();
+
+        let multimap = meta.map_or_else(
+            || trees[0].records.values().any(|values| values.len() > 1),
+            |meta| meta.flags & FLAG_MULTIMAP != 0,
+        );
+        let mut fresh = if multimap {
+            BPlusTree::open_multimap(output)?
+        } else {
+            BPlusTree::open(output)?
+        };
+        for tree in trees.iter().filter(|tree| tree.keep) {
+            report.records_recovered += tree.records.values().map(Vec::len).sum::<usize>();
+            match &tree.name {
+                None => fill(&mut fresh, &tree.records)?,
+                Some(name) => {
+                    fresh.create_tree(name)?;
+                    let mut named = fresh.open_tree(name)?;
+                    fill(&mut named, &tree.records)?;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
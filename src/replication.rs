@@ -0,0 +1,111 @@
+use crate::changes::{Change, ChangeEvent};
+use crate::pager::FLAG_FOLLOWER;
+use crate::{BPlusTree, ChangeLog};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+// A read-only copy of another index, kept up to date by tailing the primary's change log. The
+// follower numbers the changes it applies the way the primary did, so its sequence says how far
+// it has got. Only the primary's main tree is replicated; named trees are not in the log.
+pub struct Follower {
+    tree: BPlusTree,
+    log: ChangeLog,
+}
+
+impl Follower {
+    // Opens or creates the follower at `path` for the primary at `primary_path`, which needs
+    // `enable_change_log`. A new follower starts from the primary's first change; when the log
+    // no longer goes back that far, seed it with a copy of the primary's file instead.
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(path: P, primary_path: Q) -> Result<Self> {
+        let mut tree = BPlusTree::open_with_flags(path.as_ref(), FLAG_FOLLOWER)?;
+        if tree.multimap {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "a follower needs a unique-key index",
+            ));
+        }
+        let flags = tree.pager.read().unwrap().flags;
+        if flags & FLAG_FOLLOWER == 0 {
+            tree.pager.write().unwrap().set_flags(flags | FLAG_FOLLOWER);
+            tree.autocommit(|_| Ok(()))?;
+        }
+
+        let mut log = ChangeLog::open(primary_path)?;
+        log.seek(tree.sequence() + 1).map_err(|e| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "follower is at {} but the primary's {e}; seed it from a copy of the primary",
+                    tree.sequence()
+                ),
+            )
+        })?;
+        Ok(Follower { tree, log })
+    }
+
+    // Reads go through here; writes are refused until the follower is promoted.
+    pub fn tree(&self) -> &BPlusTree {
+        &self.tree
+    }
+
+    // The sequence number of the last change applied, matching the primary's numbering.
+    pub fn applied(&self) -> u64 {
+        self.tree.sequence()
+    }
+
+    // Applies every change the primary has committed since the last call, in one commit, and
+    // returns how many there were. A value the follower holds that differs from the one the
+    // primary replaced means the two have diverged, and nothing from the batch is applied.
+    pub fn catch_up(&mut self) -> Result<usize> {
+        let events = self.log.poll()?;
+        let Some(last) = events.last().map(|event| event.sequence) else {
+            return Ok(0);
+        };
+        let result = self.tree.apply_changes(&events);
+        if let Err(e) = result {
+            // Read the failed batch again on the next call.
+            self.log.seek(self.tree.sequence() + 1)?;
+            return Err(e);
+        }
+        debug_assert_eq!(self.tree.sequence(), last);
+        Ok(events.len())
+    }
+
+    // Turns the follower into an ordinary index that takes writes. It stops following the old
+    // primary, so that one should no longer be written to.
+    pub fn promote(self) -> Result<BPlusTree> {
+        let mut tree = self.tree;
+        let flags = tree.pager.read().unwrap().flags;
+        tree.pager
+            .write()
+            .unwrap()
+            .set_flags(flags & !FLAG_FOLLOWER);
+        tree.autocommit(|_| Ok(()))?;
+        Ok(tree)
+    }
+}
+
+impl BPlusTree {
+    // Redoes changes read from another index's log in one commit. Each has to find the value it
+    // replaced there, or the trees have diverged and nothing is applied.
+    pub(crate) fn apply_changes(&mut self, events: &[ChangeEvent]) -> Result<()> {
+        self.autocommit(|tree| {
+            for event in events {
+                let (key, old, found) = match &event.change {
+                    Change::Put { key, old, new } => (*key, *old, tree.put_entry(*key, new)?),
+                    Change::Delete { key, old } => (*key, Some(*old), tree.remove_entry(*key)?),
+                };
+                if found != old {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "index diverged from the log at sequence {} (key {key})",
+                            event.sequence
+                        ),
+                    ));
+                }
+            }
+            Ok(())
+        })
+    }
+}
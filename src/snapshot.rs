@@ -0,0 +1,51 @@
+use crate::{Pager, TreeReader, DATA_SIZE};
+use std::io::Result;
+use std::sync::{Arc, RwLock};
+
+pub struct Snapshot {
+    pub(crate) pager: Arc<RwLock<Pager>>,
+    pub(crate) id: u64,
+    pub(crate) root_page: i32,
+}
+
+impl Snapshot {
+    pub(crate) fn reader(&self) -> TreeReader<'_> {
+        TreeReader {
+            pager: &self.pager,
+            snapshot: Some(self.id),
+            root_page: self.root_page,
+        }
+    }
+
+    pub fn read(&self, key: i32) -> Result<Option<[u8; DATA_SIZE]>> {
+        self.reader().read(key)
+    }
+
+    pub fn get_all(&self, key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        self.reader().get_all(key)
+    }
+
+    pub fn lookup_by(
+        &self,
+        index: &str,
+        secondary_key: i32,
+    ) -> Result<Vec<(i32, [u8; DATA_SIZE])>> {
+        self.reader().lookup_by(index, secondary_key)
+    }
+
+    pub fn read_range_data(&self, start_key: i32, end_key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        self.reader().read_range_data(start_key, end_key)
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        self.reader().verify()
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Ok(mut pager) = self.pager.write() {
+            pager.release_snapshot(self.id);
+        }
+    }
+}
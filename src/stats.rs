@@ -0,0 +1,169 @@
+use crate::verify::corrupt;
+use crate::{BPlusTree, Observer, TreeReader, INTERNAL_ORDER, LEAF_HEADER_SIZE, PAGE_SIZE};
+use std::collections::HashSet;
+use std::io::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Cumulative counts since the file was opened. They live in the pager so every handle on the
+// file, named trees included, adds to the same ones, and rolled-back work still counts.
+#[derive(Default)]
+pub(crate) struct Counters {
+    pub(crate) splits: AtomicU64,
+    pub(crate) merges: AtomicU64,
+    pub(crate) borrows: AtomicU64,
+    pub(crate) root_changes: AtomicU64,
+    pub(crate) page_allocations: AtomicU64,
+    pub(crate) page_reads: AtomicU64,
+    pub(crate) file_reads: AtomicU64,
+    pub(crate) page_writes: AtomicU64,
+    pub(crate) flushes: AtomicU64,
+    pub(crate) bytes_synced: AtomicU64,
+}
+
+// Page reads and writes are counted by the pager directly; everything else arrives as events.
+impl Observer for Counters {
+    fn on_split(&self, _page: usize, _new_page: usize) {
+        self.splits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_merge(&self, _left: usize, _right: usize) {
+        self.merges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_borrow(&self, _page: usize, _from: usize) {
+        self.borrows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_root_change(&self, _old_root: i32, _new_root: i32) {
+        self.root_changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_page_alloc(&self, _page: usize) {
+        self.page_allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_flush(&self, bytes: u64) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+        self.bytes_synced.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+// One level of the tree. Fill is the share of a page in use: keys against the order for
+// internal nodes, slot and cell bytes against the page body for leaves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelStats {
+    pub pages: usize,
+    pub avg_fill: f64,
+    pub min_fill: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeStats {
+    pub height: usize,
+    pub leaf_pages: usize,
+    pub internal_pages: usize,
+    pub free_pages: usize,
+    // Root first, leaves last.
+    pub levels: Vec<LevelStats>,
+    pub keys: usize,
+    pub file_size: u64,
+    pub splits: u64,
+    pub merges: u64,
+    pub borrows: u64,
+    pub root_changes: u64,
+    pub page_allocations: u64,
+    pub page_reads: u64,
+    pub file_reads: u64,
+    pub page_writes: u64,
+    pub flushes: u64,
+    pub bytes_synced: u64,
+}
+
+impl TreeReader<'_> {
+    fn levels(&self) -> Result<Vec<LevelStats>> {
+        let mut levels = Vec::new();
+        let mut level = match self.root_page {
+            -1 => Vec::new(),
+            root => vec![root as usize],
+        };
+        // A damaged file can point back up the tree; a page reached twice stops the walk
+        // before the levels grow without end.
+        let mut seen = HashSet::new();
+        while !level.is_empty() {
+            let mut fills = Vec::with_capacity(level.len());
+            let mut next = Vec::new();
+            for &page in &level {
+                if !seen.insert(page) {
+                    return Err(corrupt(page, "reachable twice"));
+                }
+                if self.is_leaf_page(page) {
+                    let leaf = self.read_leaf_node(page)?;
+                    fills.push(leaf.used() as f64 / (PAGE_SIZE - LEAF_HEADER_SIZE) as f64);
+                } else {
+                    let node = self.read_internal_node(page)?;
+                    fills.push(node.num_keys as f64 / INTERNAL_ORDER as f64);
+                    next.extend(node.children[..=node.num_keys].iter().map(|&c| c as usize));
+                }
+            }
+            levels.push(LevelStats {
+                pages: fills.len(),
+                avg_fill: fills.iter().sum::<f64>() / fills.len() as f64,
+                min_fill: fills.iter().copied().fold(f64::INFINITY, f64::min),
+            });
+            level = next;
+        }
+        Ok(levels)
+    }
+}
+
+impl BPlusTree {
+    // Walks every node of the current tree for the shape figures, so it costs a full scan of
+    // the internal and leaf pages.
+    pub fn stats(&self) -> Result<TreeStats> {
+        let levels = self.reader().levels()?;
+        let keys = self.len()?;
+        let pager = self.pager.read().unwrap();
+        let counters = &pager.counters;
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let leaf_pages = levels.last().map_or(0, |level| level.pages);
+        Ok(TreeStats {
+            height: levels.len(),
+            leaf_pages,
+            internal_pages: levels.iter().map(|level| level.pages).sum::<usize>() - leaf_pages,
+            free_pages: pager.free_pages().len(),
+            levels,
+            keys,
+            file_size: pager.file_size(),
+            splits: load(&counters.splits),
+            merges: load(&counters.merges),
+            borrows: load(&counters.borrows),
+            root_changes: load(&counters.root_changes),
+            page_allocations: load(&counters.page_allocations),
+            page_reads: load(&counters.page_reads),
+            file_reads: load(&counters.file_reads),
+            page_writes: load(&counters.page_writes),
+            flushes: load(&counters.flushes),
+            bytes_synced: load(&counters.bytes_synced),
+        })
+    }
+
+    // Pages read through the pager since the file was opened, pages still in a transaction
+    // included.
+    pub fn page_reads(&self) -> u64 {
+        let pager = self.pager.read().unwrap();
+        pager.counters.page_reads.load(Ordering::Relaxed)
+    }
+
+    // The part of `page_reads` served from the index file rather than from pages already
+    // changed in memory by the open transaction.
+    pub fn file_reads(&self) -> u64 {
+        let pager = self.pager.read().unwrap();
+        pager.counters.file_reads.load(Ordering::Relaxed)
+    }
+
+    // Pages written to the index file by commits since it was opened.
+    pub fn page_writes(&self) -> u64 {
+        let pager = self.pager.read().unwrap();
+        pager.counters.page_writes.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,117 @@
+use super::TempIndex;
+use crate::{BPlusTree, DATA_SIZE};
+use std::io::ErrorKind;
+
+// The full backup predates the change log, so the restored file has no log of its own and the
+// replay alone has to carry its sequence forward.
+#[test]
+fn restore_until_a_midpoint_of_the_log() {
+    let primary = TempIndex::new("backup_primary");
+    let full = TempIndex::new("backup_full");
+    let restored = TempIndex::new("backup_restored");
+    let mut tree = primary.open();
+    for key in 0..100 {
+        tree.write_data(key, &[1; DATA_SIZE]).unwrap();
+    }
+    let base = tree.backup_to(&full.path).unwrap();
+    tree.enable_change_log().unwrap();
+    for key in 0..100 {
+        tree.write_data(key, &[2; DATA_SIZE]).unwrap();
+    }
+    let midpoint = base + 50;
+    assert_eq!(tree.sequence(), base + 100);
+    drop(tree);
+
+    let sequence =
+        BPlusTree::restore_until(&[&full.path], &primary.path, midpoint, &restored.path).unwrap();
+    assert_eq!(sequence, midpoint);
+    let tree = restored.open();
+    assert_eq!(tree.sequence(), midpoint);
+    for key in 0..100 {
+        let expected = if key < 50 { 2 } else { 1 };
+        assert_eq!(tree.read(key).unwrap(), Some([expected; DATA_SIZE]));
+    }
+    tree.verify().unwrap();
+}
+
+#[test]
+fn restore_until_refuses_a_sequence_inside_a_transaction() {
+    let primary = TempIndex::new("backup_tx_primary");
+    let full = TempIndex::new("backup_tx_full");
+    let inside = TempIndex::new("backup_tx_inside");
+    let boundary = TempIndex::new("backup_tx_boundary");
+    let mut tree = primary.open();
+    tree.enable_change_log().unwrap();
+    let base = tree.backup_to(&full.path).unwrap();
+    let mut tx = tree.begin().unwrap();
+    for key in 0..4 {
+        tx.put(key, &[1; DATA_SIZE]).unwrap();
+    }
+    tx.commit().unwrap();
+    tree.write_data(10, &[2; DATA_SIZE]).unwrap();
+    drop(tree);
+
+    let e =
+        BPlusTree::restore_until(&[&full.path], &primary.path, base + 2, &inside.path).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    assert!(e.to_string().contains(&format!("{base} or {}", base + 4)));
+    let tree = inside.open();
+    assert_eq!(tree.sequence(), base);
+    assert_eq!(tree.read(0).unwrap(), None);
+    drop(tree);
+
+    let sequence =
+        BPlusTree::restore_until(&[&full.path], &primary.path, base + 4, &boundary.path).unwrap();
+    assert_eq!(sequence, base + 4);
+    let tree = boundary.open();
+    for key in 0..4 {
+        assert_eq!(tree.read(key).unwrap(), Some([1; DATA_SIZE]));
+    }
+    assert_eq!(tree.read(10).unwrap(), None);
+}
+
+#[test]
+fn incrementals_restore_in_order_against_their_base() {
+    let live = TempIndex::new("backup_live");
+    let full = TempIndex::new("backup_base");
+    let first = TempIndex::new("backup_incr1");
+    let second = TempIndex::new("backup_incr2");
+    let restored = TempIndex::new("backup_copy");
+    let skipped = TempIndex::new("backup_skipped");
+    let mut tree = live.open();
+    tree.enable_change_log().unwrap();
+    for key in 0..2000 {
+        tree.write_data(key, &[1; DATA_SIZE]).unwrap();
+    }
+    assert_eq!(tree.backup_to(&full.path).unwrap(), 2000);
+    assert!(tree.backup_to(&full.path).is_err());
+
+    tree.write_data(5, &[3; DATA_SIZE]).unwrap();
+    tree.delete(6).unwrap();
+    assert_eq!(
+        tree.backup_incremental_to(&full.path, &first.path).unwrap(),
+        2002
+    );
+    tree.write_data(7, &[4; DATA_SIZE]).unwrap();
+    assert_eq!(
+        tree.backup_incremental_to(&first.path, &second.path)
+            .unwrap(),
+        2003
+    );
+    let size = |index: &TempIndex| std::fs::metadata(&index.path).unwrap().len();
+    assert!(size(&second) * 10 < size(&full));
+
+    let sequence =
+        BPlusTree::restore(&[&full.path, &first.path, &second.path], &restored.path).unwrap();
+    assert_eq!(sequence, 2003);
+    let copy = restored.open();
+    copy.verify().unwrap();
+    assert_eq!(copy.sequence(), 2003);
+    assert_eq!(copy.read(5).unwrap(), Some([3; DATA_SIZE]));
+    assert_eq!(copy.read(6).unwrap(), None);
+    assert_eq!(copy.read(7).unwrap(), Some([4; DATA_SIZE]));
+
+    // The second incremental was taken against the first, not the full backup.
+    assert!(BPlusTree::restore(&[&full.path, &second.path], &skipped.path).is_err());
+    assert!(!skipped.path.exists());
+}
@@ -0,0 +1,114 @@
+use super::TempIndex;
+use crate::catalog::CATALOG_CHECKSUM;
+use crate::checksum::seal;
+use crate::{DATA_SIZE, PAGE_SIZE};
+use std::io::ErrorKind;
+
+#[test]
+fn open_trees_cannot_be_dropped_through_a_nested_guard() {
+    let index = TempIndex::new("catalog_nested");
+    let mut tree = index.open();
+    tree.create_tree("outer").unwrap();
+    tree.create_tree("inner").unwrap();
+    tree.create_tree("spare").unwrap();
+
+    {
+        let mut outer = tree.open_tree("outer").unwrap();
+        outer.write_data(1, &[1; DATA_SIZE]).unwrap();
+        let mut inner = outer.open_tree("inner").unwrap();
+        for name in ["outer", "inner"] {
+            let err = inner.drop_tree(name).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        }
+        assert!(inner.drop_tree("spare").unwrap());
+        inner.write_data(2, &[2; DATA_SIZE]).unwrap();
+    }
+
+    // Both guards are gone, so both trees can be dropped and nothing is left behind.
+    assert!(tree.drop_tree("inner").unwrap());
+    {
+        let outer = tree.open_tree("outer").unwrap();
+        assert_eq!(outer.read(1).unwrap(), Some([1; DATA_SIZE]));
+    }
+    assert!(tree.drop_tree("outer").unwrap());
+    assert!(tree.list_trees().unwrap().is_empty());
+    tree.verify().unwrap();
+}
+
+#[test]
+fn named_trees_keep_their_own_keys_across_reopens() {
+    let index = TempIndex::new("catalog_named");
+    let mut tree = index.open();
+    tree.write_data(1, &[0; DATA_SIZE]).unwrap();
+    tree.create_tree("users").unwrap();
+    tree.create_tree("orders").unwrap();
+    assert_eq!(
+        tree.create_tree("users").unwrap_err().kind(),
+        ErrorKind::AlreadyExists
+    );
+    assert_eq!(
+        tree.open_tree("missing").err().unwrap().kind(),
+        ErrorKind::NotFound
+    );
+    {
+        let mut users = tree.open_tree("users").unwrap();
+        for key in 0..2000 {
+            users.write_data(key, &[1; DATA_SIZE]).unwrap();
+        }
+        let mut tx = users.begin().unwrap();
+        tx.put(5000, &[2; DATA_SIZE]).unwrap();
+        tx.commit().unwrap();
+    }
+    drop(tree);
+
+    let mut tree = index.open();
+    assert_eq!(tree.list_trees().unwrap(), vec!["users", "orders"]);
+    assert_eq!(tree.len().unwrap(), 1);
+    {
+        let users = tree.open_tree("users").unwrap();
+        assert_eq!(users.len().unwrap(), 2001);
+        assert_eq!(users.read(1).unwrap(), Some([1; DATA_SIZE]));
+        users.verify().unwrap();
+    }
+    assert!(tree.open_tree("orders").unwrap().is_empty().unwrap());
+
+    let free = tree.stats().unwrap().free_pages;
+    assert!(tree.drop_tree("users").unwrap());
+    assert!(!tree.drop_tree("users").unwrap());
+    assert!(tree.stats().unwrap().free_pages > free);
+    assert_eq!(tree.list_trees().unwrap(), vec!["orders"]);
+}
+
+// A name length past the end of its entry, behind a valid checksum, is reported rather than
+// sliced.
+#[test]
+fn an_overlong_name_length_is_a_malformed_catalog() {
+    let index = TempIndex::new("catalog_name_len");
+    let mut tree = index.open();
+    tree.create_tree("users").unwrap();
+    let catalog = tree.pager.read().unwrap().catalog as usize;
+    drop(tree);
+
+    let mut bytes = std::fs::read(&index.path).unwrap();
+    let page: &mut [u8; PAGE_SIZE] = (&mut bytes[catalog * PAGE_SIZE..][..PAGE_SIZE])
+        .try_into()
+        .unwrap();
+    // The first entry's name length, after the page header and the entry's root and kind.
+    page[8 + 5] = 255;
+    seal(page, CATALOG_CHECKSUM);
+    std::fs::write(&index.path, &bytes).unwrap();
+
+    let mut tree = index.open();
+    assert_eq!(
+        tree.list_trees().unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        tree.open_tree("users").err().unwrap().kind(),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        tree.drop_tree("users").unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+}
@@ -0,0 +1,96 @@
+use super::{sidecar, TempIndex};
+use crate::{BPlusTree, Change, ChangeLog, DATA_SIZE};
+
+#[test]
+fn subscribers_and_the_log_see_committed_changes_in_order() {
+    let index = TempIndex::new("changes_feed");
+    let mut tree = index.open();
+    tree.enable_change_log().unwrap();
+    let feed = tree.subscribe().unwrap();
+    let (a, b) = ([1; DATA_SIZE], [2; DATA_SIZE]);
+    tree.write_data(1, &a).unwrap();
+    tree.write_data(1, &b).unwrap();
+    tree.delete(1).unwrap();
+    tree.delete(3).unwrap();
+
+    let events: Vec<_> = feed.try_iter().collect();
+    let expected = [
+        Change::Put {
+            key: 1,
+            old: None,
+            new: a,
+        },
+        Change::Put {
+            key: 1,
+            old: Some(a),
+            new: b,
+        },
+        Change::Delete { key: 1, old: b },
+    ];
+    assert_eq!(
+        events.iter().map(|event| &event.change).collect::<Vec<_>>(),
+        expected.iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        events
+            .iter()
+            .map(|event| event.sequence)
+            .collect::<Vec<_>>(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(tree.sequence(), 3);
+
+    // Rolled-back work is neither published nor logged.
+    let mut tx = tree.begin().unwrap();
+    tx.put(10, &a).unwrap();
+    let savepoint = tx.savepoint();
+    tx.put(11, &a).unwrap();
+    tx.rollback_to(&savepoint).unwrap();
+    tx.commit().unwrap();
+    let mut tx = tree.begin().unwrap();
+    tx.put(12, &a).unwrap();
+    tx.rollback();
+    let keys: Vec<i32> = feed.try_iter().map(|event| event.change.key()).collect();
+    assert_eq!(keys, vec![10]);
+
+    let mut log = ChangeLog::open(&index.path).unwrap();
+    let logged = log.poll().unwrap();
+    assert_eq!(&logged[..3], &events[..]);
+    assert_eq!(logged.len(), 4);
+    assert!(log.poll().unwrap().is_empty());
+    tree.write_data(20, &b).unwrap();
+    assert_eq!(log.poll().unwrap()[0].sequence, 5);
+    log.seek(2).unwrap();
+    assert_eq!(log.poll().unwrap().len(), 4);
+}
+
+#[test]
+fn the_feed_is_refused_for_named_trees_and_multimaps() {
+    let index = TempIndex::new("changes_refused");
+    let mut tree = index.open();
+    tree.create_tree("other").unwrap();
+    let mut other = tree.open_tree("other").unwrap();
+    assert!(other.subscribe().is_err());
+    assert!(other.enable_change_log().is_err());
+    drop(other);
+
+    let multimap = TempIndex::new("changes_multimap");
+    let mut tree = multimap.open_multimap();
+    assert!(tree.subscribe().is_err());
+}
+
+#[test]
+fn remove_files_deletes_the_change_log_with_the_index() {
+    let index = TempIndex::new("changes_remove");
+    let mut tree = index.open();
+    tree.enable_change_log().unwrap();
+    tree.write_data(1, &[1; DATA_SIZE]).unwrap();
+    drop(tree);
+    assert!(sidecar(&index.path, ".changes").exists());
+
+    BPlusTree::remove_files(&index.path).unwrap();
+    assert!(!index.path.exists());
+    assert!(!sidecar(&index.path, ".wal").exists());
+    assert!(!sidecar(&index.path, ".changes").exists());
+    BPlusTree::remove_files(&index.path).unwrap();
+}
@@ -0,0 +1,54 @@
+use super::{damage_first_leaf, TempIndex};
+use crate::{ChecksumPolicy, Observer, DATA_SIZE};
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Failures(Mutex<Vec<usize>>);
+
+impl Observer for Failures {
+    fn on_checksum_failure(&self, page: usize) {
+        self.0.lock().unwrap().push(page);
+    }
+}
+
+#[test]
+fn each_policy_handles_a_damaged_leaf_its_own_way() {
+    let index = TempIndex::new("checksum_policy");
+    let mut tree = index.open();
+    for key in 0..1000 {
+        tree.write_data(key, &[7; DATA_SIZE]).unwrap();
+    }
+    drop(tree);
+    let leaf = damage_first_leaf(&index.path);
+
+    let mut tree = index.open();
+    let failures = Arc::new(Failures::default());
+    tree.register_observer(failures.clone());
+    let err = tree.read_range_data(0, 999).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains(&format!("page {leaf}")));
+    assert_eq!(tree.checksum_failures(), 1);
+
+    tree.set_checksum_policy(ChecksumPolicy::Log);
+    assert_eq!(tree.read_range_data(0, 999).unwrap().len(), 1000);
+    assert_eq!(tree.checksum_failures(), 2);
+    assert_eq!(*failures.0.lock().unwrap(), vec![leaf, leaf]);
+
+    tree.set_checksum_policy(ChecksumPolicy::Salvage);
+    assert_eq!(tree.read_range_data(0, 999).unwrap().len(), 1000);
+    assert_eq!(tree.checksum_failures(), 2);
+}
+
+#[test]
+fn damaged_meta_page_fails_open() {
+    let index = TempIndex::new("checksum_meta");
+    let mut tree = index.open();
+    tree.write_data(1, &[1; DATA_SIZE]).unwrap();
+    drop(tree);
+    let mut bytes = std::fs::read(&index.path).unwrap();
+    bytes[2000] ^= 0x01;
+    std::fs::write(&index.path, &bytes).unwrap();
+    let err = crate::BPlusTree::open(&index.path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
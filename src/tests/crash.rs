@@ -0,0 +1,265 @@
+use super::TempIndex;
+use crate::pager::IoEvent;
+use crate::{BPlusTree, DATA_SIZE, PAGE_SIZE};
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+
+const SECTOR_SIZE: usize = 512;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Write(i32, u8),
+    Delete(i32),
+    WriteRun(i32, u16, u8),
+    DeleteRun(i32, u16),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fault {
+    // Everything not yet synced is lost.
+    DropUnsynced,
+    // Any subset of the unsynced writes lands, in any order.
+    Reorder,
+    // As Reorder, and the last write to land is torn at sector granularity.
+    Tear,
+}
+
+// Small deterministic generator so a failing case replays the same faults.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+enum Pending {
+    Page(usize, Vec<u8>),
+    // None is a truncate.
+    Wal(Option<Vec<u8>>),
+}
+
+// The index file and its WAL as a disk would hold them. Writes stay pending until synced; a
+// page written twice before a sync only keeps its latest image, as in the page cache.
+#[derive(Default)]
+struct SimDisk {
+    data: Vec<u8>,
+    wal: Vec<u8>,
+    pages: BTreeMap<usize, Vec<u8>>,
+    wal_pending: Vec<Option<Vec<u8>>>,
+}
+
+fn write_at(file: &mut Vec<u8>, offset: usize, bytes: &[u8]) {
+    if file.len() < offset + bytes.len() {
+        file.resize(offset + bytes.len(), 0);
+    }
+    file[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn apply_wal(wal: &mut Vec<u8>, write: &Option<Vec<u8>>) {
+    match write {
+        Some(bytes) => write_at(wal, 0, bytes),
+        None => wal.clear(),
+    }
+}
+
+impl SimDisk {
+    fn from_files(index: &TempIndex) -> Self {
+        SimDisk {
+            data: std::fs::read(&index.path).unwrap(),
+            wal: std::fs::read(index.wal()).unwrap_or_default(),
+            ..SimDisk::default()
+        }
+    }
+
+    fn apply(&mut self, event: &IoEvent) {
+        match event {
+            IoEvent::WalWrite(bytes) => self.wal_pending.push(Some(bytes.clone())),
+            IoEvent::WalTruncate => self.wal_pending.push(None),
+            IoEvent::WalSync => {
+                for write in self.wal_pending.drain(..) {
+                    apply_wal(&mut self.wal, &write);
+                }
+            }
+            IoEvent::PageWrite(page_num, image) => {
+                self.pages.insert(*page_num, image.clone());
+            }
+            IoEvent::PageSync(Some(page_num)) => {
+                if let Some(image) = self.pages.remove(page_num) {
+                    write_at(&mut self.data, page_num * PAGE_SIZE, &image);
+                }
+            }
+            IoEvent::PageSync(None) => {
+                for (page_num, image) in std::mem::take(&mut self.pages) {
+                    write_at(&mut self.data, page_num * PAGE_SIZE, &image);
+                }
+            }
+        }
+    }
+
+    // The files as they might be found after losing power now.
+    fn crash(&self, fault: Fault, rng: &mut Rng) -> (Vec<u8>, Vec<u8>) {
+        let mut data = self.data.clone();
+        let mut wal = self.wal.clone();
+        let mut pending: Vec<Pending> = self
+            .pages
+            .iter()
+            .map(|(page_num, image)| Pending::Page(*page_num, image.clone()))
+            .chain(self.wal_pending.iter().cloned().map(Pending::Wal))
+            .collect();
+        if let Fault::DropUnsynced = fault {
+            pending.clear();
+        }
+        for i in (1..pending.len()).rev() {
+            pending.swap(i, rng.below(i + 1));
+        }
+        pending.truncate(rng.below(pending.len() + 1));
+
+        if let (Fault::Tear, Some(last)) = (fault, pending.last_mut()) {
+            // Sectors that did not make it keep whatever the disk held before.
+            let (before, offset, bytes) = match last {
+                Pending::Page(page_num, image) => (&data, *page_num * PAGE_SIZE, Some(image)),
+                Pending::Wal(write) => (&wal, 0, write.as_mut()),
+            };
+            for (i, sector) in bytes
+                .into_iter()
+                .flat_map(|b| b.chunks_mut(SECTOR_SIZE))
+                .enumerate()
+            {
+                if rng.below(2) == 0 {
+                    let start = offset + i * SECTOR_SIZE;
+                    for (j, byte) in sector.iter_mut().enumerate() {
+                        *byte = before.get(start + j).copied().unwrap_or(0);
+                    }
+                }
+            }
+        }
+
+        for write in &pending {
+            match write {
+                Pending::Page(page_num, image) => write_at(&mut data, page_num * PAGE_SIZE, image),
+                Pending::Wal(write) => apply_wal(&mut wal, write),
+            }
+        }
+        (data, wal)
+    }
+}
+
+fn value(key: i32, seed: u8) -> [u8; DATA_SIZE] {
+    let mut data = [seed; DATA_SIZE];
+    data[..4].copy_from_slice(&key.to_le_bytes());
+    data
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..3_000i32, any::<u8>()).prop_map(|(k, seed)| Op::Write(k, seed)),
+        3 => (0..3_000i32).prop_map(Op::Delete),
+        2 => (0..3_000i32, 1..1_500u16, any::<u8>())
+            .prop_map(|(k, n, seed)| Op::WriteRun(k, n, seed)),
+        2 => (0..3_000i32, 1..1_500u16).prop_map(|(k, n)| Op::DeleteRun(k, n)),
+    ]
+}
+
+fn apply(tree: &mut BPlusTree, model: &mut BTreeMap<i32, [u8; DATA_SIZE]>, op: &Op) {
+    match *op {
+        Op::Write(key, seed) => {
+            tree.write_data(key, &value(key, seed)).unwrap();
+            model.insert(key, value(key, seed));
+        }
+        Op::Delete(key) => {
+            tree.delete(key).unwrap();
+            model.remove(&key);
+        }
+        Op::WriteRun(start, len, seed) => {
+            let mut tx = tree.begin().unwrap();
+            for key in start..start + len as i32 {
+                tx.put(key, &value(key, seed)).unwrap();
+                model.insert(key, value(key, seed));
+            }
+            tx.commit().unwrap();
+        }
+        Op::DeleteRun(start, len) => {
+            let mut tx = tree.begin().unwrap();
+            for key in start..start + len as i32 {
+                tx.delete(key).unwrap();
+                model.remove(&key);
+            }
+            tx.commit().unwrap();
+        }
+    }
+}
+
+// Writes the crashed files out, reopens them and checks the tree holds every acknowledged
+// operation and, at most, the one in flight.
+fn check_recovery(
+    files: (Vec<u8>, Vec<u8>),
+    states: &[BTreeMap<i32, [u8; DATA_SIZE]>],
+    acked: usize,
+) -> Result<(), TestCaseError> {
+    let index = TempIndex::new("crash_image");
+    std::fs::write(&index.path, &files.0).unwrap();
+    std::fs::write(index.wal(), &files.1).unwrap();
+
+    let tree = BPlusTree::open(&index.path)
+        .map_err(|e| TestCaseError::fail(format!("reopen failed: {e}")))?;
+    if let Err(e) = tree.verify() {
+        return Err(TestCaseError::fail(e.to_string()));
+    }
+    let found = tree.read_range_data(i32::MIN, i32::MAX).unwrap();
+    let matches = |state: &BTreeMap<i32, [u8; DATA_SIZE]>| {
+        found.len() == state.len() && found.iter().eq(state.values())
+    };
+    prop_assert!(
+        states[acked..states.len().min(acked + 2)]
+            .iter()
+            .any(matches),
+        "recovered {} records, {} operations acknowledged",
+        found.len(),
+        acked
+    );
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(12))]
+
+    #[test]
+    fn survives_power_loss(ops in prop::collection::vec(op(), 1..10), seed in any::<u64>()) {
+        let index = TempIndex::new("crash");
+        drop(index.open());
+        let mut disk = SimDisk::from_files(&index);
+
+        let mut tree = index.open();
+        *tree.pager.read().unwrap().journal.lock().unwrap() = Some(Vec::new());
+        let mut states = vec![BTreeMap::new()];
+        let mut acked_at = Vec::new();
+        for op in &ops {
+            let mut model = states.last().unwrap().clone();
+            apply(&mut tree, &mut model, op);
+            states.push(model);
+            acked_at.push(tree.pager.read().unwrap().journal.lock().unwrap().as_ref().unwrap().len());
+        }
+        let journal = tree.pager.read().unwrap().journal.lock().unwrap().take().unwrap();
+        drop(tree);
+
+        let mut rng = Rng(seed);
+        for crash_at in 0..=journal.len() {
+            if crash_at > 0 {
+                disk.apply(&journal[crash_at - 1]);
+            }
+            let acked = acked_at.iter().take_while(|&&end| end <= crash_at).count();
+            for fault in [Fault::DropUnsynced, Fault::Reorder, Fault::Tear] {
+                check_recovery(disk.crash(fault, &mut rng), &states, acked)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use super::TempIndex;
+use crate::checksum::seal;
+use crate::pager::{META_CATALOG, META_CHECKSUM, META_NUM_PAGES, META_ROOT};
+use crate::{BPlusTree, DATA_SIZE, LEAF_MIN_WIDTH, PAGE_SIZE};
+use std::io::ErrorKind;
+
+// A tree two levels deep, so the root is an internal node over several leaves.
+fn two_levels(index: &TempIndex) -> BPlusTree {
+    let mut tree = index.open();
+    for key in 0..500 {
+        tree.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+    assert!(!tree.is_leaf_page(tree.root_page as usize));
+    tree
+}
+
+#[test]
+fn child_pointer_back_to_the_root_fails_reads() {
+    let index = TempIndex::new("damage_descent");
+    let mut tree = two_levels(&index);
+    let root = tree.root_page as usize;
+    let mut node = tree.read_internal_node(root).unwrap();
+    node.children[0] = root as i32;
+    tree.write_internal_node(root, &node).unwrap();
+
+    assert!(tree.read(0).is_err());
+    assert!(tree.rank(0).is_err());
+    assert!(tree.nth(0).is_err());
+    assert!(tree.first().is_err());
+    assert!(tree.read_range_data(i32::MIN, i32::MAX).is_err());
+    assert!(tree.stats().is_err());
+    // Last, since its rollback also throws away the damage written above.
+    let err = tree.rebuild_links().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn leaf_chain_loop_fails_scans() {
+    let index = TempIndex::new("damage_chain");
+    let mut tree = two_levels(&index);
+    let first = tree.reader().edge_leaf(false).unwrap();
+    let last = tree.reader().edge_leaf(true).unwrap();
+    let mut leaf = tree.read_leaf_node(last).unwrap();
+    leaf.set_next_leaf(first as i32);
+    tree.write_leaf_node(last, &leaf).unwrap();
+    let mut leaf = tree.read_leaf_node(first).unwrap();
+    leaf.set_prev_leaf(last as i32);
+    tree.write_leaf_node(first, &leaf).unwrap();
+
+    assert!(tree.verify().is_err());
+    assert!(tree.read_range_data(i32::MIN, i32::MAX).is_err());
+    assert!(tree.update_range(.., |_, data| data[0] ^= 1).is_err());
+}
+
+#[test]
+fn underfull_leaf_fails_verify() {
+    let index = TempIndex::new("damage_underfull");
+    let mut tree = two_levels(&index);
+    tree.verify().unwrap();
+    let first = tree.reader().edge_leaf(false).unwrap();
+    let mut leaf = tree.read_leaf_node(first).unwrap();
+    while leaf.width() >= LEAF_MIN_WIDTH {
+        leaf.remove_at(leaf.num_keys() - 1);
+    }
+    tree.write_leaf_node(first, &leaf).unwrap();
+
+    let problems: Vec<String> = tree
+        .reader()
+        .violations()
+        .iter()
+        .filter(|violation| violation.page == first)
+        .map(|violation| violation.problem.clone())
+        .collect();
+    assert!(
+        problems
+            .iter()
+            .any(|problem| problem.starts_with("underfull leaf")),
+        "{problems:?}"
+    );
+}
+
+// Writes a two-level tree out to the file itself, with nothing left in the WAL.
+fn flushed_file(index: &TempIndex) -> Vec<u8> {
+    let mut tree = two_levels(index);
+    tree.flush().unwrap();
+    drop(tree);
+    let _ = std::fs::remove_file(index.wal());
+    std::fs::read(&index.path).unwrap()
+}
+
+fn open_error(index: &TempIndex, file: &[u8]) -> String {
+    std::fs::write(&index.path, file).unwrap();
+    let error = BPlusTree::open(&index.path)
+        .err()
+        .expect("damaged file opened");
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    error.to_string()
+}
+
+// Once a fuzz crash: a file cut short of the page count its meta page claims was mapped and
+// read past the end.
+#[test]
+fn file_shorter_than_its_meta_page_fails_open() {
+    let index = TempIndex::new("damage_truncated");
+    let mut file = flushed_file(&index);
+    file.truncate(file.len() - PAGE_SIZE - 75);
+    assert_eq!(open_error(&index, &file), "corrupt meta page");
+}
+
+// Once a fuzz crash: meta fields shifted out of place handed out root and catalog pages that
+// are not in the file. The meta checksum is resealed so the range checks are what catch it.
+#[test]
+fn meta_page_numbers_past_the_file_fail_open() {
+    let index = TempIndex::new("damage_meta");
+    let file = flushed_file(&index);
+    for (field, value) in [
+        (META_ROOT, 0x004d_0201u32),
+        (META_CATALOG, 0x004d_0201),
+        (META_NUM_PAGES, 0),
+        (META_NUM_PAGES, 0x0300_0000),
+    ] {
+        let mut damaged = file.clone();
+        let meta: &mut [u8; PAGE_SIZE] = (&mut damaged[..PAGE_SIZE]).try_into().unwrap();
+        meta[field..field + 4].copy_from_slice(&value.to_le_bytes());
+        seal(meta, META_CHECKSUM);
+        assert_eq!(open_error(&index, &damaged), "corrupt meta page");
+    }
+}
@@ -0,0 +1,232 @@
+use super::TempIndex;
+use crate::{BPlusTree, DATA_SIZE};
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Write(i32, u8),
+    Delete(i32),
+    Read(i32),
+    Range(i32, i32),
+    // Runs of consecutive keys in one transaction, long enough to split internal nodes on the
+    // way up and to empty whole subtrees on the way down.
+    WriteRun(i32, u16, u8),
+    DeleteRun(i32, u16),
+    DeleteRange(i32, i32),
+    UpdateRange(i32, i32, u8),
+    PopFirst,
+    PopLast,
+    Rank(i32),
+    Nth(usize),
+    CountRange(i32, i32),
+    LowerBound(i32),
+    UpperBound(i32),
+    // A run written before a savepoint is kept; a range deleted and a run written after it are
+    // rolled back before the transaction commits.
+    Savepoint {
+        kept: (i32, u16, u8),
+        discarded: (i32, u16, u8),
+    },
+    Reopen,
+}
+
+fn value(key: i32, seed: u8) -> [u8; DATA_SIZE] {
+    let mut data = [seed; DATA_SIZE];
+    data[..4].copy_from_slice(&key.to_le_bytes());
+    data
+}
+
+// Keys crowd a narrow band so single writes and deletes keep hitting the runs.
+fn key() -> impl Strategy<Value = i32> {
+    prop_oneof![
+        8 => -2_000..12_000i32,
+        1 => any::<i32>(),
+        1 => Just(i32::MIN),
+        1 => Just(i32::MAX),
+    ]
+}
+
+fn run() -> impl Strategy<Value = (i32, u16, u8)> {
+    (key(), 1..4_000u16, any::<u8>())
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        6 => (key(), any::<u8>()).prop_map(|(k, seed)| Op::Write(k, seed)),
+        4 => key().prop_map(Op::Delete),
+        3 => key().prop_map(Op::Read),
+        2 => (key(), key()).prop_map(|(a, b)| Op::Range(a, b)),
+        2 => (key(), 1..12_000u16, any::<u8>()).prop_map(|(k, n, seed)| Op::WriteRun(k, n, seed)),
+        2 => (key(), 1..12_000u16).prop_map(|(k, n)| Op::DeleteRun(k, n)),
+        1 => (key(), key()).prop_map(|(a, b)| Op::DeleteRange(a, b)),
+        1 => (key(), key(), any::<u8>()).prop_map(|(a, b, seed)| Op::UpdateRange(a, b, seed)),
+        1 => Just(Op::PopFirst),
+        1 => Just(Op::PopLast),
+        1 => key().prop_map(Op::Rank),
+        1 => (0..14_000usize).prop_map(Op::Nth),
+        1 => (key(), key()).prop_map(|(a, b)| Op::CountRange(a, b)),
+        1 => key().prop_map(Op::LowerBound),
+        1 => key().prop_map(Op::UpperBound),
+        1 => (run(), run()).prop_map(|(kept, discarded)| Op::Savepoint { kept, discarded }),
+        1 => Just(Op::Reopen),
+    ]
+}
+
+fn run_keys(start: i32, len: u16) -> impl Iterator<Item = i32> {
+    (0..len as i32).map_while(move |i| start.checked_add(i))
+}
+
+fn in_range(model: &BTreeMap<i32, [u8; DATA_SIZE]>, start: i32, end: i32) -> Vec<i32> {
+    if start <= end {
+        model.range(start..=end).map(|(key, _)| *key).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+fn apply(
+    tree: &mut BPlusTree,
+    model: &mut BTreeMap<i32, [u8; DATA_SIZE]>,
+    op: &Op,
+) -> Result<(), TestCaseError> {
+    match *op {
+        Op::Write(key, seed) => {
+            let new = model.insert(key, value(key, seed)).is_none();
+            prop_assert_eq!(tree.write_data(key, &value(key, seed)).unwrap(), new);
+        }
+        Op::Delete(key) => {
+            prop_assert_eq!(tree.delete(key).unwrap(), model.remove(&key).is_some());
+        }
+        Op::Read(key) => {
+            prop_assert_eq!(tree.read(key).unwrap(), model.get(&key).copied());
+        }
+        Op::Range(start, end) => {
+            let expected: Vec<_> = if start <= end {
+                model.range(start..=end).map(|(_, data)| *data).collect()
+            } else {
+                Vec::new()
+            };
+            prop_assert!(tree.read_range_data(start, end).unwrap() == expected);
+        }
+        Op::WriteRun(start, len, seed) => {
+            let mut tx = tree.begin().unwrap();
+            for key in run_keys(start, len) {
+                tx.put(key, &value(key, seed)).unwrap();
+                model.insert(key, value(key, seed));
+            }
+            tx.commit().unwrap();
+        }
+        Op::DeleteRun(start, len) => {
+            let mut tx = tree.begin().unwrap();
+            for key in run_keys(start, len) {
+                tx.delete(key).unwrap();
+                model.remove(&key);
+            }
+            tx.commit().unwrap();
+        }
+        Op::DeleteRange(start, end) => {
+            let keys = in_range(model, start, end);
+            for key in &keys {
+                model.remove(key);
+            }
+            prop_assert_eq!(tree.delete_range(start..=end).unwrap(), keys.len());
+        }
+        Op::UpdateRange(start, end, seed) => {
+            let mut changed = 0;
+            for key in in_range(model, start, end) {
+                if model.insert(key, value(key, seed)) != Some(value(key, seed)) {
+                    changed += 1;
+                }
+            }
+            let rewritten = tree
+                .update_range(start..=end, |key, data| {
+                    if *data != value(key, seed) {
+                        *data = value(key, seed);
+                    }
+                })
+                .unwrap();
+            prop_assert_eq!(rewritten, changed);
+        }
+        Op::PopFirst => prop_assert_eq!(tree.pop_first().unwrap(), model.pop_first()),
+        Op::PopLast => prop_assert_eq!(tree.pop_last().unwrap(), model.pop_last()),
+        Op::Rank(key) => prop_assert_eq!(tree.rank(key).unwrap(), model.range(..key).count()),
+        Op::Nth(index) => {
+            let expected = model.iter().nth(index).map(|(k, v)| (*k, *v));
+            prop_assert_eq!(tree.nth(index).unwrap(), expected);
+        }
+        Op::CountRange(start, end) => {
+            let expected = in_range(model, start, end).len();
+            prop_assert_eq!(tree.count_range(start..=end).unwrap(), expected);
+        }
+        Op::LowerBound(key) => {
+            let expected = model.range(key..).next().map(|(k, v)| (*k, *v));
+            prop_assert_eq!(tree.lower_bound(key).unwrap(), expected);
+        }
+        Op::UpperBound(key) => {
+            let expected = model
+                .range((Bound::Excluded(key), Bound::Unbounded))
+                .next()
+                .map(|(k, v)| (*k, *v));
+            prop_assert_eq!(tree.upper_bound(key).unwrap(), expected);
+        }
+        Op::Savepoint { kept, discarded } => {
+            let mut tx = tree.begin().unwrap();
+            for key in run_keys(kept.0, kept.1) {
+                tx.put(key, &value(key, kept.2)).unwrap();
+                model.insert(key, value(key, kept.2));
+            }
+            let savepoint = tx.savepoint();
+            let (start, len, seed) = discarded;
+            tx.delete_range(start..=start.saturating_add(len as i32))
+                .unwrap();
+            for key in run_keys(start.wrapping_add(len as i32 / 2), len) {
+                tx.put(key, &value(key, seed)).unwrap();
+            }
+            tx.rollback_to(&savepoint).unwrap();
+            tx.release(savepoint).unwrap();
+            tx.commit().unwrap();
+        }
+        Op::Reopen => unreachable!(),
+    }
+    Ok(())
+}
+
+fn check(tree: &BPlusTree, model: &BTreeMap<i32, [u8; DATA_SIZE]>) -> Result<(), TestCaseError> {
+    if let Err(e) = tree.verify() {
+        return Err(TestCaseError::fail(e.to_string()));
+    }
+    prop_assert_eq!(tree.len().unwrap(), model.len());
+    prop_assert_eq!(
+        tree.first().unwrap(),
+        model.first_key_value().map(|(k, v)| (*k, *v))
+    );
+    prop_assert_eq!(
+        tree.last().unwrap(),
+        model.last_key_value().map(|(k, v)| (*k, *v))
+    );
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn matches_btreemap(ops in prop::collection::vec(op(), 1..40)) {
+        let index = TempIndex::new("differential");
+        let mut tree = index.open();
+        let mut model = BTreeMap::new();
+        for op in &ops {
+            if let Op::Reopen = op {
+                drop(tree);
+                tree = index.open();
+            } else {
+                apply(&mut tree, &mut model, op)?;
+            }
+            check(&tree, &model)?;
+        }
+        let all: Vec<_> = model.values().copied().collect();
+        prop_assert!(tree.read_range_data(i32::MIN, i32::MAX).unwrap() == all);
+    }
+}
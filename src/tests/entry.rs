@@ -0,0 +1,67 @@
+use super::TempIndex;
+use crate::{Entry, DATA_SIZE};
+
+fn value(seed: u8) -> [u8; DATA_SIZE] {
+    [seed; DATA_SIZE]
+}
+
+#[test]
+fn entries_read_and_write_through_one_descent() {
+    let index = TempIndex::new("entry_api");
+    let mut tree = index.open();
+    assert_eq!(
+        tree.entry(1).unwrap().or_insert(value(1)).unwrap(),
+        value(1)
+    );
+    assert_eq!(
+        tree.entry(1).unwrap().or_insert(value(2)).unwrap(),
+        value(1)
+    );
+    tree.entry(1)
+        .unwrap()
+        .and_modify(|data| data[0] = 9)
+        .unwrap();
+
+    match tree.entry(1).unwrap() {
+        Entry::Occupied(mut entry) => {
+            assert_eq!(entry.key(), 1);
+            assert_eq!(entry.get().unwrap()[0], 9);
+            assert_eq!(entry.insert(value(3)).unwrap()[0], 9);
+            assert_eq!(entry.get().unwrap(), value(3));
+            assert_eq!(entry.remove().unwrap(), value(3));
+        }
+        Entry::Vacant(_) => panic!("key 1 was written"),
+    }
+    assert_eq!(tree.read(1).unwrap(), None);
+
+    match tree.entry(2).unwrap() {
+        Entry::Vacant(entry) => entry.insert(value(4)).unwrap(),
+        Entry::Occupied(_) => panic!("key 2 was never written"),
+    }
+    assert_eq!(tree.read(2).unwrap(), Some(value(4)));
+}
+
+#[test]
+fn vacant_inserts_split_leaves_and_roll_back_with_the_transaction() {
+    let index = TempIndex::new("entry_splits");
+    let mut tree = index.open();
+    for key in 0..2000 {
+        tree.entry(key)
+            .unwrap()
+            .or_insert_with(|| value(key as u8))
+            .unwrap();
+    }
+    assert_eq!(tree.len().unwrap(), 2000);
+    assert!(tree.stats().unwrap().splits > 0);
+    tree.verify().unwrap();
+
+    let mut tx = tree.begin().unwrap();
+    tx.entry(5000).unwrap().or_insert(value(1)).unwrap();
+    tx.entry(5)
+        .unwrap()
+        .and_modify(|data| data[0] = 0xff)
+        .unwrap();
+    tx.rollback();
+    assert_eq!(tree.read(5000).unwrap(), None);
+    assert_eq!(tree.read(5).unwrap(), Some(value(5)));
+}
@@ -0,0 +1,33 @@
+use super::{damage_first_leaf, TempIndex};
+use crate::DATA_SIZE;
+
+#[test]
+fn exports_show_every_page_and_mark_damaged_ones() {
+    let index = TempIndex::new("export_tree");
+    let mut tree = index.open();
+    for key in 0..2000 {
+        tree.write_data(key, &[6; DATA_SIZE]).unwrap();
+    }
+    let stats = tree.stats().unwrap();
+    let json = tree.export_json(None, None).unwrap();
+    assert_eq!(json.matches("\"kind\": \"leaf\"").count(), stats.leaf_pages);
+    assert_eq!(
+        json.matches("\"kind\": \"internal\"").count(),
+        stats.internal_pages
+    );
+    let root_only = tree.export_dot(None, Some(0)).unwrap();
+    assert_eq!(root_only.matches("[label=\"{").count(), 1);
+    assert!(tree.export_dot(Some(1_000_000), None).is_err());
+    drop(tree);
+
+    let leaf = damage_first_leaf(&index.path);
+    let tree = index.open();
+    let dot = tree.export_dot(None, None).unwrap();
+    let red: Vec<&str> = dot
+        .lines()
+        .filter(|line| line.contains("color=red"))
+        .collect();
+    assert_eq!(red.len(), 1);
+    assert!(red[0].starts_with(&format!("  p{leaf} ")));
+    assert!(red[0].contains("checksum mismatch"));
+}
@@ -0,0 +1,35 @@
+use crate::fuzzing;
+use std::panic::catch_unwind;
+use std::path::Path;
+
+// Runs every checked-in input for a target: the seed corpus and the inputs that once crashed it.
+fn replay(target: &str, run: fn(&[u8])) {
+    let fuzz = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz");
+    for dir in ["corpus", "regressions"] {
+        let Ok(entries) = std::fs::read_dir(fuzz.join(dir).join(target)) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry.unwrap().path();
+            let input = std::fs::read(&path).unwrap();
+            if catch_unwind(|| run(&input)).is_err() {
+                panic!("{} failed", path.display());
+            }
+        }
+    }
+}
+
+#[test]
+fn index_file_inputs() {
+    replay("index_file", fuzzing::index_file);
+}
+
+#[test]
+fn page_image_inputs() {
+    replay("page_image", fuzzing::page_image);
+}
+
+#[test]
+fn api_sequence_inputs() {
+    replay("api_sequence", fuzzing::api_sequence);
+}
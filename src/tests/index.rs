@@ -0,0 +1,59 @@
+use super::TempIndex;
+use crate::DATA_SIZE;
+use std::io::ErrorKind;
+
+fn record(city: u8) -> [u8; DATA_SIZE] {
+    let mut data = [0u8; DATA_SIZE];
+    data[0] = city;
+    data
+}
+
+fn city(data: &[u8; DATA_SIZE]) -> i32 {
+    data[0] as i32
+}
+
+#[test]
+fn index_follows_writes_and_deletes() {
+    let index = TempIndex::new("index_sync");
+    let mut tree = index.open();
+    for key in 0..200 {
+        tree.write_data(key, &record((key % 5) as u8)).unwrap();
+    }
+    tree.register_index("city", city).unwrap();
+    assert_eq!(tree.lookup_by("city", 2).unwrap().len(), 40);
+
+    tree.write_data(2, &record(4)).unwrap();
+    tree.delete(7).unwrap();
+    let keys: Vec<i32> = tree
+        .lookup_by("city", 2)
+        .unwrap()
+        .iter()
+        .map(|(key, _)| *key)
+        .collect();
+    assert_eq!(keys.len(), 38);
+    assert!(!keys.contains(&2) && !keys.contains(&7));
+    tree.verify_index("city").unwrap();
+}
+
+#[test]
+fn index_cannot_be_registered_or_dropped_through_a_named_tree() {
+    let index = TempIndex::new("index_named");
+    let mut tree = index.open();
+    tree.create_tree("other").unwrap();
+    tree.register_index("city", city).unwrap();
+
+    {
+        let mut other = tree.open_tree("other").unwrap();
+        let err = other.register_index("zip", city).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        let err = other.drop_index("city").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    // The main tree still maintains the index and every write keeps it in sync.
+    tree.write_data(1, &record(3)).unwrap();
+    tree.delete(1).unwrap();
+    tree.verify_index("city").unwrap();
+    assert!(tree.drop_index("city").unwrap());
+    tree.write_data(2, &record(3)).unwrap();
+}
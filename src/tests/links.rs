@@ -0,0 +1,35 @@
+use super::TempIndex;
+use crate::DATA_SIZE;
+
+#[test]
+fn rebuild_links_rewrites_only_stale_pages() {
+    let index = TempIndex::new("links_rebuild");
+    let mut tree = index.open();
+    for key in 0..5000 {
+        tree.write_data(key, &[3; DATA_SIZE]).unwrap();
+    }
+    assert_eq!(tree.rebuild_links().unwrap(), 0);
+
+    // One leaf with a wrong parent pointer and another with a wrong next link.
+    let first = tree.reader().edge_leaf(false).unwrap();
+    let last = tree.reader().edge_leaf(true).unwrap();
+    let mut leaf = tree.read_leaf_node(first).unwrap();
+    leaf.set_parent(first as i32);
+    tree.write_leaf_node(first, &leaf).unwrap();
+    let mut leaf = tree.read_leaf_node(last).unwrap();
+    leaf.set_next_leaf(first as i32);
+    tree.write_leaf_node(last, &leaf).unwrap();
+    assert!(tree.verify().is_err());
+
+    assert_eq!(tree.rebuild_links().unwrap(), 2);
+    tree.verify().unwrap();
+    assert_eq!(tree.rebuild_links().unwrap(), 0);
+
+    // Merges keep the links right too.
+    tree.delete_range(1000..3000).unwrap();
+    for key in (3000..5000).step_by(2) {
+        tree.delete(key).unwrap();
+    }
+    assert_eq!(tree.rebuild_links().unwrap(), 0);
+    assert_eq!(tree.len().unwrap(), 2000);
+}
@@ -0,0 +1,81 @@
+use crate::pager::{sidecar, SIDECARS};
+use crate::{BPlusTree, PAGE_SIZE};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod backup;
+mod catalog;
+mod changes;
+mod checksum;
+mod crash;
+mod damage;
+mod differential;
+mod entry;
+mod export;
+mod fuzz;
+mod index;
+mod links;
+mod multimap;
+mod observer;
+mod order;
+mod range;
+mod repair;
+mod replication;
+mod snapshot;
+mod stats;
+mod transaction;
+
+// An index file in the temp directory, removed with its WAL and change log when dropped so
+// failing and shrinking runs do not leave files behind.
+pub(crate) struct TempIndex {
+    pub(crate) path: PathBuf,
+}
+
+impl TempIndex {
+    pub(crate) fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path =
+            std::env::temp_dir().join(format!("bptree_{name}_{}_{id}.dat", std::process::id()));
+        let index = TempIndex { path };
+        index.remove();
+        index
+    }
+
+    pub(crate) fn open(&self) -> BPlusTree {
+        BPlusTree::open(&self.path).unwrap()
+    }
+
+    pub(crate) fn open_multimap(&self) -> BPlusTree {
+        BPlusTree::open_multimap(&self.path).unwrap()
+    }
+
+    fn wal(&self) -> PathBuf {
+        sidecar(&self.path, ".wal")
+    }
+
+    fn remove(&self) {
+        let _ = std::fs::remove_file(&self.path);
+        for extension in SIDECARS {
+            let _ = std::fs::remove_file(sidecar(&self.path, extension));
+        }
+    }
+}
+
+// Flips one bit in the first leaf of a closed index file, which fails only its checksum, and
+// returns the leaf's page number.
+pub(crate) fn damage_first_leaf(path: &Path) -> usize {
+    let mut bytes = std::fs::read(path).unwrap();
+    let leaf = (1..bytes.len() / PAGE_SIZE)
+        .find(|page| bytes[page * PAGE_SIZE] == 1)
+        .expect("no leaf in the file");
+    bytes[leaf * PAGE_SIZE + PAGE_SIZE - 96] ^= 0x01;
+    std::fs::write(path, &bytes).unwrap();
+    leaf
+}
+
+impl Drop for TempIndex {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
@@ -0,0 +1,196 @@
+use super::TempIndex;
+use crate::{BPlusTree, Entry, DATA_SIZE, INLINE_VALUES, LEAF_MIN_WIDTH};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+
+fn value(seed: u32) -> [u8; DATA_SIZE] {
+    let mut data = [0u8; DATA_SIZE];
+    data[..4].copy_from_slice(&seed.to_le_bytes());
+    data
+}
+
+fn num_pages(tree: &BPlusTree) -> usize {
+    tree.pager.read().unwrap().num_pages()
+}
+
+#[test]
+fn small_duplicate_sets_stay_in_the_leaf() {
+    let index = TempIndex::new("multimap_inline");
+    let mut tree = index.open_multimap();
+    for key in 0..1000 {
+        tree.insert_value(key, &value(2 * key as u32)).unwrap();
+        tree.insert_value(key, &value(2 * key as u32 + 1)).unwrap();
+    }
+    tree.verify().unwrap();
+    for key in [0, 499, 999] {
+        let expected = vec![value(2 * key as u32), value(2 * key as u32 + 1)];
+        assert_eq!(tree.get_all(key).unwrap(), expected);
+    }
+    // 2,000 values at up to 38 a leaf; a posting page per key would take over a thousand.
+    assert!(num_pages(&tree) < 150, "{} pages", num_pages(&tree));
+}
+
+#[test]
+fn values_move_to_posting_pages_past_the_threshold_and_back() {
+    let index = TempIndex::new("multimap_threshold");
+    let mut tree = index.open_multimap();
+    let values: Vec<_> = (0..INLINE_VALUES as u32 + 3).map(value).collect();
+    for data in &values[..INLINE_VALUES] {
+        tree.insert_value(7, data).unwrap();
+    }
+    let inline_pages = num_pages(&tree);
+
+    for data in &values[INLINE_VALUES..] {
+        tree.insert_value(7, data).unwrap();
+    }
+    tree.verify().unwrap();
+    assert_eq!(num_pages(&tree), inline_pages + 1);
+    assert_eq!(tree.get_all(7).unwrap(), values);
+    assert_eq!(tree.read(7).unwrap(), Some(values[0]));
+
+    for data in &values[1..] {
+        assert!(tree.delete_one(7, data).unwrap());
+        tree.verify().unwrap();
+    }
+    assert_eq!(tree.get_all(7).unwrap(), vec![values[0]]);
+    assert_eq!(tree.delete_all(7).unwrap(), 1);
+    assert!(tree.get_all(7).unwrap().is_empty());
+}
+
+// Mixed appends and removals across enough keys to split and merge leaves holding inline
+// sets, checked against a map of vectors after every step.
+#[test]
+fn inline_sets_survive_splits_and_merges() {
+    let index = TempIndex::new("multimap_mixed");
+    let mut tree = index.open_multimap();
+    let mut model: BTreeMap<i32, Vec<[u8; DATA_SIZE]>> = BTreeMap::new();
+    let mut state = 0x2545_f491u32;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+    for step in 0..4000 {
+        let key = (next() % 300) as i32;
+        match next() % 4 {
+            0 | 1 => {
+                let data = value(next() % 8);
+                tree.insert_value(key, &data).unwrap();
+                model.entry(key).or_default().push(data);
+            }
+            2 => {
+                let data = value(next() % 8);
+                let values = model.entry(key).or_default();
+                let position = values.iter().position(|v| *v == data);
+                assert_eq!(tree.delete_one(key, &data).unwrap(), position.is_some());
+                if let Some(i) = position {
+                    values.remove(i);
+                }
+            }
+            _ => {
+                let removed = model.remove(&key).map_or(0, |values| values.len());
+                assert_eq!(tree.delete_all(key).unwrap(), removed);
+            }
+        }
+        model.retain(|_, values| !values.is_empty());
+        if step % 100 == 0 {
+            tree.verify().unwrap();
+        }
+    }
+    tree.verify().unwrap();
+    for (key, values) in &model {
+        assert_eq!(tree.get_all(*key).unwrap(), *values, "key {key}");
+    }
+    assert_eq!(tree.len().unwrap(), model.len());
+    let scanned = tree.read_range_data(i32::MIN, i32::MAX).unwrap();
+    assert_eq!(
+        scanned,
+        model.values().flatten().copied().collect::<Vec<_>>()
+    );
+}
+
+// The fewest values held by a leaf other than the root.
+fn narrowest_leaf(tree: &BPlusTree) -> usize {
+    let reader = tree.reader();
+    let mut page = reader.edge_leaf(false).unwrap() as i32;
+    let mut narrowest = usize::MAX;
+    while page != -1 {
+        let leaf = tree.read_leaf_node(page as usize).unwrap();
+        if leaf.parent() != -1 {
+            narrowest = narrowest.min(leaf.width());
+        }
+        page = leaf.next_leaf();
+    }
+    narrowest
+}
+
+// Every third key holds a full inline set, so a sibling lending its edge cell can give up
+// INLINE_VALUES values at once.
+#[test]
+fn borrowing_a_whole_inline_set_leaves_the_lender_half_full() {
+    let index = TempIndex::new("multimap_borrow");
+    let mut tree = index.open_multimap();
+    for key in 0..600 {
+        let count = if key % 3 == 0 { INLINE_VALUES } else { 1 };
+        for seed in 0..count as u32 {
+            tree.insert_value(key, &value(seed)).unwrap();
+        }
+    }
+    assert!(narrowest_leaf(&tree) >= LEAF_MIN_WIDTH);
+
+    let mut state = 0x9e37_79b9u32;
+    let mut keys: Vec<i32> = (0..600).collect();
+    while keys.len() > 100 {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let key = keys.swap_remove(state as usize % keys.len());
+        if key % 2 == 0 {
+            tree.delete_all(key).unwrap();
+        } else {
+            // Single values go one at a time, inline sets shrink by one.
+            tree.delete_one(key, &value(0)).unwrap();
+        }
+        assert!(
+            narrowest_leaf(&tree) >= LEAF_MIN_WIDTH,
+            "after removing {key}"
+        );
+    }
+    tree.verify().unwrap();
+}
+
+#[test]
+fn overwrites_are_refused_instead_of_dropping_values() {
+    let index = TempIndex::new("multimap_overwrite");
+    let mut tree = index.open_multimap();
+    let values = [value(1), value(2)];
+    for data in &values {
+        tree.insert_value(3, data).unwrap();
+    }
+
+    let refused = |result: std::io::Result<()>| {
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    };
+    refused(tree.put(3, &value(9)).map(|_| ()));
+    refused(tree.update_if_present(3, &value(9)).map(|_| ()));
+    refused(
+        tree.compare_and_swap(3, Some(&values[0]), Some(&value(9)))
+            .map(|_| ()),
+    );
+    match tree.entry(3).unwrap() {
+        Entry::Occupied(mut entry) => refused(entry.insert(value(9)).map(|_| ())),
+        Entry::Vacant(_) => panic!("key 3 has values"),
+    }
+    let mut tx = tree.begin().unwrap();
+    refused(tx.put(3, &value(9)).map(|_| ()));
+    tx.commit().unwrap();
+    assert_eq!(tree.get_all(3).unwrap(), values);
+
+    // A new key still takes its first value through `put`.
+    assert_eq!(tree.put(4, &value(9)).unwrap(), None);
+    assert_eq!(tree.get_all(4).unwrap(), vec![value(9)]);
+    // Lengths count keys, not values.
+    assert_eq!(tree.len().unwrap(), 2);
+    assert_eq!(tree.count_range(..).unwrap(), 2);
+}
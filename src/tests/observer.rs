@@ -0,0 +1,65 @@
+use super::TempIndex;
+use crate::{Observer, DATA_SIZE};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Events {
+    splits: AtomicU64,
+    merges: AtomicU64,
+    allocations: AtomicU64,
+    flushes: AtomicU64,
+    roots: Mutex<Vec<i32>>,
+}
+
+impl Observer for Events {
+    fn on_split(&self, _page: usize, _new_page: usize) {
+        self.splits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_merge(&self, _left: usize, _right: usize) {
+        self.merges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_root_change(&self, _old_root: i32, new_root: i32) {
+        self.roots.lock().unwrap().push(new_root);
+    }
+
+    fn on_page_alloc(&self, _page: usize) {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_flush(&self, _bytes: u64) {
+        self.flushes.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn observers_see_the_events_the_stats_count() {
+    let index = TempIndex::new("observer_events");
+    let mut tree = index.open();
+    let events = Arc::new(Events::default());
+    tree.register_observer(events.clone());
+    let before = tree.stats().unwrap();
+    for key in 0..3000 {
+        tree.write_data(key, &[7; DATA_SIZE]).unwrap();
+    }
+    for key in 0..3000 {
+        tree.delete(key).unwrap();
+    }
+
+    let after = tree.stats().unwrap();
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    assert!(load(&events.splits) > 0 && load(&events.merges) > 0);
+    assert_eq!(load(&events.splits), after.splits - before.splits);
+    assert_eq!(load(&events.merges), after.merges - before.merges);
+    assert_eq!(
+        load(&events.allocations),
+        after.page_allocations - before.page_allocations
+    );
+    assert_eq!(load(&events.flushes), 6000);
+    let roots = events.roots.lock().unwrap();
+    assert_eq!(roots.len() as u64, after.root_changes - before.root_changes);
+    // Emptied down to a single leaf, which stays the root.
+    assert_eq!(*roots.last().unwrap(), tree.root_page);
+}
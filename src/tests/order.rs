@@ -0,0 +1,49 @@
+use super::TempIndex;
+use crate::{BPlusTree, DATA_SIZE};
+
+fn key(entry: Option<(i32, [u8; DATA_SIZE])>) -> Option<i32> {
+    entry.map(|(key, _)| key)
+}
+
+fn write_even_keys(tree: &mut BPlusTree) {
+    for key in (0..4000).step_by(2) {
+        tree.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+}
+
+#[test]
+fn rank_and_nth_on_even_keys() {
+    let index = TempIndex::new("order_rank");
+    let mut tree = index.open();
+    assert_eq!(tree.nth(0).unwrap(), None);
+    assert_eq!(tree.rank(0).unwrap(), 0);
+    write_even_keys(&mut tree);
+
+    assert_eq!(tree.rank(-1).unwrap(), 0);
+    assert_eq!(tree.rank(1000).unwrap(), 500);
+    assert_eq!(tree.rank(1001).unwrap(), 501);
+    assert_eq!(tree.rank(i32::MAX).unwrap(), 2000);
+    for i in [0, 1, 999, 1999] {
+        assert_eq!(key(tree.nth(i).unwrap()), Some(2 * i as i32));
+    }
+    assert_eq!(tree.nth(2000).unwrap(), None);
+}
+
+#[test]
+fn first_last_and_bounds_on_even_keys() {
+    let index = TempIndex::new("order_bounds");
+    let mut tree = index.open();
+    assert_eq!(tree.first().unwrap(), None);
+    assert_eq!(tree.last().unwrap(), None);
+    write_even_keys(&mut tree);
+
+    assert_eq!(key(tree.first().unwrap()), Some(0));
+    assert_eq!(key(tree.last().unwrap()), Some(3998));
+    assert_eq!(key(tree.lower_bound(1000).unwrap()), Some(1000));
+    assert_eq!(key(tree.lower_bound(1001).unwrap()), Some(1002));
+    assert_eq!(key(tree.upper_bound(1000).unwrap()), Some(1002));
+    assert_eq!(key(tree.floor(1001).unwrap()), Some(1000));
+    assert_eq!(key(tree.floor(-1).unwrap()), None);
+    assert_eq!(key(tree.ceiling(1001).unwrap()), Some(1002));
+    assert_eq!(key(tree.upper_bound(3998).unwrap()), None);
+}
@@ -0,0 +1,59 @@
+use super::TempIndex;
+use crate::DATA_SIZE;
+
+fn value(key: i32) -> [u8; DATA_SIZE] {
+    [key as u8; DATA_SIZE]
+}
+
+#[test]
+fn range_operations_cover_exactly_their_bounds() {
+    let index = TempIndex::new("range_ops");
+    let mut tree = index.open();
+    for key in 0..1000 {
+        tree.write_data(key, &value(key)).unwrap();
+    }
+
+    assert_eq!(tree.count_range(..).unwrap(), 1000);
+    assert_eq!(tree.count_range(100..200).unwrap(), 100);
+    assert_eq!(tree.count_range(100..=200).unwrap(), 101);
+    assert_eq!(tree.count_range(2000..).unwrap(), 0);
+    let read = tree.read_range_data(10, 19).unwrap();
+    assert_eq!(read, (10..=19).map(value).collect::<Vec<_>>());
+
+    // Only values the closure changes count as rewritten.
+    let rewritten = tree
+        .update_range(500..=509, |key, data| {
+            if key % 2 == 0 {
+                data[0] = 0xff;
+            }
+        })
+        .unwrap();
+    assert_eq!(rewritten, 5);
+    assert_eq!(tree.read(500).unwrap().unwrap()[0], 0xff);
+    assert_eq!(tree.read(501).unwrap(), Some(value(501)));
+
+    assert_eq!(tree.delete_range(100..600).unwrap(), 500);
+    assert_eq!(tree.len().unwrap(), 500);
+    assert_eq!(tree.read(99).unwrap(), Some(value(99)));
+    assert_eq!(tree.read(100).unwrap(), None);
+    assert_eq!(tree.read(600).unwrap(), Some(value(600)));
+    assert_eq!(tree.delete_range(100..600).unwrap(), 0);
+    tree.verify().unwrap();
+}
+
+#[test]
+fn pops_take_from_either_end_until_empty() {
+    let index = TempIndex::new("range_pops");
+    let mut tree = index.open();
+    for key in 0..600 {
+        tree.write_data(key, &value(key)).unwrap();
+    }
+    for i in 0..300 {
+        assert_eq!(tree.pop_first().unwrap(), Some((i, value(i))));
+        assert_eq!(tree.pop_last().unwrap(), Some((599 - i, value(599 - i))));
+    }
+    assert_eq!(tree.pop_first().unwrap(), None);
+    assert_eq!(tree.pop_last().unwrap(), None);
+    assert!(tree.is_empty().unwrap());
+    tree.verify().unwrap();
+}
@@ -0,0 +1,70 @@
+use super::{damage_first_leaf, TempIndex};
+use crate::{BPlusTree, DATA_SIZE, PAGE_SIZE};
+
+#[test]
+fn repair_copies_every_intact_leaf_and_reports_the_rest() {
+    let damaged = TempIndex::new("repair_damaged");
+    let repaired = TempIndex::new("repair_output");
+    let mut tree = damaged.open();
+    for key in 0..1000 {
+        tree.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+    drop(tree);
+    let leaf = damage_first_leaf(&damaged.path);
+
+    let report = BPlusTree::repair(&damaged.path, &repaired.path).unwrap();
+    assert_eq!(report.damaged_pages, vec![leaf]);
+    assert_eq!(report.lost.len(), 1);
+    let lost = &report.lost[0];
+    assert_eq!(lost.tree, None);
+    let expected = lost.expected.unwrap() as usize;
+
+    let tree = repaired.open();
+    tree.verify().unwrap();
+    assert_eq!(tree.len().unwrap() + expected, 1000);
+    for key in 0..1000 {
+        let in_lost =
+            lost.start.is_none_or(|start| key >= start) && lost.end.is_none_or(|end| key < end);
+        match tree.read(key).unwrap() {
+            Some(data) => assert_eq!(data, [key as u8; DATA_SIZE]),
+            None => assert!(in_lost, "key {key} lost outside the reported range"),
+        }
+    }
+    drop(tree);
+
+    // The output is never overwritten.
+    assert!(BPlusTree::repair(&damaged.path, &repaired.path).is_err());
+}
+
+// With the meta page gone, every page is tried as the catalog, including stray ones whose
+// entries do not fit.
+#[test]
+fn repair_without_a_meta_page_reports_a_garbled_catalog_as_lost() {
+    let damaged = TempIndex::new("repair_catalog_damaged");
+    let repaired = TempIndex::new("repair_catalog_output");
+    let mut tree = damaged.open();
+    for key in 0..100 {
+        tree.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+    tree.create_tree("users").unwrap();
+    let catalog = tree.pager.read().unwrap().catalog as usize;
+    drop(tree);
+
+    let mut bytes = std::fs::read(&damaged.path).unwrap();
+    bytes[..PAGE_SIZE].fill(0);
+    let page = &mut bytes[catalog * PAGE_SIZE..][..PAGE_SIZE];
+    page.fill(0);
+    // A catalog page type and one entry, of a known kind, whose name length runs off its end.
+    page[0] = 3;
+    page[2] = 1;
+    page[8 + 4] = 2;
+    page[8 + 5] = 200;
+    std::fs::write(&damaged.path, &bytes).unwrap();
+
+    let report = BPlusTree::repair(&damaged.path, &repaired.path).unwrap();
+    assert!(report.catalog_lost);
+    let tree = repaired.open();
+    assert!(tree.list_trees().unwrap().is_empty());
+    assert_eq!(tree.len().unwrap(), 100);
+    tree.verify().unwrap();
+}
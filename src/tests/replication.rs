@@ -0,0 +1,59 @@
+use super::TempIndex;
+use crate::{BPlusTree, Follower, DATA_SIZE};
+
+#[test]
+fn follower_catches_up_resumes_and_promotes() {
+    let primary_index = TempIndex::new("replication_primary");
+    let follower_index = TempIndex::new("replication_follower");
+    let mut primary = primary_index.open();
+    primary.enable_change_log().unwrap();
+    for key in 0..200 {
+        primary.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+    primary.delete_range(50..100).unwrap();
+
+    let mut follower = Follower::open(&follower_index.path, &primary_index.path).unwrap();
+    assert_eq!(follower.catch_up().unwrap(), 250);
+    assert_eq!(follower.applied(), primary.sequence());
+    assert_eq!(follower.tree().len().unwrap(), 150);
+    assert_eq!(follower.catch_up().unwrap(), 0);
+
+    // Only `Follower` opens the file, and it picks up where it stopped.
+    drop(follower);
+    assert!(BPlusTree::open(&follower_index.path).is_err());
+    primary.delete(199).unwrap();
+    let mut follower = Follower::open(&follower_index.path, &primary_index.path).unwrap();
+    assert_eq!(follower.catch_up().unwrap(), 1);
+    assert_eq!(follower.tree().read(199).unwrap(), None);
+
+    let mut promoted = follower.promote().unwrap();
+    promoted.write_data(500, &[5; DATA_SIZE]).unwrap();
+    drop(promoted);
+    let reopened = follower_index.open();
+    assert_eq!(reopened.len().unwrap(), 150);
+    reopened.verify().unwrap();
+}
+
+#[test]
+fn follower_refuses_a_batch_that_diverges() {
+    let primary_index = TempIndex::new("replication_diverged");
+    let follower_index = TempIndex::new("replication_diverged_follower");
+    let mut primary = primary_index.open();
+    primary.enable_change_log().unwrap();
+    primary.write_data(1, &[1; DATA_SIZE]).unwrap();
+    let mut follower = Follower::open(&follower_index.path, &primary_index.path).unwrap();
+    follower.catch_up().unwrap();
+    drop(follower);
+
+    // Promoting and writing behind the primary's back makes the next change disagree.
+    let mut diverged = Follower::open(&follower_index.path, &primary_index.path)
+        .unwrap()
+        .promote()
+        .unwrap();
+    diverged.write_data(1, &[9; DATA_SIZE]).unwrap();
+    drop(diverged);
+    primary.write_data(1, &[2; DATA_SIZE]).unwrap();
+    let mut follower = Follower::open(&follower_index.path, &primary_index.path).unwrap();
+    assert!(follower.catch_up().is_err());
+    assert_eq!(follower.tree().read(1).unwrap(), Some([9; DATA_SIZE]));
+}
@@ -0,0 +1,102 @@
+use super::TempIndex;
+use crate::{BPlusTree, DATA_SIZE};
+
+fn value(key: i32, seed: u8) -> [u8; DATA_SIZE] {
+    let mut data = [seed; DATA_SIZE];
+    data[..4].copy_from_slice(&key.to_le_bytes());
+    data
+}
+
+fn fill(tree: &mut BPlusTree, keys: i32, seed: u8) {
+    let mut tx = tree.begin().unwrap();
+    for key in 0..keys {
+        tx.put(key, &value(key, seed)).unwrap();
+    }
+    tx.commit().unwrap();
+}
+
+// What a crash right now would leave: the file and WAL as they are, with no snapshot open.
+fn crash_copy(index: &TempIndex, name: &str) -> TempIndex {
+    let copy = TempIndex::new(name);
+    std::fs::copy(&index.path, &copy.path).unwrap();
+    std::fs::copy(index.wal(), copy.wal()).unwrap();
+    copy
+}
+
+fn shadows(tree: &BPlusTree) -> Vec<usize> {
+    let pager = tree.pager.read().unwrap();
+    pager.shadow_refs.keys().copied().collect()
+}
+
+#[test]
+fn snapshot_keeps_reading_the_state_it_was_taken_at() {
+    let index = TempIndex::new("snapshot_reads");
+    let mut tree = index.open();
+    fill(&mut tree, 300, 0);
+    let snapshot = tree.snapshot();
+    fill(&mut tree, 600, 1);
+    tree.delete_range(..100).unwrap();
+
+    assert_eq!(snapshot.read(5).unwrap(), Some(value(5, 0)));
+    assert_eq!(snapshot.read(400).unwrap(), None);
+    assert_eq!(snapshot.read_range_data(0, 299).unwrap().len(), 300);
+    snapshot.verify().unwrap();
+    assert_eq!(tree.read(5).unwrap(), None);
+    assert_eq!(tree.read(400).unwrap(), Some(value(400, 1)));
+
+    drop(snapshot);
+    assert!(shadows(&tree).is_empty());
+    tree.verify().unwrap();
+}
+
+#[test]
+fn shadow_pages_are_free_after_a_crash() {
+    let index = TempIndex::new("snapshot_crash");
+    let mut tree = index.open();
+    fill(&mut tree, 300, 0);
+    let snapshot = tree.snapshot();
+    fill(&mut tree, 300, 1);
+    // The shadows of the commit above only reach the on-disk free list with the next one.
+    tree.write_data(1000, &value(1000, 1)).unwrap();
+    let shadowed = shadows(&tree);
+    assert!(!shadowed.is_empty());
+
+    let crashed = crash_copy(&index, "snapshot_crashed");
+    let reopened = crashed.open();
+    let pager = reopened.pager.read().unwrap();
+    for page in &shadowed {
+        assert!(
+            pager.free_pages().contains(page) || *page >= pager.num_pages(),
+            "shadow page {page} leaked"
+        );
+    }
+    drop(pager);
+    reopened.verify().unwrap();
+    assert_eq!(reopened.read(5).unwrap(), Some(value(5, 1)));
+    assert_eq!(snapshot.read(5).unwrap(), Some(value(5, 0)));
+}
+
+// More shadows than the meta page lists, with no free pages left to hold the rest, so the
+// free-list trunks have to come from new pages rather than from shadows a snapshot still reads.
+#[test]
+fn shadow_pages_are_never_reused_as_free_list_trunks() {
+    let index = TempIndex::new("snapshot_trunks");
+    let mut tree = index.open();
+    fill(&mut tree, 40_000, 0);
+    let snapshot = tree.snapshot();
+    fill(&mut tree, 40_000, 1);
+    tree.write_data(-1, &value(-1, 1)).unwrap();
+    assert!(shadows(&tree).len() > 1013);
+
+    snapshot.verify().unwrap();
+    let old = snapshot.read_range_data(0, 39_999).unwrap();
+    assert!(old
+        .iter()
+        .enumerate()
+        .all(|(key, data)| *data == value(key as i32, 0)));
+
+    let crashed = crash_copy(&index, "snapshot_trunks_crashed");
+    let reopened = crashed.open();
+    reopened.verify().unwrap();
+    assert_eq!(reopened.read(39_999).unwrap(), Some(value(39_999, 1)));
+}
@@ -0,0 +1,65 @@
+use super::TempIndex;
+use crate::DATA_SIZE;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+#[test]
+fn file_reads_skip_pages_changed_in_the_transaction() {
+    let index = TempIndex::new("stats_file_reads");
+    let mut tree = index.open();
+    tree.write_data(1, &[1; DATA_SIZE]).unwrap();
+
+    // A committed single-leaf tree: one page read, served from the file.
+    let (pages, files) = (tree.page_reads(), tree.file_reads());
+    tree.read(1).unwrap();
+    assert_eq!(tree.page_reads() - pages, 1);
+    assert_eq!(tree.file_reads() - files, 1);
+
+    // The transaction borrows the handle, so its reads are counted off the shared pager.
+    let pager = Arc::clone(&tree.pager);
+    let counts = || {
+        let counters = &pager.read().unwrap().counters;
+        (
+            counters.page_reads.load(Ordering::Relaxed),
+            counters.file_reads.load(Ordering::Relaxed),
+        )
+    };
+    let mut tx = tree.begin().unwrap();
+    tx.put(2, &[2; DATA_SIZE]).unwrap();
+    let (pages, files) = counts();
+    assert_eq!(tx.get(2).unwrap(), Some([2; DATA_SIZE]));
+    assert_eq!(counts(), (pages + 1, files));
+    tx.commit().unwrap();
+}
+
+#[test]
+fn stats_describe_the_shape_of_the_tree() {
+    let index = TempIndex::new("stats_shape");
+    let mut tree = index.open();
+    for key in 0..5000 {
+        tree.write_data(key, &[key as u8; DATA_SIZE]).unwrap();
+    }
+    let stats = tree.stats().unwrap();
+    assert_eq!(stats.keys, 5000);
+    assert_eq!(stats.height, stats.levels.len());
+    assert!(stats.height >= 2);
+    assert_eq!(stats.levels[0].pages, 1);
+    assert_eq!(stats.levels.last().unwrap().pages, stats.leaf_pages);
+    assert_eq!(
+        stats.internal_pages,
+        stats.levels[..stats.height - 1]
+            .iter()
+            .map(|level| level.pages)
+            .sum::<usize>()
+    );
+    assert!(stats.splits > 0 && stats.merges == 0);
+
+    for key in (0..5000).filter(|key| key % 10 != 0) {
+        tree.delete(key).unwrap();
+    }
+    let after = tree.stats().unwrap();
+    assert_eq!(after.keys, 500);
+    assert!(after.leaf_pages < stats.leaf_pages);
+    assert!(after.merges > 0);
+    assert!(after.free_pages > 0);
+}
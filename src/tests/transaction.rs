@@ -0,0 +1,98 @@
+use super::TempIndex;
+use crate::DATA_SIZE;
+
+fn value(seed: u8) -> [u8; DATA_SIZE] {
+    [seed; DATA_SIZE]
+}
+
+#[test]
+fn savepoints_roll_back_to_where_they_were_taken() {
+    let index = TempIndex::new("transaction_savepoints");
+    let mut tree = index.open();
+    for key in 0..10 {
+        tree.write_data(key, &value(1)).unwrap();
+    }
+
+    let mut tx = tree.begin().unwrap();
+    tx.put(100, &value(2)).unwrap();
+    let outer = tx.savepoint();
+    assert_eq!(tx.delete_range(0..5).unwrap(), 5);
+    let inner = tx.savepoint();
+    tx.put(200, &value(2)).unwrap();
+
+    tx.rollback_to(&inner).unwrap();
+    assert_eq!(tx.get(200).unwrap(), None);
+    assert_eq!(tx.get(0).unwrap(), None);
+    tx.rollback_to(&outer).unwrap();
+    assert_eq!(tx.get(0).unwrap(), Some(value(1)));
+    // Rolling back to the outer savepoint discarded the inner one.
+    assert!(tx.rollback_to(&inner).is_err());
+    tx.release(outer).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(tree.len().unwrap(), 11);
+    assert_eq!(tree.read(100).unwrap(), Some(value(2)));
+
+    // A transaction dropped without a commit rolls back.
+    {
+        let mut tx = tree.begin().unwrap();
+        tx.put(300, &value(3)).unwrap();
+    }
+    assert_eq!(tree.read(300).unwrap(), None);
+    tree.verify().unwrap();
+}
+
+#[test]
+fn conditional_writes_check_the_current_value() {
+    let index = TempIndex::new("transaction_conditional");
+    let mut tree = index.open();
+    assert!(tree.insert_if_absent(1, &value(1)).unwrap());
+    assert!(!tree.insert_if_absent(1, &value(2)).unwrap());
+    assert!(tree.update_if_present(1, &value(3)).unwrap());
+    assert!(!tree.update_if_present(2, &value(3)).unwrap());
+    assert_eq!(tree.read(2).unwrap(), None);
+
+    let swap = |tree: &mut crate::BPlusTree, key, expected: Option<u8>, new: Option<u8>| {
+        tree.compare_and_swap(key, expected.map(value).as_ref(), new.map(value).as_ref())
+            .unwrap()
+    };
+    assert_eq!(swap(&mut tree, 1, Some(1), Some(4)), Err(Some(value(3))));
+    assert_eq!(swap(&mut tree, 1, Some(3), Some(4)), Ok(()));
+    assert_eq!(swap(&mut tree, 2, Some(4), Some(5)), Err(None));
+    assert_eq!(swap(&mut tree, 2, None, Some(5)), Ok(()));
+    assert_eq!(swap(&mut tree, 2, Some(5), None), Ok(()));
+    assert_eq!(tree.read(2).unwrap(), None);
+    assert_eq!(tree.put(1, &value(6)).unwrap(), Some(value(4)));
+
+    // A swap inside a transaction that rolls back leaves nothing behind.
+    let mut tx = tree.begin().unwrap();
+    let expected = value(6);
+    assert_eq!(
+        tx.compare_and_swap(1, Some(&expected), None).unwrap(),
+        Ok(())
+    );
+    assert_eq!(tx.get(1).unwrap(), None);
+    tx.rollback();
+    assert_eq!(tree.read(1).unwrap(), Some(value(6)));
+}
+
+#[test]
+fn multimap_write_data_reports_new_keys_without_reading_their_values() {
+    let index = TempIndex::new("transaction_write_data");
+    let mut tree = index.open_multimap();
+    assert!(tree.write_data(1, &value(1)).unwrap());
+    assert!(!tree.write_data(1, &value(1)).unwrap());
+    assert_eq!(tree.get_all(1).unwrap(), vec![value(1), value(1)]);
+
+    // Key 2 has a posting chain several pages long. Adding to it reads no more pages than
+    // adding to key 3's two inline values, since the key's values are never read.
+    for seed in 0..200 {
+        tree.write_data(2, &value(seed)).unwrap();
+    }
+    tree.write_data(3, &value(0)).unwrap();
+    let reads = |tree: &mut crate::BPlusTree, key| {
+        let before = tree.page_reads();
+        assert!(!tree.write_data(key, &value(9)).unwrap());
+        tree.page_reads() - before
+    };
+    assert!(reads(&mut tree, 2) <= reads(&mut tree, 3) + 1);
+}
@@ -0,0 +1,73 @@
+// Wraps a public operation in a `tracing` span carrying its key, any extra fields, the pages
+// from the root down to the key's leaf and the latency in microseconds. Without the `tracing`
+// feature it expands to the body alone.
+macro_rules! traced {
+    ($name:literal, $reader:expr, $key:expr $(, $field:ident = $value:expr)*; $body:expr) => {{
+        #[cfg(feature = "tracing")]
+        let _trace = $crate::trace::Trace::enter(
+            tracing::debug_span!(
+                $name,
+                key = $key,
+                $($field = $value,)*
+                path = tracing::field::Empty,
+                latency_us = tracing::field::Empty,
+            ),
+            &$reader,
+            $key,
+        );
+        $body
+    }};
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing_impl::Trace;
+
+#[cfg(feature = "tracing")]
+mod tracing_impl {
+    use crate::TreeReader;
+    use std::time::Instant;
+    use tracing::span::EnteredSpan;
+    use tracing::Span;
+
+    // Records the latency on the span when the operation finishes, just before leaving it.
+    pub(crate) struct Trace {
+        span: EnteredSpan,
+        start: Instant,
+    }
+
+    impl Trace {
+        // The path takes a descent of its own, so it is only worked out when a subscriber
+        // wants the span, and then counts toward `page_reads`.
+        pub(crate) fn enter(span: Span, reader: &TreeReader<'_>, key: i32) -> Self {
+            // A path that cannot be read is left out; the operation itself reports the error.
+            if !span.is_disabled() && reader.root_page != -1 {
+                if let Ok(path) = reader.path(key) {
+                    span.record("path", tracing::field::debug(path));
+                }
+            }
+            Trace {
+                span: span.entered(),
+                start: Instant::now(),
+            }
+        }
+    }
+
+    impl Drop for Trace {
+        fn drop(&mut self) {
+            let latency = self.start.elapsed().as_micros() as u64;
+            self.span.record("latency_us", latency);
+        }
+    }
+
+    impl TreeReader<'_> {
+        fn path(&self, key: i32) -> std::io::Result<Vec<usize>> {
+            let mut path = vec![self.root_page as usize];
+            self.descend(|node| {
+                let idx = node.child_index(key);
+                path.push(node.children[idx] as usize);
+                idx
+            })?;
+            Ok(path)
+        }
+    }
+}
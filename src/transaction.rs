@@ -0,0 +1,160 @@
+use crate::{BPlusTree, Entry, DATA_SIZE};
+use std::io::{Error, ErrorKind, Result};
+use std::ops::RangeBounds;
+
+pub struct Savepoint {
+    id: u64,
+}
+
+pub struct Transaction<'a> {
+    tree: &'a mut BPlusTree,
+    finished: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(tree: &'a mut BPlusTree) -> Self {
+        Transaction {
+            tree,
+            finished: false,
+        }
+    }
+
+    pub fn put(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<Option<[u8; DATA_SIZE]>> {
+        self.tree.put_entry(key, data)
+    }
+
+    pub fn insert_if_absent(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.tree.insert_entry_if_absent(key, data)
+    }
+
+    pub fn update_if_present(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.tree.update_entry_if_present(key, data)
+    }
+
+    pub fn compare_and_swap(
+        &mut self,
+        key: i32,
+        expected: Option<&[u8; DATA_SIZE]>,
+        new: Option<&[u8; DATA_SIZE]>,
+    ) -> Result<std::result::Result<(), Option<[u8; DATA_SIZE]>>> {
+        self.tree.compare_and_swap_entry(key, expected, new)
+    }
+
+    pub fn entry(&mut self, key: i32) -> Result<Entry<'_>> {
+        Entry::new(self.tree, key, false)
+    }
+
+    pub fn delete(&mut self, key: i32) -> Result<bool> {
+        Ok(self.tree.remove_entry(key)?.is_some())
+    }
+
+    pub fn delete_range(&mut self, range: impl RangeBounds<i32>) -> Result<usize> {
+        self.tree.remove_range(range)
+    }
+
+    pub fn update_range(
+        &mut self,
+        range: impl RangeBounds<i32>,
+        f: impl FnMut(i32, &mut [u8; DATA_SIZE]),
+    ) -> Result<usize> {
+        self.tree.rewrite_range(range, f)
+    }
+
+    pub fn pop_first(&mut self) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        self.tree.pop_entry(false)
+    }
+
+    pub fn pop_last(&mut self) -> Result<Option<(i32, [u8; DATA_SIZE])>> {
+        self.tree.pop_entry(true)
+    }
+
+    pub fn insert_value(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<()> {
+        self.tree.add_value(key, data).map(|_| ())
+    }
+
+    pub fn delete_one(&mut self, key: i32, data: &[u8; DATA_SIZE]) -> Result<bool> {
+        self.tree.remove_value(key, data)
+    }
+
+    pub fn delete_all(&mut self, key: i32) -> Result<usize> {
+        self.tree.remove_all(key)
+    }
+
+    pub fn get_all(&self, key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        self.tree.get_all(key)
+    }
+
+    pub fn get(&self, key: i32) -> Result<Option<[u8; DATA_SIZE]>> {
+        self.tree.read(key)
+    }
+
+    pub fn lookup_by(
+        &self,
+        index: &str,
+        secondary_key: i32,
+    ) -> Result<Vec<(i32, [u8; DATA_SIZE])>> {
+        self.tree.lookup_by(index, secondary_key)
+    }
+
+    pub fn read_range_data(&self, start_key: i32, end_key: i32) -> Result<Vec<[u8; DATA_SIZE]>> {
+        self.tree.read_range_data(start_key, end_key)
+    }
+
+    pub fn savepoint(&mut self) -> Savepoint {
+        let id = self
+            .tree
+            .pager
+            .write()
+            .unwrap()
+            .savepoint(self.tree.root_page);
+        Savepoint { id }
+    }
+
+    pub fn rollback_to(&mut self, savepoint: &Savepoint) -> Result<()> {
+        let root_page = self
+            .tree
+            .pager
+            .write()
+            .unwrap()
+            .rollback_to_savepoint(savepoint.id)
+            .ok_or_else(released_savepoint)?;
+        self.tree.root_page = root_page;
+        Ok(())
+    }
+
+    pub fn release(&mut self, savepoint: Savepoint) -> Result<()> {
+        if self
+            .tree
+            .pager
+            .write()
+            .unwrap()
+            .release_savepoint(savepoint.id)
+        {
+            Ok(())
+        } else {
+            Err(released_savepoint())
+        }
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        self.finished = true;
+        self.tree.commit()
+    }
+
+    pub fn rollback(mut self) {
+        self.finished = true;
+        self.tree.rollback();
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.tree.rollback();
+        }
+    }
+}
+
+fn released_savepoint() -> Error {
+    Error::new(ErrorKind::InvalidInput, "savepoint is no longer active")
+}
@@ -0,0 +1,313 @@
+use crate::checksum::page_checksum;
+use crate::pager::FLAG_MULTIMAP;
+use crate::{
+    BPlusTree, InternalNode, LeafNode, TreeReader, INLINE_VALUES, INTERNAL_BODY, INTERNAL_CHECKSUM,
+    INTERNAL_ORDER, KEY_SIZE, LEAF_CHECKSUM, LEAF_HEADER_SIZE, LEAF_HEAP_START, LEAF_MIN_WIDTH,
+    PAGE_SIZE, SLOT_SIZE,
+};
+use bincode::config;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result};
+
+// Carried inside the InvalidData error so a walk that keeps going can tell which page each
+// problem was found on.
+#[derive(Debug)]
+pub(crate) struct Violation {
+    pub(crate) page: usize,
+    pub(crate) problem: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "page {}: {}", self.page, self.problem)
+    }
+}
+
+impl std::error::Error for Violation {}
+
+pub(crate) fn corrupt(page_num: usize, problem: impl Display) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        Violation {
+            page: page_num,
+            problem: problem.to_string(),
+        },
+    )
+}
+
+impl LeafNode {
+    // Slots, cells and prefix stay inside the page, so reading any slot cannot go past it.
+    pub(crate) fn is_in_bounds(&self) -> bool {
+        let n = self.num_keys();
+        let heap_start = self.get_u16(LEAF_HEAP_START) as usize;
+        if self.prefix_len() > KEY_SIZE
+            || LEAF_HEADER_SIZE + n * SLOT_SIZE > heap_start
+            || heap_start > PAGE_SIZE
+        {
+            return false;
+        }
+        (0..n).all(|i| {
+            let offset = self.cell_offset(i);
+            offset >= heap_start && offset + self.cell_size(i) <= PAGE_SIZE
+        })
+    }
+
+    // In bounds, and the keys are strictly increasing.
+    pub(crate) fn is_well_formed(&self) -> bool {
+        self.is_in_bounds() && (1..self.num_keys()).all(|i| self.key(i - 1) < self.key(i))
+    }
+}
+
+// A node reached by the descent, with what its parent says about it.
+struct Visit {
+    page_num: usize,
+    parent: i32,
+    low: Option<i32>,
+    high: Option<i32>,
+    depth: usize,
+    count: Option<u64>,
+}
+
+impl TreeReader<'_> {
+    pub(crate) fn checked_page(
+        &self,
+        page_num: usize,
+        num_pages: usize,
+    ) -> Result<[u8; PAGE_SIZE]> {
+        if page_num == 0 || page_num >= num_pages {
+            return Err(corrupt(page_num, "child pointer out of range"));
+        }
+        let page = self.read_page(page_num)?;
+        let offset = match page[0] {
+            0 => INTERNAL_CHECKSUM,
+            1 => LEAF_CHECKSUM,
+            kind => return Err(corrupt(page_num, format!("unexpected page type {kind}"))),
+        };
+        let stored = u32::from_le_bytes(page[offset..offset + 4].try_into().unwrap());
+        if stored != page_checksum(&page, offset) {
+            return Err(corrupt(page_num, "checksum mismatch"));
+        }
+        Ok(page)
+    }
+
+    // Walks the whole tree and checks every invariant the lookups rely on: separator bounds,
+    // subtree counts, uniform leaf depth, parent pointers, posting chains, the leaf chain in both
+    // directions, and the minimum fill that deletes keep for every node but the root. Each
+    // problem goes to `report`, and the walk stops when it returns an error. Past a page that
+    // cannot be read or decoded the walk skips its subtree, and the leaf chain is only checked if
+    // nothing was skipped.
+    fn check(&self, report: &mut dyn FnMut(Error) -> Result<()>) -> Result<()> {
+        if self.root_page == -1 {
+            return Ok(());
+        }
+        let pager = self.pager.read().unwrap();
+        let num_pages = pager.num_pages();
+        let multimap = pager.flags & FLAG_MULTIMAP != 0;
+        let free: HashSet<usize> = match self.snapshot {
+            Some(_) => HashSet::new(),
+            None => pager.free_pages().iter().copied().collect(),
+        };
+        drop(pager);
+
+        let mut seen = HashSet::new();
+        let mut leaves = Vec::new();
+        let mut leaf_depth = None;
+        let mut skipped = false;
+        let mut stack = vec![Visit {
+            page_num: self.root_page as usize,
+            parent: -1,
+            low: None,
+            high: None,
+            depth: 0,
+            count: None,
+        }];
+        while let Some(visit) = stack.pop() {
+            let page_num = visit.page_num;
+            let page = match self.checked_page(page_num, num_pages) {
+                Ok(page) => page,
+                Err(e) => {
+                    report(e)?;
+                    skipped = true;
+                    continue;
+                }
+            };
+            if !seen.insert(page_num) {
+                report(corrupt(page_num, "reachable twice"))?;
+                skipped = true;
+                continue;
+            }
+            if free.contains(&page_num) {
+                report(corrupt(page_num, "reachable but on the free list"))?;
+            }
+            let in_bounds = |key: i32| {
+                visit.low.is_none_or(|low| key >= low) && visit.high.is_none_or(|high| key < high)
+            };
+
+            if page[0] == 1 {
+                let leaf = LeafNode { page };
+                if !leaf.is_well_formed() {
+                    report(corrupt(page_num, "malformed leaf"))?;
+                    skipped = true;
+                    continue;
+                }
+                let n = leaf.num_keys();
+                if leaf.parent() != visit.parent {
+                    report(corrupt(
+                        page_num,
+                        format!("parent pointer {}", leaf.parent()),
+                    ))?;
+                }
+                if n == 0 && visit.parent != -1 {
+                    report(corrupt(page_num, "empty non-root leaf"))?;
+                } else if leaf.width() < LEAF_MIN_WIDTH && visit.parent != -1 {
+                    report(corrupt(
+                        page_num,
+                        format!("underfull leaf with {} values", leaf.width()),
+                    ))?;
+                }
+                if let Some(i) = (0..n).find(|&i| !in_bounds(leaf.key(i))) {
+                    report(corrupt(
+                        page_num,
+                        format!("key {} out of range", leaf.key(i)),
+                    ))?;
+                }
+                if visit.count.is_some_and(|count| count != n as u64) {
+                    report(corrupt(page_num, "count kept by the parent is wrong"))?;
+                }
+                if *leaf_depth.get_or_insert(visit.depth) != visit.depth {
+                    report(corrupt(page_num, "leaves at different depths"))?;
+                }
+                if let Some(i) = (0..n).find(|&i| leaf.inline_len(i) > 1) {
+                    if !multimap {
+                        report(corrupt(page_num, "duplicate values in a unique-key index"))?;
+                    } else if leaf.is_posting(i) || leaf.inline_len(i) > INLINE_VALUES {
+                        report(corrupt(
+                            page_num,
+                            format!(
+                                "{} values inline under key {}",
+                                leaf.inline_len(i),
+                                leaf.key(i)
+                            ),
+                        ))?;
+                    }
+                }
+                for i in (0..n).filter(|&i| leaf.is_posting(i)) {
+                    if !multimap {
+                        report(corrupt(page_num, "posting list in a unique-key index"))?;
+                        continue;
+                    }
+                    let chain = match self.posting_chain(page_num, &leaf, i, num_pages) {
+                        Ok(chain) => chain,
+                        Err(e) => {
+                            report(e)?;
+                            continue;
+                        }
+                    };
+                    for posting in chain {
+                        if !seen.insert(posting) {
+                            report(corrupt(posting, "reachable twice"))?;
+                        }
+                        if free.contains(&posting) {
+                            report(corrupt(posting, "reachable but on the free list"))?;
+                        }
+                    }
+                }
+                leaves.push((page_num, leaf));
+                continue;
+            }
+
+            let node: InternalNode =
+                match bincode::decode_from_slice(&page[INTERNAL_BODY..], config::standard()) {
+                    Ok((node, _)) => node,
+                    Err(e) => {
+                        report(corrupt(page_num, e))?;
+                        skipped = true;
+                        continue;
+                    }
+                };
+            let n = node.num_keys;
+            if n == 0 || n > INTERNAL_ORDER {
+                report(corrupt(page_num, format!("{n} keys")))?;
+                skipped = true;
+                continue;
+            }
+            if node.parent != visit.parent {
+                report(corrupt(page_num, format!("parent pointer {}", node.parent)))?;
+            }
+            if n < INTERNAL_ORDER.div_ceil(2) && visit.parent != -1 {
+                report(corrupt(
+                    page_num,
+                    format!("underfull internal node with {n} keys"),
+                ))?;
+            }
+            if (1..n).any(|i| node.keys[i - 1] >= node.keys[i]) {
+                report(corrupt(page_num, "keys out of order"))?;
+            }
+            if let Some(&key) = node.keys[..n].iter().find(|&&key| !in_bounds(key)) {
+                report(corrupt(page_num, format!("separator {key} out of range")))?;
+            }
+            if visit.count.is_some_and(|count| count != node.total()) {
+                report(corrupt(page_num, "count kept by the parent is wrong"))?;
+            }
+            // Pushed in reverse so leaves come off the stack in key order.
+            for i in (0..=n).rev() {
+                stack.push(Visit {
+                    page_num: node.children[i].max(0) as usize,
+                    parent: page_num as i32,
+                    low: if i == 0 {
+                        visit.low
+                    } else {
+                        Some(node.keys[i - 1])
+                    },
+                    high: if i == n {
+                        visit.high
+                    } else {
+                        Some(node.keys[i])
+                    },
+                    depth: visit.depth + 1,
+                    count: Some(node.counts[i]),
+                });
+            }
+        }
+
+        if skipped {
+            return Ok(());
+        }
+        for (i, (page_num, leaf)) in leaves.iter().enumerate() {
+            let prev = if i == 0 { -1 } else { leaves[i - 1].0 as i32 };
+            let next = leaves.get(i + 1).map_or(-1, |(page, _)| *page as i32);
+            if leaf.prev_leaf() != prev || leaf.next_leaf() != next {
+                report(corrupt(*page_num, "leaf links out of order"))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn verify(&self) -> Result<()> {
+        self.check(&mut Err)
+    }
+
+    // Every problem `verify` would find if it kept going after the first.
+    pub(crate) fn violations(&self) -> Vec<Violation> {
+        let mut found = Vec::new();
+        let _ = self.check(&mut |e| {
+            let inner = e.into_inner().expect("verify reports violations");
+            found.push(
+                *inner
+                    .downcast::<Violation>()
+                    .expect("verify reports violations"),
+            );
+            Ok(())
+        });
+        found
+    }
+}
+
+impl BPlusTree {
+    // Checks the structure of the current tree and returns the first problem found as an
+    // InvalidData error naming the page.
+    pub fn verify(&self) -> Result<()> {
+        self.reader().verify()
+    }
+}